@@ -15,13 +15,14 @@ use std::{
     thread,
     time::{Duration, Instant},
 };
-use tokio::{runtime::Runtime, sync::Mutex};
+use tokio::{runtime::Runtime, sync::broadcast};
 use widget_types::{
-    ApiAction, AppSettings, AppUiState, ConfigInformation, CreateCheckoutSessionResponse,
-    CreateWidgetRequest, FileConfiguration, IpcEvent, Level, LicenceTier, Modifier,
-    MonitorPosition, ScrapedData, UrlConfiguration, VersionInfo, WidgetBounds, WidgetConfiguration,
-    WidgetModifier, WidgetType, API_PORT, DEFAULT_WIDGET_HEIGHT, DEFAULT_WIDGET_WIDTH,
-    DEFAULT_WIDGET_X, DEFAULT_WIDGET_Y,
+    ApiAction, AppSettings, ChangeSource, ConfigInformation, CreateCheckoutSessionResponse,
+    CreateWidgetRequest, DbChange, ExtractResult, FileConfiguration, IpcEvent, LayoutPreset, Level,
+    LicenceTier, MessageSeverity, Modifier, MonitorPosition, ScrapedData, UrlConfiguration,
+    VersionInfo, WidgetBounds, WidgetConfiguration, WidgetModifier, WidgetType, API_PORT,
+    DEFAULT_TRASH_RETENTION_DAYS, DEFAULT_WIDGET_HEIGHT, DEFAULT_WIDGET_WIDTH, DEFAULT_WIDGET_X,
+    DEFAULT_WIDGET_Y,
 };
 use winit::{
     application::ApplicationHandler,
@@ -62,6 +63,9 @@ use image::ImageBuffer;
 
 pub const RESIZE_DEBOUNCE_TIME: u128 = 50;
 pub const DEFAULT_SCRAPE_INTERVAL: u64 = 5;
+/// The scheduler never waits longer than this, nor shorter than `MIN_SCHEDULER_WAIT`
+pub const MAX_SCHEDULER_WAIT: Duration = Duration::from_secs(60);
+pub const MIN_SCHEDULER_WAIT: Duration = Duration::from_secs(1);
 pub const TABBING_IDENTIFIER: &str = "New View"; // empty = no tabs, two separate windows are created
 
 use widget_types::NanoId;
@@ -140,9 +144,8 @@ struct App {
     all_widgets: HashMap<WindowId, WidgetView>,
     widget_id_to_window_id: HashMap<NanoId, WindowId>,
     window_id_to_widget_id: HashMap<WindowId, NanoId>,
-    db: widget_db::Database,
+    db: widget_db::DbService,
     settings: DesktopAppSettings,
-    ui_state: AppUiState,
 }
 
 struct WidgetView {
@@ -189,47 +192,46 @@ type Seconds = i32;
 
 impl App {
     // TODO: possibly split out the refresh timer and the extraction logic - maybe a page auto reloads already, and we just need to grab the newest value
-    fn refresh_webview(&mut self, id: NanoId, refresh_interval_secs: i32) {
+    /// Returns whether the page reloaded, or `None` when there was nothing to reload.
+    fn refresh_webview(&mut self, id: NanoId, refresh_interval_secs: i32) -> Option<bool> {
         info!("Refreshing webview for: {}", id.0);
 
         let window_id = self.widget_id_to_window_id.get(&id);
         if window_id.is_none() {
             error!("Webview not found or window is closed");
-            return;
+            return None;
         }
 
         let Some(webview) = self.all_widgets.get_mut(&window_id.unwrap()) else {
             error!("Webview not found");
-            return;
+            return None;
         };
 
         if webview.last_refresh.elapsed() > Duration::from_secs(refresh_interval_secs as u64) {
             webview.last_refresh = Instant::now();
         } else {
             info!("Skipping refresh for widget: {:?}", id);
-            return;
+            return None;
         }
 
-        match &webview.options.widget_type {
-            WidgetType::Url(url_config) => {
-                webview
-                    .app_webview
-                    .webview
-                    .reload()
-                    .expect("Something failed");
-            }
+        let reloaded = match &webview.options.widget_type {
+            WidgetType::Url(url_config) => webview.app_webview.webview.reload(),
             WidgetType::File(file_config) => {
                 // TODO: may need to take the file and replace the window.WIDGET_ID with the actual widget id
                 webview
                     .app_webview
                     .webview
                     .load_html(file_config.html.as_str())
-                    .expect("Something failed");
             }
             _ => {
                 error!("Cannot refresh non-url widget");
+                return None;
             }
+        };
+        if let Err(e) = &reloaded {
+            error!("Failed to refresh widget {:?}: {:?}", id, e);
         }
+        Some(reloaded.is_ok())
     }
 
     fn remove_webview(&mut self, id: NanoId) {
@@ -244,7 +246,7 @@ impl App {
         }
     }
 
-    fn scrape_webview(&self, widget_id: NanoId, element_selector: String) {
+    fn scrape_webview(&self, widget_id: NanoId, modifier_id: &NanoId, element_selector: String) {
         info!("Scraping webview: {:?}", widget_id);
         info!("Widget id to window id: {:?}", self.widget_id_to_window_id);
         info!("window to webview id: {:?}", self.window_id_to_widget_id);
@@ -260,56 +262,42 @@ impl App {
         };
 
         info!("TEMP: attempting to extract a value now...");
+        // Every result names the modifier, so only its run state changes. Wrapped in a function
+        // so running it again on the same page doesn't redeclare anything.
         let script_content = String::from(
             r#"
-try {
-const element = document.querySelector("$selector");
-if (!element) {
-window.ipc.postMessage(
-  JSON.stringify({
-    type: "extractresult",
-    content: {
-        error: "Element not found",
-        value: null,
-        widget_id: "$widget_id",
-        timestamp: Date.now().toString(),
-  }
-  })
-);
-}
-
-const scrape_value = element.getAttribute("aria-label") || element.textContent.trim();
-
-window.ipc.postMessage(
-JSON.stringify({
-  type: "extractresult",
-  content: {
-    error: null,
-    value: scrape_value,
-    widget_id: "$widget_id",
-    timestamp: Date.now().toString(),
+(() => {
+  const post = (error, value) => window.ipc.postMessage(
+    JSON.stringify({
+      type: "extractresult",
+      content: {
+        modifier_id: "$modifier_id",
+        data: {
+          error,
+          value,
+          widget_id: "$widget_id",
+          timestamp: Date.now().toString(),
+        },
+      },
+    })
+  );
+  try {
+    const element = document.querySelector("$selector");
+    if (!element) {
+      post("Element not found", "");
+    } else {
+      post(null, element.getAttribute("aria-label") || element.textContent.trim());
+    }
+  } catch (e) {
+    post(JSON.stringify(e.message), "");
   }
-})
-);
-} catch (e) {
-window.ipc.postMessage(
-JSON.stringify({
-  type: "extractresult",
-  content: {
-  error: JSON.stringify(e.message),
-  value: null,
-  widget_id: "$widget_id",
-  timestamp: Date.now().toString(),
-}
-})
-);
-}
-
+})();
             "#,
         );
 
         let script_content = script_content
             .replace("$selector", &element_selector)
+            .replace("$modifier_id", &modifier_id.0)
             .replace("$widget_id", &widget_view.nano_id.0.clone())
             .replace("$PORT", &API_PORT.to_string());
 
@@ -321,12 +309,66 @@ JSON.stringify({
         info!("Scrape completed");
     }
 
-    fn add_scrape_result(&mut self, result: ScrapedData) {
-        if let Err(e) = self.db.insert_data(result) {
+    fn add_scrape_result(&mut self, extracted: ExtractResult) {
+        let ExtractResult {
+            modifier_id,
+            data: result,
+        } = extracted;
+        let success = result.error.is_none();
+        let newly_failing = match self
+            .db
+            .blocking_read(|db| db.get_widget_modifier_by_id(&result.widget_id, &modifier_id))
+        {
+            Ok(modifier) => {
+                self.record_modifier_result(&modifier_id, success);
+                !success && modifier.consecutive_failures == 0
+            }
+            // Removed while the scrape ran, the value is still kept
+            Err(e) => {
+                info!("No modifier to record the scrape against: {:?}", e);
+                false
+            }
+        };
+        // Only the first failure in a row is worth telling the user about
+        if let (true, Some(error)) = (newly_failing, &result.error) {
+            self.add_ui_message(
+                MessageSeverity::Warning,
+                ChangeSource::App,
+                Some(&result.widget_id),
+                &format!("Scraping stopped working: {}", error),
+            );
+        }
+
+        if let Err(e) = self.db.blocking_write(|db| db.insert_data(result)) {
             error!("Failed to insert data: {:?}", e);
         }
     }
 
+    fn record_modifier_result(&self, modifier_id: &str, success: bool) {
+        let now = jiff::Timestamp::now().as_millisecond();
+        if let Err(e) = self
+            .db
+            .blocking_write(|db| db.record_modifier_result(modifier_id, success, now))
+        {
+            error!("Failed to save result of modifier {}: {:?}", modifier_id, e);
+        }
+    }
+
+    fn add_ui_message(
+        &self,
+        severity: MessageSeverity,
+        source: ChangeSource,
+        widget_id: Option<&str>,
+        message: &str,
+    ) {
+        if let Err(e) = self
+            .db
+            .blocking_write(|db| db.add_message(severity, source, widget_id, message))
+        {
+            error!("Failed to save message {:?}: {:?}", message, e);
+        }
+    }
+
     fn create_widget(&mut self, event_loop: &ActiveEventLoop, widget_config: WidgetConfiguration) {
         // Check the widget limit, excluding the 'controls' widget
         let current_widget_count = self
@@ -381,11 +423,7 @@ JSON.stringify({
                 window_attributes
                     .clone()
                     .with_title(widget_config.title.clone())
-                    .with_window_level(match widget_config.level {
-                        Level::AlwaysOnTop => WindowLevel::AlwaysOnTop,
-                        Level::Normal => WindowLevel::Normal,
-                        Level::AlwaysOnBottom => WindowLevel::AlwaysOnBottom,
-                    }),
+                    .with_window_level(window_level(&widget_config.level)),
             )
             .expect("Something failed");
 
@@ -446,11 +484,7 @@ JSON.stringify({
                 Some(webview)
             }
             WidgetType::Url(url_config) => {
-                let updated_url = if url_config.url.starts_with("http") {
-                    url_config.url.clone()
-                } else {
-                    format!("https://{}", url_config.url)
-                };
+                let updated_url = full_url(&url_config.url);
                 info!("Creating url widget with url: {}", updated_url);
                 let webview = common_webview_attributes
                     .with_url(updated_url)
@@ -487,6 +521,7 @@ JSON.stringify({
                 options: WidgetOptions {
                     title: widget_config.title.clone(),
                     widget_type: widget_config.widget_type.clone(),
+                    transparent: widget_config.transparent,
                 },
             },
         );
@@ -541,9 +576,34 @@ JSON.stringify({
         });
     }
 
+    /// Every thread holds its own connection to the current profile's database, so switching
+    /// relaunches the app with the new profile rather than swapping connections in place.
+    fn switch_profile(&mut self, event_loop: &ActiveEventLoop, profile: String) {
+        let relaunched = std::env::current_exe().and_then(|exe| {
+            std::process::Command::new(exe)
+                .arg("--profile")
+                .arg(&profile)
+                .spawn()
+        });
+        match relaunched {
+            Ok(_) => event_loop.exit(),
+            Err(e) => {
+                error!("Failed to relaunch with profile {}: {:?}", profile, e);
+                self.add_ui_message(
+                    MessageSeverity::Error,
+                    ChangeSource::Api,
+                    None,
+                    &format!("Could not switch to profile {}: {}", profile, e),
+                );
+            }
+        }
+    }
+
     fn reset_database(&mut self, event_loop: &ActiveEventLoop) {
         info!("Resetting database");
-        self.db.reset();
+        if let Err(e) = self.db.blocking_write(|db| db.reset()) {
+            error!("Failed to reset database: {:?}", e);
+        }
     }
 
     fn show_titlebars(&mut self, event_loop: &ActiveEventLoop) {
@@ -565,18 +625,33 @@ JSON.stringify({
     //     }
     // }
 
-    fn update_app_settings(&mut self, settings: AppSettings) {
+    fn update_app_settings(&mut self, settings: AppSettings, source: ChangeSource) {
         info!("Updating app settings: {:?}", settings);
-        // Save to database
-        if let Err(e) = self.db.set_settings(&settings) {
+        if let Err(e) = self.db.blocking_write(|db| db.set_settings(&settings)) {
             error!("Failed to save settings to db: {:?}", e);
+            self.add_ui_message(
+                MessageSeverity::Error,
+                source,
+                None,
+                &format!("Settings were not saved: {}", e),
+            );
+            return;
         }
+        self.apply_settings(settings);
+    }
 
-        // update the tray icon
-        if !settings.show_tray_icon {
-            self.tray_icon.set_visible(false);
-        } else {
-            self.tray_icon.set_visible(true);
+    /// Brings the tray and every webview in line with settings that are already saved.
+    fn apply_settings(&mut self, settings: AppSettings) {
+        self.tray_icon.set_visible(settings.show_tray_icon);
+
+        let message = Message {
+            data_key: "settings".to_string(),
+            window_id: String::new(),
+            message: json!(settings).to_string(),
+            timestamp: jiff::Timestamp::now().as_millisecond().to_string(),
+        };
+        for widget in self.all_widgets.values() {
+            widget.app_webview.send_message(&message);
         }
         self.settings.app_settings = settings;
     }
@@ -592,21 +667,71 @@ JSON.stringify({
             let widget = self.all_widgets.get_mut(window_id).unwrap();
             widget.visible = !widget.visible;
             widget.window.set_visible(widget.visible);
-            self.db
-                .update_widget_open_state(NanoId(widget_id), widget.visible);
+            if let Err(e) = self
+                .db
+                .blocking_write(|db| db.update_widget_open_state(NanoId(widget_id), widget.visible))
+            {
+                error!("Failed to save widget open state: {:?}", e);
+            }
         } else {
             // create the widget based on widget in the database
-            let mut widget_config = self
+            let mut widget_config = match self
                 .db
-                .get_widget_configuration_by_id(widget_id.clone().as_str())
-                .unwrap();
+                .blocking_read(|db| db.get_widget_configuration_by_id(widget_id.as_str()))
+            {
+                Ok(widget_config) => widget_config,
+                Err(e) => {
+                    error!("Failed to load widget {:?}: {:?}", widget_id, e);
+                    return;
+                }
+            };
             widget_config.is_open = true;
-            self.db.update_widget_open_state(NanoId(widget_id), true);
+            if let Err(e) = self
+                .db
+                .blocking_write(|db| db.update_widget_open_state(NanoId(widget_id), true))
+            {
+                error!("Failed to save widget open state: {:?}", e);
+            }
             self.create_widget(event_loop, widget_config);
         }
     }
 
-    fn update_widget_bounds(&mut self, widget_id: String, bounds: WidgetBounds) {
+    /// Unlike `toggle_visibility`, leaves widgets that are already in the wanted state alone.
+    fn set_widget_visibility(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        widget_id: String,
+        visible: bool,
+    ) {
+        match self.widget_id_to_window_id.get(&NanoId(widget_id.clone())) {
+            Some(window_id) => {
+                let widget = self.all_widgets.get_mut(window_id).unwrap();
+                if widget.visible == visible {
+                    return;
+                }
+                widget.visible = visible;
+                widget.window.set_visible(visible);
+                if let Err(e) = self
+                    .db
+                    .blocking_write(|db| db.update_widget_open_state(NanoId(widget_id), visible))
+                {
+                    error!("Failed to save widget open state: {:?}", e);
+                }
+            }
+            // Not open yet, opening it is the same as toggling
+            None if visible => self.toggle_visibility(event_loop, widget_id, true),
+            None => {}
+        }
+    }
+
+    /// Moves the widget's window if it is open. The bounds are saved either way, so a closed
+    /// widget opens where it was put.
+    fn update_widget_bounds(
+        &mut self,
+        widget_id: String,
+        bounds: WidgetBounds,
+        source: ChangeSource,
+    ) {
         if let Some(window_id) = self.widget_id_to_window_id.get(&NanoId(widget_id.clone())) {
             let widget = self.all_widgets.get_mut(window_id).unwrap();
             widget
@@ -615,9 +740,158 @@ JSON.stringify({
             widget
                 .window
                 .set_outer_position(LogicalPosition::new(bounds.x, bounds.y));
-            self.db.update_widget_bounds(widget_id, bounds);
         } else {
-            info!("Widget {:?} not found", widget_id);
+            info!(
+                "Widget {:?} has no window, only saving its bounds",
+                widget_id
+            );
+        }
+        if let Err(e) = self
+            .db
+            .blocking_write(|db| db.update_widget_bounds(widget_id, bounds, source))
+        {
+            error!("Failed to save widget bounds: {:?}", e);
+        }
+    }
+
+    fn set_widget_level(&mut self, widget_id: &str, level: Level, source: ChangeSource) {
+        if let Some(window_id) = self
+            .widget_id_to_window_id
+            .get(&NanoId(widget_id.to_string()))
+        {
+            let widget = self.all_widgets.get(window_id).unwrap();
+            widget.window.set_window_level(window_level(&level));
+        }
+        if let Err(e) = self
+            .db
+            .blocking_write(|db| db.update_widget_level(widget_id, &level, source))
+        {
+            error!("Failed to save widget level: {:?}", e);
+        }
+    }
+
+    /// Applies an edit that is already saved to the widget's window. Closed widgets pick it up
+    /// when they are opened.
+    fn update_widget(&mut self, event_loop: &ActiveEventLoop, widget_config: WidgetConfiguration) {
+        let Some(window_id) = self
+            .widget_id_to_window_id
+            .get(&widget_config.widget_id)
+            .copied()
+        else {
+            return;
+        };
+        let widget = self.all_widgets.get_mut(&window_id).unwrap();
+
+        // Windows can't change transparency once created, so this one is opened again
+        if widget.options.transparent != widget_config.transparent {
+            self.remove_webview(widget_config.widget_id.clone());
+            self.create_widget(
+                event_loop,
+                WidgetConfiguration {
+                    is_open: true,
+                    ..widget_config
+                },
+            );
+            return;
+        }
+
+        widget.window.set_title(&widget_config.title);
+        widget
+            .window
+            .set_window_level(window_level(&widget_config.level));
+        widget.window.request_inner_size(LogicalSize::new(
+            widget_config.bounds.width,
+            widget_config.bounds.height,
+        ));
+        widget.window.set_outer_position(LogicalPosition::new(
+            widget_config.bounds.x,
+            widget_config.bounds.y,
+        ));
+        if widget.options.widget_type != widget_config.widget_type {
+            let reloaded = match &widget_config.widget_type {
+                WidgetType::Url(url_config) => widget
+                    .app_webview
+                    .webview
+                    .load_url(&full_url(&url_config.url)),
+                WidgetType::File(file_config) => {
+                    widget.app_webview.webview.load_html(&file_config.html)
+                }
+            };
+            if let Err(e) = reloaded {
+                error!(
+                    "Failed to load the new content of widget {:?}: {:?}",
+                    widget_config.widget_id, e
+                );
+            }
+        }
+        // Decorations follow the titlebar setting for every window, like when they are created
+        widget.options = WidgetOptions {
+            title: widget_config.title,
+            widget_type: widget_config.widget_type,
+            transparent: widget_config.transparent,
+        };
+    }
+
+    /// Puts every widget of the preset back where it was saved. Widgets the preset doesn't
+    /// know about are left alone. The saved changes are recorded as coming from `source`.
+    fn apply_layout(
+        &mut self,
+        event_loop: &ActiveEventLoop,
+        layout: LayoutPreset,
+        source: ChangeSource,
+    ) {
+        for entry in layout.widgets {
+            // Level first, so a widget the preset opens gets its window at the right level
+            self.set_widget_level(&entry.widget_id, entry.level, source);
+            self.set_widget_visibility(event_loop, entry.widget_id.clone(), entry.is_open);
+            self.update_widget_bounds(entry.widget_id, entry.bounds, source);
+        }
+    }
+
+    /// Saves the current arrangement from the tray, where there is no way to type a name.
+    fn save_layout(&mut self) {
+        let name = format!(
+            "Layout {}",
+            jiff::Zoned::now().strftime("%Y-%m-%d %H:%M:%S")
+        );
+        match self.db.blocking_write(|db| db.save_layout(&name)) {
+            Ok(layout) => {
+                self.add_ui_message(
+                    MessageSeverity::Info,
+                    ChangeSource::App,
+                    None,
+                    &format!("Saved {} widgets as {}", layout.widgets.len(), layout.name),
+                );
+                self.refresh_layouts_menu();
+            }
+            Err(e) => error!("Failed to save layout: {:?}", e),
+        }
+    }
+
+    /// Rebuilds the preset items of the tray's layouts submenu from the database.
+    fn refresh_layouts_menu(&mut self) {
+        let layouts = match self.db.blocking_read(|db| db.list_layouts()) {
+            Ok(layouts) => layouts,
+            Err(e) => {
+                error!("Failed to load layouts: {:?}", e);
+                return;
+            }
+        };
+        let menu = &self.menu_items.layouts_menu;
+        while menu.items().len() > 2 {
+            menu.remove_at(2);
+        }
+        self.menu_items.layout_ids.clear();
+        if layouts.is_empty() {
+            let empty = tray_icon::menu::MenuItem::new("No saved layouts", false, None);
+            menu.append(&empty).unwrap();
+        }
+        for layout in layouts {
+            let item = tray_icon::menu::MenuItem::new(&layout.name, true, None);
+            menu.append(&item).unwrap();
+            self.menu_items
+                .layout_ids
+                .insert(item.id().0.clone(), layout.name);
         }
     }
 
@@ -725,6 +999,28 @@ pub struct MenuItems {
     pub show_titlebar_id: String,
     pub reset_database_id: String,
     pub check_updates_id: String,
+    pub save_layout_id: String,
+    /// Starts with the save item and a separator, followed by one item per preset.
+    pub layouts_menu: tray_icon::menu::Submenu,
+    /// Menu item id of each preset in `layouts_menu`, mapped to the preset name.
+    pub layout_ids: HashMap<String, String>,
+}
+
+/// URLs without a scheme are opened over https.
+fn full_url(url: &str) -> String {
+    if url.starts_with("http") {
+        url.to_string()
+    } else {
+        format!("https://{}", url)
+    }
+}
+
+fn window_level(level: &Level) -> WindowLevel {
+    match level {
+        Level::AlwaysOnTop => WindowLevel::AlwaysOnTop,
+        Level::Normal => WindowLevel::Normal,
+        Level::AlwaysOnBottom => WindowLevel::AlwaysOnBottom,
+    }
 }
 
 fn setup_tray_menu(
@@ -739,6 +1035,12 @@ fn setup_tray_menu(
     let show_titlebar_item = tray_icon::menu::MenuItem::new("Show titlebars", true, None);
     let reset_database_item = tray_icon::menu::MenuItem::new("Reset database", true, None);
     let check_updates_item = tray_icon::menu::MenuItem::new("Check for updates...", true, None);
+    let layouts_menu = tray_icon::menu::Submenu::new("Layouts", true);
+    let save_layout_item = tray_icon::menu::MenuItem::new("Save current layout", true, None);
+    layouts_menu.append(&save_layout_item).unwrap();
+    layouts_menu
+        .append(&tray_icon::menu::PredefinedMenuItem::separator())
+        .unwrap();
     let version_item = tray_icon::menu::MenuItem::new(
         format!(
             "HoverPane ({:?}) v{}",
@@ -751,6 +1053,7 @@ fn setup_tray_menu(
 
     // Append items and the separator correctly
     tray_menu.append(&show_controls_item).unwrap();
+    tray_menu.append(&layouts_menu).unwrap();
     tray_menu.append(&hide_titlebar_item).unwrap();
     tray_menu.append(&show_titlebar_item).unwrap();
     tray_menu.append(&reset_database_item).unwrap();
@@ -795,6 +1098,9 @@ fn setup_tray_menu(
             show_titlebar_id: tray_show_titlebar_id,
             reset_database_id: tray_reset_database_id,
             check_updates_id: tray_check_updates_id,
+            save_layout_id: save_layout_item.id().0.clone(),
+            layouts_menu,
+            layout_ids: HashMap::new(),
         },
         tray_icon,
     )
@@ -804,6 +1110,7 @@ fn setup_tray_menu(
 struct WidgetOptions {
     title: String,
     widget_type: WidgetType,
+    transparent: bool,
 }
 
 struct AppWebView {
@@ -822,7 +1129,15 @@ impl AppWebView {
     fn send_message(&self, message: &Message) {
         info!("Sending message to react: {:?}", message);
         self.webview
-            .evaluate_script(format!("window.onRustMessage('{}');", json!(message)).as_str())
+            // Passed as a string literal so quotes in the payload can't break the script, and
+            // only pages of our own UI define the handler
+            .evaluate_script(
+                format!(
+                    "window.onRustMessage?.({});",
+                    json!(json!(message).to_string())
+                )
+                .as_str(),
+            )
             .expect("Something failed");
     }
 }
@@ -866,13 +1181,20 @@ impl ApplicationHandler<UserEvent> for App {
                 scale_factor: monitor.scale_factor(),
             };
         }
-        self.db.set_config_information(config_information);
+        if let Err(e) = self
+            .db
+            .blocking_write(|db| db.set_config_information(config_information))
+        {
+            error!("Failed to save monitor information: {:?}", e);
+        }
 
         let mut widgets = vec![];
         {
             info!("TODO: Get widgets from db");
-            let config = self.db.get_configuration().unwrap();
-            widgets.extend_from_slice(&config);
+            match self.db.blocking_read(|db| db.get_configuration()) {
+                Ok(config) => widgets.extend_from_slice(&config),
+                Err(e) => error!("Failed to load widgets: {:?}", e),
+            }
         }
 
         info!("Found {} widgets", widgets.len());
@@ -904,7 +1226,12 @@ impl ApplicationHandler<UserEvent> for App {
                 let widget_id = self.window_id_to_widget_id.remove(&window_id).unwrap();
                 self.widget_id_to_window_id.remove(&widget_id);
                 self.all_widgets.remove(&window_id);
-                self.db.update_widget_open_state(widget_id, false);
+                if let Err(e) = self
+                    .db
+                    .blocking_write(|db| db.update_widget_open_state(widget_id, false))
+                {
+                    error!("Failed to save widget open state: {:?}", e);
+                }
             }
             WindowEvent::RedrawRequested => {}
             WindowEvent::Resized(size) => {
@@ -1049,6 +1376,18 @@ impl ApplicationHandler<UserEvent> for App {
                         info!("Checking for updates");
                         self.proxy.send_event(UserEvent::CheckForUpdates).unwrap();
                     }
+                    val if val == self.menu_items.save_layout_id => {
+                        info!("Saving layout");
+                        self.save_layout();
+                    }
+                    val if self.menu_items.layout_ids.contains_key(val) => {
+                        let name = self.menu_items.layout_ids[val].clone();
+                        info!("Applying layout {}", name);
+                        match self.db.blocking_read(|db| db.get_layout(&name)) {
+                            Ok(layout) => self.apply_layout(event_loop, layout, ChangeSource::Tray),
+                            Err(e) => error!("Failed to load layout {}: {:?}", name, e),
+                        }
+                    }
                     _ => {
                         info!("No tray menu show controls id found");
                     }
@@ -1063,14 +1402,16 @@ impl ApplicationHandler<UserEvent> for App {
                         interval_sec,
                     } => {
                         info!("User event: Refreshing widget: {:?}", widget_id);
-                        self.refresh_webview(widget_id, interval_sec);
+                        if let Some(success) = self.refresh_webview(widget_id, interval_sec) {
+                            self.record_modifier_result(&modifier_id.0, success);
+                        }
                     }
                     Modifier::Scrape {
                         modifier_id,
                         selector,
                     } => {
                         info!("User event: Scraping widget: {:?}", widget_id);
-                        self.scrape_webview(widget_id, selector);
+                        self.scrape_webview(widget_id, &modifier_id, selector);
                     }
                 }
             }
@@ -1092,7 +1433,7 @@ impl ApplicationHandler<UserEvent> for App {
                     }
                     ApiAction::UpdateWidgetBounds { widget_id, bounds } => {
                         info!("Updating widget bounds: {:?}", widget_id);
-                        self.update_widget_bounds(widget_id, bounds);
+                        self.update_widget_bounds(widget_id, bounds, ChangeSource::Api);
                     }
                     ApiAction::MaximizeWidget { widget_id } => {
                         info!("Maximizing widget: {:?}", widget_id);
@@ -1108,7 +1449,13 @@ impl ApplicationHandler<UserEvent> for App {
                     } => {
                         info!("Deleting widget modifier: {:?}", modifier_id);
                         // self.remove_widget_modifier(widget_id, modifier_id);
-                        self.db.delete_widget_modifier(modifier_id.as_str());
+                        // Already gone when the request came through the modifier endpoint
+                        match self.db.blocking_write(|db| {
+                            db.delete_widget_modifier(&widget_id, &modifier_id, ChangeSource::Api)
+                        }) {
+                            Ok(()) | Err(widget_db::DbError::NotFound(_)) => {}
+                            Err(e) => error!("Failed to delete widget modifier: {:?}", e),
+                        }
                     }
                     ApiAction::CheckLicence {
                         user_email,
@@ -1122,14 +1469,78 @@ impl ApplicationHandler<UserEvent> for App {
                             self.settings.app_settings.licence_tier = user_version;
                             self.settings.app_settings.licence_key = licence_key;
                             self.settings.app_settings.email = user_email;
-                            self.update_app_settings(self.settings.app_settings.clone());
-
-                            let mut all_messages = self.ui_state.messages.clone();
-                            all_messages.push("Licence check successful".to_string());
-                            self.db.set_app_ui_state(&AppUiState {
-                                app_settings: self.settings.app_settings.clone(),
-                                messages: all_messages,
-                            });
+                            self.update_app_settings(
+                                self.settings.app_settings.clone(),
+                                ChangeSource::Api,
+                            );
+                            self.add_ui_message(
+                                MessageSeverity::Info,
+                                ChangeSource::Api,
+                                None,
+                                "Licence check successful",
+                            );
+                        }
+                    }
+                    ApiAction::SwitchProfile { profile } => {
+                        info!("Switching to profile: {:?}", profile);
+                        self.switch_profile(event_loop, profile);
+                    }
+                    ApiAction::SettingsChanged(settings) => {
+                        self.apply_settings(settings);
+                    }
+                    ApiAction::SetWidgetsVisibility {
+                        widget_ids,
+                        visible,
+                    } => {
+                        info!("Setting visibility of {} widgets", widget_ids.len());
+                        for widget_id in widget_ids {
+                            self.set_widget_visibility(event_loop, widget_id, visible);
+                        }
+                    }
+                    ApiAction::RefreshWidgets { widget_ids } => {
+                        info!("Refreshing {} widgets", widget_ids.len());
+                        for widget_id in widget_ids {
+                            self.refresh_webview(NanoId(widget_id), 0);
+                        }
+                    }
+                    ApiAction::DeleteWidgets { widget_ids } => {
+                        info!("Deleting {} widgets", widget_ids.len());
+                        for widget_id in widget_ids {
+                            self.remove_webview(NanoId(widget_id));
+                        }
+                    }
+                    ApiAction::ApplyLayout(layout) => {
+                        info!("Applying layout {}", layout.name);
+                        self.apply_layout(event_loop, layout, ChangeSource::Api);
+                    }
+                    ApiAction::LayoutsChanged => {
+                        self.refresh_layouts_menu();
+                    }
+                    ApiAction::UpdateWidget(widget_config) => {
+                        info!("Updating widget: {:?}", widget_config.widget_id);
+                        self.update_widget(event_loop, widget_config);
+                    }
+                    ApiAction::RefreshWidget { widget_id } => {
+                        info!("Refreshing widget: {:?}", widget_id);
+                        self.refresh_webview(NanoId(widget_id), 0);
+                    }
+                    ApiAction::ScrapeWidget {
+                        widget_id,
+                        modifiers,
+                    } => {
+                        info!("Scraping widget: {:?}", widget_id);
+                        for modifier in modifiers {
+                            if let Modifier::Scrape {
+                                modifier_id,
+                                selector,
+                            } = modifier
+                            {
+                                self.scrape_webview(
+                                    NanoId(widget_id.clone()),
+                                    &modifier_id,
+                                    selector,
+                                );
+                            }
                         }
                     }
                 }
@@ -1138,7 +1549,7 @@ impl ApplicationHandler<UserEvent> for App {
                 info!("Ipc event: {:?}", ipc_event);
                 match ipc_event {
                     IpcEvent::SaveSettings(app_settings) => {
-                        self.update_app_settings(app_settings);
+                        self.update_app_settings(app_settings, ChangeSource::Ipc);
                     }
                     IpcEvent::ExtractResult(scraped_data) => {
                         self.add_scrape_result(scraped_data);
@@ -1152,11 +1563,12 @@ impl ApplicationHandler<UserEvent> for App {
                                 open::that(checkout_url).unwrap();
                             }
                             Err(e) => {
-                                let mut app_ui_state = self.db.get_app_ui_state().unwrap().clone();
-                                app_ui_state
-                                    .messages
-                                    .push(format!("Failed to get checkout session url: {:?}", e));
-                                self.db.set_app_ui_state(&app_ui_state).unwrap();
+                                self.add_ui_message(
+                                    MessageSeverity::Error,
+                                    ChangeSource::Ipc,
+                                    None,
+                                    &format!("Failed to get checkout session url: {:?}", e),
+                                );
                                 error!("Failed to get checkout session url: {:?}", e);
                             }
                         }
@@ -1174,20 +1586,23 @@ impl ApplicationHandler<UserEvent> for App {
                             self.settings.app_settings.licence_key =
                                 check_licence_request.licence_key;
                             self.settings.app_settings.email = check_licence_request.email;
-                            self.update_app_settings(self.settings.app_settings.clone());
-
-                            let mut all_messages = self.ui_state.messages.clone();
-                            all_messages.push("Licence check successful".to_string());
-                            self.db.set_app_ui_state(&AppUiState {
-                                app_settings: self.settings.app_settings.clone(),
-                                messages: all_messages,
-                            });
+                            self.update_app_settings(
+                                self.settings.app_settings.clone(),
+                                ChangeSource::Ipc,
+                            );
+                            self.add_ui_message(
+                                MessageSeverity::Info,
+                                ChangeSource::Ipc,
+                                None,
+                                "Licence check successful",
+                            );
                         } else {
-                            let mut app_ui_state = self.db.get_app_ui_state().unwrap().clone();
-                            app_ui_state
-                                .messages
-                                .push("Licence check failed".to_string());
-                            self.db.set_app_ui_state(&app_ui_state).unwrap();
+                            self.add_ui_message(
+                                MessageSeverity::Warning,
+                                ChangeSource::Ipc,
+                                None,
+                                "Licence check failed",
+                            );
                         }
                     }
                 }
@@ -1437,26 +1852,50 @@ impl DesktopAppSettings {
     }
 }
 
+/// Resolves once a change that affects which modifiers are scheduled is committed.
+async fn schedule_changed(changes: &mut broadcast::Receiver<DbChange>) {
+    loop {
+        match changes.recv().await {
+            Ok(
+                DbChange::ModifiersChanged { .. }
+                | DbChange::WidgetCreated { .. }
+                | DbChange::WidgetSaved { .. }
+                | DbChange::WidgetDeleted { .. }
+                | DbChange::WidgetPurged { .. }
+                | DbChange::Reloaded,
+            ) => return,
+            Ok(_) => continue,
+            // Whatever was missed may have been relevant
+            Err(broadcast::error::RecvError::Lagged(_)) => return,
+            Err(broadcast::error::RecvError::Closed) => std::future::pending().await,
+        }
+    }
+}
+
 fn load_app_settings(
-    db: &widget_db::Database,
+    db: &widget_db::DbService,
     licence_check_url: &str,
     api_base_url: &str,
 ) -> DesktopAppSettings {
-    let app_settings = match db.get_settings() {
-        Ok(settings) => settings,
-        Err(_) => {
-            // Insert default settings if not present
-            let default = AppSettings {
+    let mut app_settings = db
+        .blocking_read(|db| db.get_settings())
+        .unwrap_or_else(|e| {
+            error!("Failed to load settings, using the defaults: {:?}", e);
+            AppSettings {
                 show_tray_icon: true,
                 email: "".to_string(),
                 licence_key: "".to_string(),
-                machine_id: machine_uid::get().unwrap(),
+                machine_id: "".to_string(),
                 licence_tier: LicenceTier::None,
-            };
-            let _ = db.set_settings(&default);
-            default
+                trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
+            }
+        });
+    if app_settings.machine_id.is_empty() {
+        app_settings.machine_id = machine_uid::get().unwrap();
+        if let Err(e) = db.blocking_write(|db| db.set_settings(&app_settings)) {
+            error!("Failed to save machine id: {:?}", e);
         }
-    };
+    }
     DesktopAppSettings::new(app_settings, licence_check_url, api_base_url)
 }
 
@@ -1496,6 +1935,20 @@ fn setup_logging(config_dir: &Path) {
     }
 }
 
+/// Value of `--profile <name>` or `--profile=<name>`, if given.
+fn profile_flag() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--profile" {
+            return args.next();
+        }
+        if let Some(profile) = arg.strip_prefix("--profile=") {
+            return Some(profile.to_string());
+        }
+    }
+    None
+}
+
 fn main() {
     // env_logger::init();
     info!("Starting application...");
@@ -1524,12 +1977,16 @@ fn main() {
     } else {
         "http://localhost:3000"
     };
-    let app_db = widget_db::Database::from(false).unwrap();
+    let profile = widget_db::resolve_profile(profile_flag()).unwrap_or_else(|e| {
+        error!("Invalid profile, using the default: {:?}", e);
+        widget_db::DEFAULT_PROFILE.to_string()
+    });
+    info!("Using profile: {}", profile);
+
+    // load db, run migrations, etc. Everything below shares this one service
+    let app_db = widget_db::DbService::open_profile(&profile).unwrap();
     let desktop_settings = load_app_settings(&app_db, licence_check_url, api_base_url);
     info!("App settings: {:?}", desktop_settings);
-
-    // load db, run migrations, etc
-    let app_db = widget_db::Database::from(false).unwrap();
     let mut builder = EventLoop::<UserEvent>::with_user_event();
     #[cfg(target_os = "macos")]
     {
@@ -1572,14 +2029,10 @@ fn main() {
     tray_icon.set_visible(desktop_settings.app_settings.show_tray_icon);
 
     let mut app = App {
-        ui_state: AppUiState {
-            app_settings: desktop_settings.app_settings.clone(),
-            messages: vec![],
-        },
         updater: Updater::new(env!("CARGO_PKG_VERSION"), &updater_api_url),
         tray_icon,
         // theme,
-        db: app_db,
+        db: app_db.clone(),
         settings: desktop_settings,
         menu_items,
         current_size: LogicalSize::new(DEFAULT_WIDGET_WIDTH, DEFAULT_WIDGET_HEIGHT),
@@ -1591,41 +2044,47 @@ fn main() {
         last_resize: None,
         menu,
     };
+    app.refresh_layouts_menu();
 
     let modifier_thread_proxy = event_loop_proxy.clone();
 
     let event_sender = WinitEventSender::new(event_loop_proxy.clone());
     let rt = Runtime::new().unwrap();
+    let api_db = app_db.clone();
     thread::spawn(move || {
         // Execute the future, blocking the current thread until completion
         rt.block_on(async {
-            let mut api_db = widget_db::Database::from(false).unwrap();
             // put the new controls widget into the db
-            let res = api_db.upsert_widget_configuration(config[0].clone());
+            let controls = config[0].clone();
+            let res = api_db
+                .write(move |db| db.upsert_widget_configuration(controls, ChangeSource::App))
+                .await;
             match res {
                 Ok(_) => info!("Inserted widget configurations"),
                 Err(e) => error!("Error inserting widget configurations: {:?}", e),
             }
-            let res = api_db.insert_widget_modifiers(modifiers);
+            let res = api_db
+                .write(move |db| db.insert_widget_modifiers(modifiers, ChangeSource::App))
+                .await;
             match res {
                 Ok(_) => info!("Inserted widget modifiers"),
                 Err(e) => error!("Error inserting widget modifiers: {:?}", e),
             }
-            widget_db::run_api(
-                Arc::new(Mutex::new(api_db)),
-                event_sender.into_event_sender(),
-            )
-            .await;
+            widget_db::run_api(api_db, event_sender.into_event_sender()).await;
         });
     });
 
-    thread::spawn(move || loop {
-        let modifier_db_access = widget_db::Database::from(false).unwrap();
-        let mut last_refresh_dict = HashMap::new();
-        let mut last_scrape_dict = HashMap::new();
+    let modifier_db_access = app_db;
+    thread::spawn(move || {
+        let mut changes = modifier_db_access.subscribe();
+        let waiter = tokio::runtime::Builder::new_current_thread()
+            .enable_time()
+            .build()
+            .unwrap();
 
         loop {
-            let widget_modifiers = modifier_db_access.get_all_widget_modifiers();
+            let widget_modifiers =
+                modifier_db_access.blocking_read(|db| db.get_all_widget_modifiers());
             let modifiers = match widget_modifiers {
                 Ok(modifiers) => modifiers,
                 Err(e) => {
@@ -1634,56 +2093,51 @@ fn main() {
                 }
             };
 
-            // let Ok(widget_modifiers) = widget_modifiers else {
-            //     error!(
-            //         "Error getting widget modifiers: {:?}",
-            //         widget_modifiers.err().unwrap()
-            //     );
-            //     panic!("Error getting widget modifiers");
-            // };
             info!("Found {} widget modifiers", modifiers.len());
+            // Runs are tracked in the database, so a restart picks up where the last session
+            // left off instead of running everything again
+            let now = jiff::Timestamp::now().as_millisecond();
+            let mut wait = MAX_SCHEDULER_WAIT;
             for modifier in modifiers {
-                // info!("Modifier: {:?}", modifier);
-                let modifier_clone = modifier.clone();
-                let widget_id = modifier.widget_id.clone();
-                match modifier.modifier_type {
-                    Modifier::Refresh {
-                        modifier_id,
-                        interval_sec,
-                    } => {
-                        // info!("Refresh modifier: {:?}", modifier_id);
-                        let last_update = last_refresh_dict
-                            .entry(widget_id.clone())
-                            .or_insert(Instant::now());
-
-                        if last_update.elapsed().as_secs() >= interval_sec as u64 {
-                            info!("Refreshing widget: {:?}", widget_id);
-                            let _ = modifier_thread_proxy
-                                .send_event(UserEvent::ModifierEvent(modifier_clone));
-                            last_refresh_dict.insert(widget_id.clone(), Instant::now());
-                        }
-                    }
-                    Modifier::Scrape {
-                        modifier_id,
-                        selector,
-                    } => {
-                        info!(
-                            "Scrape modifier: {:?}, selector: {:?}",
-                            modifier_id, selector
-                        );
-                        let last_update = last_scrape_dict
-                            .entry(widget_id.clone())
-                            .or_insert(Instant::now());
-                        if last_update.elapsed().as_secs() >= DEFAULT_SCRAPE_INTERVAL {
-                            info!("Scraping widget: {:?}", widget_id);
-                            modifier_thread_proxy
-                                .send_event(UserEvent::ModifierEvent(modifier_clone));
-                            last_scrape_dict.insert(widget_id.clone(), Instant::now());
-                        }
-                    }
+                if !modifier.enabled {
+                    continue;
                 }
+                let interval_sec = match &modifier.modifier_type {
+                    Modifier::Refresh { interval_sec, .. } => (*interval_sec).max(0) as u64,
+                    Modifier::Scrape { .. } => DEFAULT_SCRAPE_INTERVAL,
+                };
+                let interval_ms = interval_sec as i64 * 1000;
+                let last_run_at = modifier
+                    .last_run_at
+                    .as_deref()
+                    .and_then(|ms| ms.parse::<i64>().ok());
+                if let Some(last) = last_run_at.filter(|last| now - last < interval_ms) {
+                    let due_in = Duration::from_millis((last + interval_ms - now) as u64);
+                    wait = wait.min(due_in);
+                    continue;
+                }
+
+                let modifier_id = modifier.modifier_type.modifier_id().0.clone();
+                info!(
+                    "Running modifier {} of widget {:?}",
+                    modifier_id, modifier.widget_id
+                );
+                if let Err(e) = modifier_db_access
+                    .blocking_write(|db| db.record_modifier_run(&modifier_id, now))
+                {
+                    error!("Failed to save run of modifier {}: {:?}", modifier_id, e);
+                }
+                wait = wait.min(Duration::from_secs(interval_sec));
+                let _ = modifier_thread_proxy.send_event(UserEvent::ModifierEvent(modifier));
             }
-            thread::sleep(Duration::from_secs(10));
+
+            // Sleep until the next modifier is due, or until modifiers change somewhere else
+            waiter.block_on(async {
+                tokio::select! {
+                    _ = tokio::time::sleep(wait.max(MIN_SCHEDULER_WAIT)) => {}
+                    _ = schedule_changed(&mut changes) => {}
+                }
+            });
         }
     });
     event_loop.run_app(&mut app).expect("Something failed");
@@ -42,6 +42,16 @@ export default function SettingsWidget() {
       });
   }, []);
 
+  // Pick up settings changed from the tray or the API
+  useEffect(() => {
+    window.onRustMessage = (element: string) => {
+      const message = JSON.parse(element);
+      if (message.data_key === "settings") {
+        setAppSettings(JSON.parse(message.message));
+      }
+    };
+  }, []);
+
   const handleSettingsChange = useCallback(
     (updatedData: Partial<AppSettings>) => {
       setAppSettings((prevSettings) => ({
@@ -0,0 +1,141 @@
+//! Latency of API reads while widgets are being scraped in the background.
+//!
+//! Run with `cargo bench -p widget-db`. Compare the `idle` and `scraping` groups: with reads on
+//! their own connections the second should stay close to the first.
+
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use axum::{body::Body, http::Request, Router};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use tower::ServiceExt;
+use widget_db::{router, DbService};
+use widget_types::{
+    ApiAction, ChangeSource, EventSender, EventSenderImpl, NanoId, ScrapedData, WidgetConfiguration,
+};
+
+const WIDGETS: usize = 10;
+const SCRAPERS: usize = 4;
+const HISTORY: usize = 1000;
+/// Far more often than widgets are really scraped, but slow enough that the history doesn't
+/// grow much over the run and the groups compare contention rather than table size.
+const SCRAPE_PAUSE: Duration = Duration::from_millis(50);
+
+#[derive(Clone)]
+struct NoopSender;
+
+impl EventSenderImpl for NoopSender {
+    fn send_message(&self, _message: ApiAction) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+fn scraped(widget_id: &str, value: usize) -> ScrapedData {
+    ScrapedData {
+        id: 0,
+        widget_id: widget_id.to_string(),
+        value: format!("${}.00", value),
+        error: None,
+        timestamp: jiff::Timestamp::now().as_millisecond().to_string(),
+        numeric_value: None,
+        unit: None,
+    }
+}
+
+fn widget_id(i: usize) -> String {
+    format!("widget{}", i)
+}
+
+fn setup(dir: &std::path::Path) -> DbService {
+    let db = DbService::open(&dir.join("widgets.db"), widget_db::DEFAULT_READERS).unwrap();
+    db.blocking_write(|db| {
+        db.insert_widget_configuration(
+            (0..WIDGETS)
+                .map(|i| WidgetConfiguration::new().with_widget_id(NanoId(widget_id(i))))
+                .collect(),
+            ChangeSource::App,
+        )?;
+        for i in 0..HISTORY {
+            db.insert_data(scraped(&widget_id(i % WIDGETS), i))?;
+        }
+        Ok(())
+    })
+    .unwrap();
+    db
+}
+
+/// Keeps inserting scrape results from `SCRAPERS` threads until `stop` is set.
+fn start_scraping(db: &DbService, stop: &Arc<AtomicBool>) -> Vec<thread::JoinHandle<()>> {
+    (0..SCRAPERS)
+        .map(|scraper| {
+            let (db, stop) = (db.clone(), stop.clone());
+            thread::spawn(move || {
+                let mut i = 0;
+                while !stop.load(Ordering::Relaxed) {
+                    let result = scraped(&widget_id((scraper + i) % WIDGETS), i);
+                    db.blocking_write(|db| db.insert_data(result)).unwrap();
+                    i += 1;
+                    thread::sleep(SCRAPE_PAUSE);
+                }
+            })
+        })
+        .collect()
+}
+
+fn bench_requests(c: &mut Criterion, group_name: &str, app: &Router) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let mut group = c.benchmark_group(group_name);
+    for uri in [
+        "/widgets",
+        "/values/latest",
+        "/widgets/widget0/latest",
+        "/widgets/widget0/values?limit=50",
+    ] {
+        group.bench_with_input(BenchmarkId::from_parameter(uri), uri, |b, uri| {
+            b.to_async(&rt).iter(|| async {
+                let response = app
+                    .clone()
+                    .oneshot(Request::get(uri).body(Body::empty()).unwrap())
+                    .await
+                    .unwrap();
+                assert!(response.status().is_success());
+            })
+        });
+    }
+    group.finish();
+}
+
+fn api_latency(c: &mut Criterion) {
+    let dir = std::env::temp_dir().join(format!("widget-db-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let db = setup(&dir);
+    let app = router(
+        db.clone(),
+        EventSender {
+            inner: Box::new(NoopSender),
+        },
+    );
+
+    bench_requests(c, "idle", &app);
+
+    let stop = Arc::new(AtomicBool::new(false));
+    let scrapers = start_scraping(&db, &stop);
+    bench_requests(c, "scraping", &app);
+    stop.store(true, Ordering::Relaxed);
+    for scraper in scrapers {
+        scraper.join().unwrap();
+    }
+
+    drop(app);
+    drop(db);
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+criterion_group!(benches, api_latency);
+criterion_main!(benches);
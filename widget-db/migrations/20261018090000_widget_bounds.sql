@@ -0,0 +1,10 @@
+-- Older builds wrote window bounds to a `position` column that never existed,
+-- so those updates were silently dropped. Make sure every widget has a
+-- well-formed bounds object before the code starts relying on it.
+UPDATE widgets
+SET bounds = json_object('x', 0, 'y', 0, 'width', 200, 'height', 200)
+WHERE json_valid(bounds) = 0
+   OR json_type(bounds, '$.x') IS NULL
+   OR json_type(bounds, '$.y') IS NULL
+   OR json_type(bounds, '$.width') IS NULL
+   OR json_type(bounds, '$.height') IS NULL;
@@ -0,0 +1,21 @@
+-- Modifiers were only identifiable by their integer row id even though the
+-- API hands out the `modifier_id` embedded in `modifier_type`. Store it in its
+-- own column so it can be queried directly.
+ALTER TABLE modifiers ADD COLUMN modifier_id TEXT;
+
+UPDATE modifiers
+SET modifier_id = json_extract(modifier_type, '$.content.modifier_id')
+WHERE json_valid(modifier_type);
+
+-- Rows written without an id get a fresh one, kept in sync with the JSON.
+UPDATE modifiers
+SET modifier_id = lower(hex(randomblob(4)))
+WHERE modifier_id IS NULL OR modifier_id = '';
+
+UPDATE modifiers
+SET modifier_type = json_set(modifier_type, '$.content.modifier_id', modifier_id)
+WHERE json_valid(modifier_type)
+  AND json_extract(modifier_type, '$.content.modifier_id') IS NOT modifier_id;
+
+CREATE INDEX IF NOT EXISTS idx_modifiers_widget_id ON modifiers (widget_id);
+CREATE INDEX IF NOT EXISTS idx_modifiers_modifier_id ON modifiers (modifier_id);
@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS idx_scraped_data_widget_timestamp ON scraped_data (widget_id, timestamp);
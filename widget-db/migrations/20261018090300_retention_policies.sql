@@ -0,0 +1,2 @@
+-- JSON encoded `RetentionPolicy`, NULL keeps all scrape history.
+ALTER TABLE widgets ADD COLUMN retention_policy TEXT;
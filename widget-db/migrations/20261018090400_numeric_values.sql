@@ -0,0 +1,4 @@
+-- Parsed form of `value`, filled in by `parse::parse_value` on insert. Existing rows are
+-- backfilled by the migration hook.
+ALTER TABLE scraped_data ADD COLUMN numeric_value REAL;
+ALTER TABLE scraped_data ADD COLUMN unit TEXT;
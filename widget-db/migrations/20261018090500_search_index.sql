@@ -0,0 +1,56 @@
+-- Full-text search over widgets and scraped values. `widget_search` rows share their rowid with
+-- `widgets.id`, `value_search` is an external content index over `scraped_data`.
+CREATE VIRTUAL TABLE widget_search USING fts5(title, source);
+CREATE VIRTUAL TABLE value_search USING fts5(value, content = 'scraped_data', content_rowid = 'id');
+
+-- URL or inline HTML of a widget, NULL when `widget_type` is unreadable
+CREATE TRIGGER widgets_search_insert AFTER INSERT ON widgets BEGIN
+    INSERT INTO widget_search (rowid, title, source)
+    VALUES (
+        new.id,
+        new.title,
+        CASE WHEN json_valid(new.widget_type) THEN
+            coalesce(json_extract(new.widget_type, '$.content.url'), json_extract(new.widget_type, '$.content.html'))
+        END
+    );
+END;
+
+CREATE TRIGGER widgets_search_update AFTER UPDATE OF title, widget_type ON widgets BEGIN
+    DELETE FROM widget_search WHERE rowid = old.id;
+    INSERT INTO widget_search (rowid, title, source)
+    VALUES (
+        new.id,
+        new.title,
+        CASE WHEN json_valid(new.widget_type) THEN
+            coalesce(json_extract(new.widget_type, '$.content.url'), json_extract(new.widget_type, '$.content.html'))
+        END
+    );
+END;
+
+CREATE TRIGGER widgets_search_delete AFTER DELETE ON widgets BEGIN
+    DELETE FROM widget_search WHERE rowid = old.id;
+END;
+
+CREATE TRIGGER scraped_data_search_insert AFTER INSERT ON scraped_data BEGIN
+    INSERT INTO value_search (rowid, value) VALUES (new.id, new.value);
+END;
+
+CREATE TRIGGER scraped_data_search_update AFTER UPDATE OF value ON scraped_data BEGIN
+    INSERT INTO value_search (value_search, rowid, value) VALUES ('delete', old.id, old.value);
+    INSERT INTO value_search (rowid, value) VALUES (new.id, new.value);
+END;
+
+CREATE TRIGGER scraped_data_search_delete AFTER DELETE ON scraped_data BEGIN
+    INSERT INTO value_search (value_search, rowid, value) VALUES ('delete', old.id, old.value);
+END;
+
+INSERT INTO widget_search (rowid, title, source)
+SELECT
+    id,
+    title,
+    CASE WHEN json_valid(widget_type) THEN
+        coalesce(json_extract(widget_type, '$.content.url'), json_extract(widget_type, '$.content.html'))
+    END
+FROM widgets;
+
+INSERT INTO value_search (value_search) VALUES ('rebuild');
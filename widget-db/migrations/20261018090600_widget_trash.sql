@@ -0,0 +1,3 @@
+-- Epoch milliseconds at which the widget was moved to the trash, NULL while it is in use.
+ALTER TABLE widgets ADD COLUMN deleted_at INTEGER;
+CREATE INDEX IF NOT EXISTS idx_widgets_deleted_at ON widgets (deleted_at);
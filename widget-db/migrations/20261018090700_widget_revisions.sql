@@ -0,0 +1,41 @@
+-- Every version of a widget's configuration. `widget` is a JSON encoded
+-- `WidgetConfiguration` (with `is_open` always false), `modifiers` a JSON array
+-- of `Modifier`. `created_at` is epoch milliseconds.
+CREATE TABLE IF NOT EXISTS widget_revisions (
+    id INTEGER PRIMARY KEY,
+    widget_id TEXT NOT NULL,
+    revision INTEGER NOT NULL,
+    source TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    deleted INTEGER NOT NULL DEFAULT 0,
+    widget TEXT NOT NULL,
+    modifiers TEXT NOT NULL,
+    UNIQUE (widget_id, revision)
+);
+
+-- Existing widgets start out with their current configuration as revision 1, so
+-- the first change made after upgrading can be rolled back.
+INSERT INTO widget_revisions (widget_id, revision, source, created_at, deleted, widget, modifiers)
+SELECT
+    w.widget_id,
+    1,
+    'app',
+    CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER),
+    w.deleted_at IS NOT NULL,
+    json_object(
+        'widget_id', w.widget_id,
+        'title', w.title,
+        'widget_type', json(w.widget_type),
+        'level', json(w.level),
+        'transparent', json(CASE WHEN w.transparent != 0 THEN 'true' ELSE 'false' END),
+        'decorations', json(CASE WHEN w.decorations != 0 THEN 'true' ELSE 'false' END),
+        'is_open', json('false'),
+        'bounds', json(w.bounds)
+    ),
+    (
+        SELECT json_group_array(json(m.modifier_type))
+        FROM modifiers m
+        WHERE m.widget_id = w.widget_id AND json_valid(m.modifier_type)
+    )
+FROM widgets w
+WHERE json_valid(w.widget_type) AND json_valid(w.level) AND json_valid(w.bounds);
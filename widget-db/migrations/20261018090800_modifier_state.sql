@@ -0,0 +1,33 @@
+-- Modifiers were unique by their whole serialized `modifier_type`, so two widgets
+-- could not share an identical modifier, while nothing stopped two rows from
+-- sharing a `modifier_id`. Key them by `modifier_id` instead and keep their run
+-- state so scheduling survives a restart. Timestamps are epoch milliseconds.
+
+-- Later rows that reuse an id get a fresh one, kept in sync with the JSON.
+UPDATE modifiers
+SET modifier_id = lower(hex(randomblob(4)))
+WHERE id != (SELECT MIN(m.id) FROM modifiers m WHERE m.modifier_id = modifiers.modifier_id);
+
+UPDATE modifiers
+SET modifier_type = json_set(modifier_type, '$.content.modifier_id', modifier_id)
+WHERE json_valid(modifier_type)
+  AND json_extract(modifier_type, '$.content.modifier_id') IS NOT modifier_id;
+
+CREATE TABLE modifiers_new (
+    id INTEGER PRIMARY KEY,
+    modifier_id TEXT NOT NULL UNIQUE,
+    widget_id TEXT NOT NULL,
+    modifier_type TEXT NOT NULL,
+    enabled INTEGER NOT NULL DEFAULT 1,
+    last_run_at INTEGER,
+    last_success_at INTEGER,
+    consecutive_failures INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT INTO modifiers_new (id, modifier_id, widget_id, modifier_type)
+SELECT id, modifier_id, widget_id, modifier_type FROM modifiers;
+
+DROP TABLE modifiers;
+ALTER TABLE modifiers_new RENAME TO modifiers;
+
+CREATE INDEX IF NOT EXISTS idx_modifiers_widget_id ON modifiers (widget_id);
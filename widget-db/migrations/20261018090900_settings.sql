@@ -0,0 +1,30 @@
+-- One row per setting instead of a JSON blob in `config`, which also holds the
+-- monitor information. Keys are `AppSettings` field names, values are JSON and
+-- `updated_at` is epoch milliseconds. Settings without a row have their default.
+CREATE TABLE IF NOT EXISTS settings (
+    key TEXT PRIMARY KEY,
+    value TEXT NOT NULL,
+    version INTEGER NOT NULL DEFAULT 1,
+    updated_at INTEGER NOT NULL
+);
+
+-- Settings blobs are objects, monitor information is stored as an array.
+INSERT INTO settings (key, value, updated_at)
+SELECT
+    j.key,
+    CASE j.type
+        WHEN 'true' THEN 'true'
+        WHEN 'false' THEN 'false'
+        WHEN 'null' THEN 'null'
+        WHEN 'object' THEN j.value
+        WHEN 'array' THEN j.value
+        ELSE json_quote(j.value)
+    END,
+    CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)
+FROM config c, json_each(c.json) j
+WHERE c.id = (
+    SELECT MAX(id) FROM config
+    WHERE json_valid(json) AND json_type(json) = 'object'
+);
+
+DELETE FROM config WHERE NOT json_valid(json) OR json_type(json) = 'object';
@@ -0,0 +1,28 @@
+-- Messages for the user were a JSON array inside the single `app_ui_state` row,
+-- read and rewritten whole on every change. `created_at` is epoch milliseconds.
+CREATE TABLE IF NOT EXISTS messages (
+    id INTEGER PRIMARY KEY,
+    severity TEXT NOT NULL,
+    source TEXT NOT NULL,
+    widget_id TEXT,
+    message TEXT NOT NULL,
+    created_at INTEGER NOT NULL,
+    acknowledged INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS idx_messages_created_at ON messages (created_at);
+
+-- Kept in their original order, they have no timestamps of their own.
+INSERT INTO messages (severity, source, message, created_at)
+SELECT
+    'info',
+    'app',
+    j.value,
+    CAST((julianday('now') - 2440587.5) * 86400000 AS INTEGER)
+FROM app_ui_state a, json_each(a.json, '$.messages') j
+WHERE json_valid(a.json)
+  AND json_type(a.json, '$.messages') = 'array'
+  AND j.type = 'text'
+ORDER BY a.rowid, j.key;
+
+DROP TABLE app_ui_state;
@@ -0,0 +1,9 @@
+-- Free-form labels for grouping widgets. Tags belong to the widget rather than to a
+-- revision, so rolling a widget back keeps them.
+CREATE TABLE IF NOT EXISTS widget_tags (
+    widget_id TEXT NOT NULL,
+    tag TEXT NOT NULL,
+    PRIMARY KEY (widget_id, tag)
+);
+
+CREATE INDEX IF NOT EXISTS idx_widget_tags_tag ON widget_tags (tag);
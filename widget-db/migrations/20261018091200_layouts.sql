@@ -0,0 +1,15 @@
+-- Named snapshots of where every widget is, whether it's open and how it's layered.
+-- `bounds` and `level` are JSON like in `widgets`, `created_at` is epoch milliseconds.
+CREATE TABLE IF NOT EXISTS layouts (
+    name TEXT PRIMARY KEY,
+    created_at INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS layout_widgets (
+    layout_name TEXT NOT NULL,
+    widget_id TEXT NOT NULL,
+    bounds TEXT NOT NULL,
+    is_open INTEGER NOT NULL,
+    level TEXT NOT NULL,
+    PRIMARY KEY (layout_name, widget_id)
+);
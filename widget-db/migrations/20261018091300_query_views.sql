@@ -0,0 +1,31 @@
+-- What `POST /query` can read. Queries are only allowed to touch these views, so settings,
+-- messages and the raw JSON columns stay out of reach. Timestamps are epoch milliseconds,
+-- with the local time split out for questions like "weekdays between 9 and 5".
+CREATE VIEW IF NOT EXISTS query_widgets AS
+SELECT
+    widget_id,
+    title,
+    json_extract(widget_type, '$.type') AS widget_type,
+    json_extract(widget_type, '$.content.url') AS url,
+    json_extract(level, '$') AS level,
+    is_open,
+    json_extract(bounds, '$.x') AS x,
+    json_extract(bounds, '$.y') AS y,
+    json_extract(bounds, '$.width') AS width,
+    json_extract(bounds, '$.height') AS height,
+    deleted_at
+FROM widgets;
+
+CREATE VIEW IF NOT EXISTS query_values AS
+SELECT
+    id,
+    widget_id,
+    value,
+    numeric_value,
+    unit,
+    NULLIF(error, '') AS error,
+    CAST(timestamp AS INTEGER) AS timestamp,
+    datetime(CAST(timestamp AS INTEGER) / 1000, 'unixepoch', 'localtime') AS local_time,
+    CAST(strftime('%w', CAST(timestamp AS INTEGER) / 1000, 'unixepoch', 'localtime') AS INTEGER) AS weekday,
+    CAST(strftime('%H', CAST(timestamp AS INTEGER) / 1000, 'unixepoch', 'localtime') AS INTEGER) AS hour
+FROM scraped_data;
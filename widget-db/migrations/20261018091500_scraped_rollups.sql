@@ -0,0 +1,17 @@
+-- What downsampling removed from `scraped_data`, per widget and hour or day bucket, so aggregates
+-- over compacted ranges stay right. `bucket_ms` is the bucket width and times are epoch
+-- milliseconds. `total` is the sum of the values, the average is `total / count`.
+CREATE TABLE IF NOT EXISTS scraped_rollups (
+    widget_id TEXT NOT NULL,
+    bucket_ms INTEGER NOT NULL,
+    bucket_start INTEGER NOT NULL,
+    count INTEGER NOT NULL,
+    total REAL NOT NULL,
+    min_value REAL NOT NULL,
+    max_value REAL NOT NULL,
+    first_value REAL NOT NULL,
+    first_at INTEGER NOT NULL,
+    last_value REAL NOT NULL,
+    last_at INTEGER NOT NULL,
+    PRIMARY KEY (widget_id, bucket_ms, bucket_start)
+);
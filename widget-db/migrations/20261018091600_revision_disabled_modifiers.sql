@@ -0,0 +1,3 @@
+-- JSON array of the ids of the modifiers that were disabled at each revision. Older revisions
+-- had no way to tell, they count as having everything enabled.
+ALTER TABLE widget_revisions ADD COLUMN disabled_modifiers TEXT NOT NULL DEFAULT '[]';
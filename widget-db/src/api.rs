@@ -2,16 +2,20 @@ pub mod api {
     use nanoid::nanoid_gen;
     use serde::{Deserialize, Serialize};
     use serde_json::{json, Value};
-    use std::sync::Arc;
-    use tokio::sync::Mutex;
     use tower_http::trace::TraceLayer;
     use widget_types::{
-        ApiAction, AppSettings, AppUiState, EventSender, MonitorPosition, WidgetBounds, API_PORT,
-        DEFAULT_WIDGET_HEIGHT, DEFAULT_WIDGET_WIDTH, DEFAULT_WIDGET_X, DEFAULT_WIDGET_Y,
+        AggregateBucket, AppMessage, BackupInfo, ChangeSource, CreateLayoutRequest,
+        CreateProfileRequest, CreateWebhookRequest, CreateWidgetRequest, CreatedWebhook,
+        DeliveryStatus, FileConfiguration, HistoryPage, ImportReport, LayoutPreset,
+        MessageSeverity, Modifier, ProfileInfo, RetentionPolicy, RevisionDiff, ScrapedData,
+        SearchResult, SettingEntry, TagInfo, TrashedWidget, UpdateModifierRequest,
+        UpdateWidgetRequest, UrlConfiguration, Webhook, WebhookDelivery, WidgetConfiguration,
+        WidgetModifier, WidgetRevision, WidgetType, WorkspaceBundle,
     };
     use widget_types::{
-        CreateWidgetRequest, FileConfiguration, Modifier, UrlConfiguration, WidgetConfiguration,
-        WidgetModifier, WidgetType,
+        ApiAction, AppSettings, AppUiState, EventSender, MonitorPosition, WidgetBounds, API_PORT,
+        CONTROLS_WIDGET_ID, DEFAULT_WIDGET_HEIGHT, DEFAULT_WIDGET_WIDTH, DEFAULT_WIDGET_X,
+        DEFAULT_WIDGET_Y,
     };
 
     // use crate::db::db::ScrapedData;
@@ -24,6 +28,7 @@ pub mod api {
     use axum::routing::post;
 
     use axum::extract::Path;
+    use axum::extract::Query;
     use axum::Router;
     use log::info;
     use log::{debug, error};
@@ -32,7 +37,9 @@ pub mod api {
 
     use axum::http::StatusCode;
 
+    use axum::response::sse::{Event, KeepAlive, Sse};
     use axum::response::IntoResponse;
+    use futures::{Stream, StreamExt};
 
     use axum::extract::State;
 
@@ -41,62 +48,151 @@ pub mod api {
     use http::Request;
     use std::time::Instant;
 
-    pub async fn run_api(db: Arc<Mutex<crate::db::db::Database>>, event_sender: EventSender) {
+    pub async fn run_api(db: DbService, event_sender: EventSender) {
         info!("Starting API");
-        let state = ApiState {
-            db: db.clone(),
-            event_sender: event_sender.clone(),
+        tokio::spawn(run_compaction(db.clone(), COMPACTION_INTERVAL));
+        tokio::spawn(run_backups(db.clone(), BACKUP_INTERVAL));
+        tokio::spawn(run_webhooks(db.clone()));
+
+        let router = router(db, event_sender);
+
+        let addr = format!("{}:{}", "127.0.0.1", API_PORT);
+        // After a profile switch the previous instance may still be shutting down and holding
+        // the port for a moment
+        let mut attempts = 0;
+        let listener = loop {
+            match tokio::net::TcpListener::bind(&addr).await {
+                Ok(listener) => break listener,
+                Err(e) if attempts < 10 => {
+                    attempts += 1;
+                    info!("Waiting for {} to be free: {}", addr, e);
+                    tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+                }
+                Err(e) => panic!("Failed to bind {}: {}", addr, e),
+            }
         };
+        info!("API listening on http://{}", addr);
+        axum::serve(listener, router).await.unwrap();
+    }
+
+    /// Every route of the API, without the background tasks `run_api` starts next to it.
+    pub fn router(db: DbService, event_sender: EventSender) -> Router {
+        let state = ApiState { db, event_sender };
 
         let cors_layer = CorsLayer::new()
             .allow_methods(vec![
                 http::Method::GET,
                 http::Method::POST,
+                http::Method::PUT,
+                http::Method::PATCH,
                 http::Method::DELETE,
             ])
             .allow_headers(vec![http::HeaderName::from_static("content-type")])
             .allow_origin(AllowOrigin::any());
 
-        let router = Router::new()
+        Router::new()
             .route("/values", get(get_values))
             // .route("/sites", get(get_sites))
             // .route("/elements", get(get_elements))
             // .route("/latest", get(get_latest_values))
             .route(
                 "/widgets/{widget_id}",
-                delete(delete_widget).post(widget_rpc_handler),
+                delete(delete_widget)
+                    .post(widget_rpc_handler)
+                    .patch(update_widget),
             )
+            .route("/values/latest", get(get_latest_values_per_widget))
             .route("/widgets/{widget_id}/latest", get(get_latest_values))
+            .route("/widgets/{widget_id}/values", get(get_widget_values))
+            .route(
+                "/widgets/{widget_id}/values/aggregate",
+                get(get_widget_aggregates),
+            )
+            .route(
+                "/widgets/{widget_id}/retention",
+                get(get_retention_policy).put(set_retention_policy),
+            )
+            .route("/widgets/{widget_id}/refresh", post(refresh_widget))
+            .route("/widgets/{widget_id}/scrape", post(scrape_widget))
+            .route("/widgets/{widget_id}/revisions", get(list_revisions))
+            .route("/widgets/{widget_id}/revisions/diff", get(diff_revisions))
+            .route(
+                "/widgets/{widget_id}/revisions/{revision}/rollback",
+                post(rollback_widget),
+            )
             .route("/widgets", get(get_widgets).post(create_widget))
+            .route(
+                "/widgets/{widget_id}/tags",
+                get(get_widget_tags).put(set_widget_tags),
+            )
+            .route("/tags", get(list_tags))
+            .route("/layouts", get(list_layouts).post(save_layout))
+            .route("/layouts/{name}", get(get_layout).delete(delete_layout))
+            .route("/layouts/{name}/apply", post(apply_layout))
+            .route("/tags/{tag}/show", post(show_tag))
+            .route("/tags/{tag}/hide", post(hide_tag))
+            .route("/tags/{tag}/refresh", post(refresh_tag))
+            .route("/tags/{tag}/delete", post(delete_tag))
+            .route("/trash", get(get_trash).delete(empty_trash))
+            .route("/trash/{widget_id}", delete(purge_widget))
+            .route("/trash/{widget_id}/restore", post(restore_widget))
             .route(
                 "/widgets/{id}/modifiers",
                 post(add_widget_modifier).get(get_widget_modifiers),
             )
             .route(
                 "/widgets/{widget_id}/modifiers/{modifier_id}",
-                delete(delete_widget_modifier),
+                delete(delete_widget_modifier).put(update_widget_modifier),
+            )
+            .route(
+                "/widgets/{widget_id}/modifiers/{modifier_id}/enable",
+                post(enable_widget_modifier),
+            )
+            .route(
+                "/widgets/{widget_id}/modifiers/{modifier_id}/disable",
+                post(disable_widget_modifier),
             )
+            .route("/search", get(search))
+            .route("/query", post(run_query))
+            .route("/events", get(stream_events))
+            .route("/profiles", get(list_profiles).post(create_profile))
+            .route("/profiles/{name}/activate", post(activate_profile))
+            .route("/backups", get(list_backups).post(create_backup))
+            .route("/backups/{name}/restore", post(restore_backup))
+            .route("/export", get(export_workspace))
+            .route("/import", post(import_workspace))
             .route("/settings", get(get_settings).post(set_settings))
+            .route("/settings/{key}", get(get_setting).put(set_setting))
             .route("/app-ui-state", get(get_app_ui_state))
+            .route("/messages", get(get_messages))
+            .route("/messages/{id}/ack", post(acknowledge_message))
+            .route("/webhooks", get(list_webhooks).post(create_webhook))
+            .route("/webhooks/{id}", get(get_webhook).delete(delete_webhook))
+            .route("/webhooks/{id}/deliveries", get(get_webhook_deliveries))
             .layer(TraceLayer::new_for_http())
             .layer(cors_layer)
             // .layer(axum::middleware::from_fn(logging_middleware))
-            .with_state(state);
-
-        let addr = format!("{}:{}", "127.0.0.1", API_PORT);
-        let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-        info!("API listening on http://{}", addr);
-        axum::serve(listener, router).await.unwrap();
+            .with_state(state)
     }
 
     use thiserror::Error;
 
+    use crate::backup::backup::{run_backups, BACKUP_INTERVAL};
     use crate::deserializer::deserializer::Json;
+    use crate::error::error::DbError;
+    use crate::events::events::{live_events, next_scrapes};
+    use crate::history::history::HistoryCursor;
+    use crate::messages::messages::{MessageFilter, MAX_MESSAGES};
+    use crate::paths::paths;
+    use crate::query::query::{QueryRequest, QueryResult};
+    use crate::retention::retention::{run_compaction, COMPACTION_INTERVAL};
+    use crate::service::service::DbService;
+    use crate::webhooks::webhooks::{run_webhooks, DeliveryFilter, MAX_DELIVERIES};
 
     #[derive(Debug, Error)]
     pub enum ApiError {
         #[error("Database error: {0}")]
-        Database(#[from] rusqlite::Error),
+        Database(#[from] DbError),
 
         #[error("Event sender error: {0}")]
         EventSender(String),
@@ -109,17 +205,39 @@ pub mod api {
 
         #[error("Invalid request: {0}")]
         InvalidRequest(String),
+
+        #[error("Timed out: {0}")]
+        Timeout(String),
     }
 
     // We implement `IntoResponse` so ApiError can be used as a response
     impl IntoResponse for ApiError {
         fn into_response(self) -> axum::response::Response {
             let (status, message) = match self {
-                ApiError::Database(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()),
+                ApiError::Database(e) => {
+                    let status = match &e {
+                        DbError::NotFound(_) => StatusCode::NOT_FOUND,
+                        DbError::InvalidInput(_) => StatusCode::BAD_REQUEST,
+                        DbError::Conflict(_) => StatusCode::CONFLICT,
+                        DbError::Sqlite(rusqlite::Error::SqliteFailure(err, _))
+                            if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+                        {
+                            StatusCode::CONFLICT
+                        }
+                        DbError::Sqlite(_)
+                        | DbError::Migration(_)
+                        | DbError::CorruptRow { .. }
+                        | DbError::Serialization(_)
+                        | DbError::Io(_)
+                        | DbError::Task(_) => StatusCode::INTERNAL_SERVER_ERROR,
+                    };
+                    (status, e.to_string())
+                }
                 ApiError::EventSender(e) => (StatusCode::INTERNAL_SERVER_ERROR, e),
                 ApiError::WidgetNotFound(e) => (StatusCode::NOT_FOUND, e),
                 ApiError::ModifierNotFound(e) => (StatusCode::NOT_FOUND, e),
                 ApiError::InvalidRequest(e) => (StatusCode::BAD_REQUEST, e),
+                ApiError::Timeout(e) => (StatusCode::GATEWAY_TIMEOUT, e),
             };
 
             let payload = json!({
@@ -136,7 +254,7 @@ pub mod api {
         State(state): State<ApiState>,
         Path(widget_id): Path<String>,
     ) -> Result<StatusCode, ApiError> {
-        info!("Deleting widget {}", widget_id);
+        info!("Moving widget {} to the trash", widget_id);
 
         if state
             .event_sender
@@ -148,12 +266,286 @@ pub mod api {
             ));
         }
 
-        let mut db = state.db.lock().await;
-        db.delete_widget(&widget_id)?;
+        state
+            .db
+            .write(move |db| db.delete_widget(&widget_id, ChangeSource::Api))
+            .await?;
 
         Ok(StatusCode::NO_CONTENT)
     }
 
+    #[axum::debug_handler]
+    pub(crate) async fn update_widget(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+        Json(update): Json<UpdateWidgetRequest>,
+    ) -> Result<Json<WidgetConfiguration>, ApiError> {
+        info!("Updating widget {}", widget_id);
+        if widget_id == CONTROLS_WIDGET_ID {
+            return Err(ApiError::InvalidRequest(
+                "the controls widget can't be edited".into(),
+            ));
+        }
+
+        let widget = state
+            .db
+            .write(move |db| db.update_widget(&widget_id, &update, ChangeSource::Api))
+            .await?;
+
+        if state
+            .event_sender
+            .send_message(ApiAction::UpdateWidget(widget.clone()))
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send update widget event".into(),
+            ));
+        }
+        Ok(Json(widget))
+    }
+
+    /// How long `POST /widgets/{widget_id}/scrape?wait=true` waits for the page to answer.
+    const SCRAPE_WAIT_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(15);
+
+    /// Refreshing and scraping need the widget's window, which only open widgets have.
+    async fn open_widget(
+        state: &ApiState,
+        widget_id: String,
+    ) -> Result<WidgetConfiguration, ApiError> {
+        let widget = state
+            .db
+            .read(move |db| db.get_widget_configuration_by_id(&widget_id))
+            .await?;
+        if !widget.is_open {
+            return Err(ApiError::InvalidRequest(format!(
+                "widget {} isn't open",
+                widget.widget_id.0
+            )));
+        }
+        Ok(widget)
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn refresh_widget(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+    ) -> Result<StatusCode, ApiError> {
+        info!("Refreshing widget {}", widget_id);
+        let widget = open_widget(&state, widget_id).await?;
+        if state
+            .event_sender
+            .send_message(ApiAction::RefreshWidget {
+                widget_id: widget.widget_id.0,
+            })
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send refresh widget event".into(),
+            ));
+        }
+        Ok(StatusCode::ACCEPTED)
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct ScrapeQuery {
+        /// Answer with the scraped values instead of right away.
+        #[serde(default)]
+        wait: bool,
+    }
+
+    /// Runs the widget's enabled scrape modifiers now. With `wait` the response lists the next
+    /// result stored for the widget per scrape modifier, failed scrapes included, in the order they
+    /// were stored. Results carry no request id, so a scheduled scrape finishing at the same
+    /// time can take the place of one of this request's.
+    #[axum::debug_handler]
+    pub(crate) async fn scrape_widget(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+        Query(query): Query<ScrapeQuery>,
+    ) -> Result<Response, ApiError> {
+        info!("Scraping widget {}", widget_id);
+        let widget = open_widget(&state, widget_id).await?;
+        let modifier_widget_id = widget.widget_id.0.clone();
+        let modifiers = state
+            .db
+            .read(move |db| db.get_widget_modifier(&modifier_widget_id))
+            .await?
+            .into_iter()
+            .filter(|modifier| modifier.enabled)
+            .map(|modifier| modifier.modifier_type)
+            .filter(|modifier| matches!(modifier, Modifier::Scrape { .. }))
+            .collect::<Vec<_>>();
+        if modifiers.is_empty() {
+            return Err(ApiError::InvalidRequest(format!(
+                "widget {} has no enabled scrape modifier",
+                widget.widget_id.0
+            )));
+        }
+
+        // Subscribed before the scrape is asked for, so no result can arrive unseen
+        let mut changes = state.db.subscribe();
+        let expected = modifiers.len();
+        if state
+            .event_sender
+            .send_message(ApiAction::ScrapeWidget {
+                widget_id: widget.widget_id.0.clone(),
+                modifiers,
+            })
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send scrape widget event".into(),
+            ));
+        }
+        if !query.wait {
+            return Ok(StatusCode::ACCEPTED.into_response());
+        }
+
+        let waited = tokio::time::timeout(
+            SCRAPE_WAIT_TIMEOUT,
+            next_scrapes(&mut changes, &state.db, &widget.widget_id.0, expected),
+        )
+        .await;
+        match waited {
+            Ok(Ok(Some(data))) => Ok(Json(data).into_response()),
+            Ok(Ok(None)) => Err(ApiError::Database(DbError::Task(
+                "the database closed before the scrape finished".into(),
+            ))),
+            Ok(Err(e)) => Err(e.into()),
+            Err(_) => Err(ApiError::Timeout(format!(
+                "widget {} didn't answer within {} seconds",
+                widget.widget_id.0,
+                SCRAPE_WAIT_TIMEOUT.as_secs()
+            ))),
+        }
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn get_trash(
+        State(state): State<ApiState>,
+    ) -> Result<Json<Vec<TrashedWidget>>, ApiError> {
+        let trash = state.db.read(|db| db.get_trash()).await?;
+        Ok(Json(trash))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn restore_widget(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+    ) -> Result<Json<WidgetConfiguration>, ApiError> {
+        info!("Restoring widget {}", widget_id);
+
+        let widget = state
+            .db
+            .write(move |db| {
+                db.restore_widget(&widget_id, ChangeSource::Api)?;
+                db.get_widget_configuration_by_id(&widget_id)
+            })
+            .await?;
+
+        if state
+            .event_sender
+            .send_message(ApiAction::CreateWidget(widget.clone()))
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send create widget event".into(),
+            ));
+        }
+
+        Ok(Json(widget))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn purge_widget(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+    ) -> Result<StatusCode, ApiError> {
+        info!("Purging widget {}", widget_id);
+
+        state
+            .db
+            .write(move |db| db.purge_widget(&widget_id))
+            .await?;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn empty_trash(
+        State(state): State<ApiState>,
+    ) -> Result<Json<Vec<String>>, ApiError> {
+        info!("Emptying trash");
+
+        let purged = state.db.write(|db| db.empty_trash(None)).await?;
+        Ok(Json(purged))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn list_revisions(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+    ) -> Result<Json<Vec<WidgetRevision>>, ApiError> {
+        let revisions = state
+            .db
+            .read(move |db| db.list_revisions(&widget_id))
+            .await?;
+        Ok(Json(revisions))
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct DiffQuery {
+        from: u32,
+        to: u32,
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn diff_revisions(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+        Query(query): Query<DiffQuery>,
+    ) -> Result<Json<RevisionDiff>, ApiError> {
+        let diff = state
+            .db
+            .read(move |db| db.diff_revisions(&widget_id, query.from, query.to))
+            .await?;
+        Ok(Json(diff))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn rollback_widget(
+        State(state): State<ApiState>,
+        Path((widget_id, revision)): Path<(String, u32)>,
+    ) -> Result<Json<WidgetConfiguration>, ApiError> {
+        info!("Rolling widget {} back to revision {}", widget_id, revision);
+
+        let widget = state
+            .db
+            .write({
+                let widget_id = widget_id.clone();
+                move |db| db.rollback_widget(&widget_id, revision, ChangeSource::Api)
+            })
+            .await?;
+
+        // Recreate the window so it picks up the old configuration
+        if widget.is_open {
+            let recreated = state
+                .event_sender
+                .send_message(ApiAction::DeleteWidget(widget_id))
+                .is_ok()
+                && state
+                    .event_sender
+                    .send_message(ApiAction::CreateWidget(widget.clone()))
+                    .is_ok();
+            if !recreated {
+                return Err(ApiError::EventSender(
+                    "Failed to send recreate widget events".into(),
+                ));
+            }
+        }
+
+        Ok(Json(widget))
+    }
+
     #[axum::debug_handler]
     pub(crate) async fn widget_rpc_handler(
         State(state): State<ApiState>,
@@ -223,6 +615,16 @@ pub mod api {
             }),
         };
 
+        // Saved before the window opens, so an invalid modifier leaves nothing behind
+        let widget = widget_config.clone();
+        let modifiers = widget_request.modifiers;
+        state
+            .db
+            .write(move |db| {
+                db.create_widget_with_modifiers(&widget, &modifiers, ChangeSource::Api)
+            })
+            .await?;
+
         if state
             .event_sender
             .send_message(ApiAction::CreateWidget(widget_config.clone()))
@@ -233,30 +635,12 @@ pub mod api {
             ));
         }
 
-        let mut db = state.db.lock().await;
-        db.insert_widget_configuration(vec![widget_config.clone()])?;
-
-        if !widget_request.modifiers.is_empty() {
-            db.insert_widget_modifiers(
-                widget_request
-                    .modifiers
-                    .iter()
-                    .map(|m| WidgetModifier {
-                        id: 0,
-                        widget_id: widget_config.widget_id.clone(),
-                        modifier_type: m.clone(),
-                    })
-                    .collect(),
-            )?;
-        }
-
         Ok((StatusCode::CREATED, Json(json!(widget_config))))
     }
 
     #[axum::debug_handler]
     pub(crate) async fn get_values(State(state): State<ApiState>) -> impl IntoResponse {
-        let db = state.db.lock().await;
-        match db.get_data() {
+        match state.db.read(|db| db.get_data()).await {
             Ok(values) => Json(values).into_response(),
             Err(e) => {
                 error!("Failed to get values: {:?}", e);
@@ -269,16 +653,94 @@ pub mod api {
     pub(crate) async fn get_latest_values(
         State(state): State<ApiState>,
         Path(widget_id): Path<String>,
-    ) -> impl IntoResponse {
+    ) -> Result<Json<Vec<ScrapedData>>, ApiError> {
         info!("Getting latest values for widget {}", widget_id);
-        let db = state.db.lock().await;
-        match db.get_latest_data() {
-            Ok(values) => Json(values).into_response(),
-            Err(e) => {
-                error!("Failed to get latest values: {:?}", e);
-                StatusCode::INTERNAL_SERVER_ERROR.into_response()
-            }
+        let latest = state
+            .db
+            .read(move |db| db.get_latest_widget_data(&widget_id))
+            .await?;
+        Ok(Json(latest.into_iter().collect()))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn get_latest_values_per_widget(
+        State(state): State<ApiState>,
+    ) -> Result<Json<Vec<ScrapedData>>, ApiError> {
+        let latest = state.db.read(|db| db.get_latest_data_per_widget()).await?;
+        Ok(Json(latest))
+    }
+
+    const DEFAULT_HISTORY_LIMIT: usize = 100;
+    const MAX_HISTORY_LIMIT: usize = 1000;
+
+    /// `from` and `to` are epoch milliseconds, like the scraped timestamps.
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct HistoryQuery {
+        from: Option<i64>,
+        to: Option<i64>,
+        limit: Option<usize>,
+        cursor: Option<String>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct AggregateQuery {
+        from: Option<i64>,
+        to: Option<i64>,
+        /// Bucket width in seconds
+        interval: u32,
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn get_widget_values(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+        Query(query): Query<HistoryQuery>,
+    ) -> Result<Json<HistoryPage>, ApiError> {
+        let limit = query.limit.unwrap_or(DEFAULT_HISTORY_LIMIT);
+        if limit == 0 || limit > MAX_HISTORY_LIMIT {
+            return Err(ApiError::InvalidRequest(format!(
+                "limit must be between 1 and {}",
+                MAX_HISTORY_LIMIT
+            )));
+        }
+        let cursor = query
+            .cursor
+            .as_deref()
+            .map(str::parse::<HistoryCursor>)
+            .transpose()
+            .map_err(ApiError::InvalidRequest)?;
+
+        let page = state
+            .db
+            .read(move |db| db.get_widget_history(&widget_id, query.from, query.to, limit, cursor))
+            .await?;
+        Ok(Json(page))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn get_widget_aggregates(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+        Query(query): Query<AggregateQuery>,
+    ) -> Result<Json<Vec<AggregateBucket>>, ApiError> {
+        if query.interval == 0 {
+            return Err(ApiError::InvalidRequest(
+                "interval must be at least one second".into(),
+            ));
         }
+
+        let buckets = state
+            .db
+            .read(move |db| {
+                db.get_widget_aggregates(
+                    &widget_id,
+                    query.from,
+                    query.to,
+                    query.interval as i64 * 1000,
+                )
+            })
+            .await?;
+        Ok(Json(buckets))
     }
 
     // pub(crate) async fn get_sites(State(state): State<ApiState>) -> impl IntoResponse {
@@ -303,17 +765,240 @@ pub mod api {
     //     }
     // }
 
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct WidgetsQuery {
+        tag: Option<String>,
+    }
+
     #[axum::debug_handler]
     pub(crate) async fn get_widgets(
         State(state): State<ApiState>,
+        Query(query): Query<WidgetsQuery>,
     ) -> Result<Json<Vec<WidgetConfiguration>>, ApiError> {
         info!("get widgets called");
-        let db = state.db.lock().await;
-        let widgets = db.get_configuration()?;
+        let widgets = state
+            .db
+            .read(move |db| match &query.tag {
+                Some(tag) => db.get_configuration_by_tag(tag),
+                None => db.get_configuration(),
+            })
+            .await?;
         info!("# widgets: {:?}", widgets.len());
         Ok(Json(widgets))
     }
 
+    #[axum::debug_handler]
+    pub(crate) async fn list_tags(
+        State(state): State<ApiState>,
+    ) -> Result<Json<Vec<TagInfo>>, ApiError> {
+        let tags = state.db.read(|db| db.list_tags()).await?;
+        Ok(Json(tags))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn get_widget_tags(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+    ) -> Result<Json<Vec<String>>, ApiError> {
+        let tags = state
+            .db
+            .read(move |db| db.get_widget_tags(&widget_id))
+            .await?;
+        Ok(Json(tags))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn set_widget_tags(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+        Json(tags): Json<Vec<String>>,
+    ) -> Result<Json<Vec<String>>, ApiError> {
+        info!("Setting tags of widget {}: {:?}", widget_id, tags);
+        let tags = state
+            .db
+            .write(move |db| db.set_widget_tags(&widget_id, &tags))
+            .await?;
+        Ok(Json(tags))
+    }
+
+    async fn tagged_widget_ids(state: &ApiState, tag: String) -> Result<Vec<String>, ApiError> {
+        let widgets = state
+            .db
+            .read(move |db| db.get_configuration_by_tag(&tag))
+            .await?;
+        Ok(widgets
+            .into_iter()
+            .map(|widget| widget.widget_id.0)
+            .filter(|widget_id| widget_id != CONTROLS_WIDGET_ID)
+            .collect())
+    }
+
+    async fn set_tag_visibility(
+        state: ApiState,
+        tag: String,
+        visible: bool,
+    ) -> Result<Json<Vec<String>>, ApiError> {
+        info!("Setting visibility of widgets tagged {}: {}", tag, visible);
+        let widget_ids = tagged_widget_ids(&state, tag).await?;
+        if state
+            .event_sender
+            .send_message(ApiAction::SetWidgetsVisibility {
+                widget_ids: widget_ids.clone(),
+                visible,
+            })
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send widgets visibility event".into(),
+            ));
+        }
+        Ok(Json(widget_ids))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn show_tag(
+        State(state): State<ApiState>,
+        Path(tag): Path<String>,
+    ) -> Result<Json<Vec<String>>, ApiError> {
+        set_tag_visibility(state, tag, true).await
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn hide_tag(
+        State(state): State<ApiState>,
+        Path(tag): Path<String>,
+    ) -> Result<Json<Vec<String>>, ApiError> {
+        set_tag_visibility(state, tag, false).await
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn refresh_tag(
+        State(state): State<ApiState>,
+        Path(tag): Path<String>,
+    ) -> Result<Json<Vec<String>>, ApiError> {
+        info!("Refreshing widgets tagged {}", tag);
+        let widget_ids = tagged_widget_ids(&state, tag).await?;
+        if state
+            .event_sender
+            .send_message(ApiAction::RefreshWidgets {
+                widget_ids: widget_ids.clone(),
+            })
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send refresh widgets event".into(),
+            ));
+        }
+        Ok(Json(widget_ids))
+    }
+
+    /// Moves every widget carrying the tag to the trash.
+    #[axum::debug_handler]
+    pub(crate) async fn delete_tag(
+        State(state): State<ApiState>,
+        Path(tag): Path<String>,
+    ) -> Result<Json<Vec<String>>, ApiError> {
+        info!("Moving widgets tagged {} to the trash", tag);
+        let widget_ids = tagged_widget_ids(&state, tag).await?;
+        let deleted = widget_ids.clone();
+        state
+            .db
+            .write(move |db| {
+                for widget_id in &deleted {
+                    db.delete_widget(widget_id, ChangeSource::Api)?;
+                }
+                Ok(())
+            })
+            .await?;
+        if state
+            .event_sender
+            .send_message(ApiAction::DeleteWidgets {
+                widget_ids: widget_ids.clone(),
+            })
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send delete widgets event".into(),
+            ));
+        }
+        Ok(Json(widget_ids))
+    }
+
+    fn send_layouts_changed(state: &ApiState) -> Result<(), ApiError> {
+        if state
+            .event_sender
+            .send_message(ApiAction::LayoutsChanged)
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send layouts changed event".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn list_layouts(
+        State(state): State<ApiState>,
+    ) -> Result<Json<Vec<LayoutPreset>>, ApiError> {
+        let layouts = state.db.read(|db| db.list_layouts()).await?;
+        Ok(Json(layouts))
+    }
+
+    /// Saves the current arrangement of widgets under the requested name.
+    #[axum::debug_handler]
+    pub(crate) async fn save_layout(
+        State(state): State<ApiState>,
+        Json(request): Json<CreateLayoutRequest>,
+    ) -> Result<(StatusCode, Json<LayoutPreset>), ApiError> {
+        info!("Saving layout {}", request.name);
+        let layout = state
+            .db
+            .write(move |db| db.save_layout(&request.name))
+            .await?;
+        send_layouts_changed(&state)?;
+        Ok((StatusCode::CREATED, Json(layout)))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn get_layout(
+        State(state): State<ApiState>,
+        Path(name): Path<String>,
+    ) -> Result<Json<LayoutPreset>, ApiError> {
+        let layout = state.db.read(move |db| db.get_layout(&name)).await?;
+        Ok(Json(layout))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn delete_layout(
+        State(state): State<ApiState>,
+        Path(name): Path<String>,
+    ) -> Result<StatusCode, ApiError> {
+        info!("Deleting layout {}", name);
+        state.db.write(move |db| db.delete_layout(&name)).await?;
+        send_layouts_changed(&state)?;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn apply_layout(
+        State(state): State<ApiState>,
+        Path(name): Path<String>,
+    ) -> Result<Json<LayoutPreset>, ApiError> {
+        info!("Applying layout {}", name);
+        let layout = state.db.read(move |db| db.get_layout(&name)).await?;
+        if state
+            .event_sender
+            .send_message(ApiAction::ApplyLayout(layout.clone()))
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send apply layout event".into(),
+            ));
+        }
+        Ok(Json(layout))
+    }
+
     #[axum::debug_handler]
     pub(crate) async fn get_widget_modifiers(
         State(state): State<ApiState>,
@@ -321,8 +1006,10 @@ pub mod api {
     ) -> Result<Json<Vec<WidgetModifier>>, ApiError> {
         info!("Getting modifiers for widget {}", widget_id);
 
-        let db = state.db.lock().await;
-        let modifiers = db.get_widget_modifier(&widget_id)?;
+        let modifiers = state
+            .db
+            .read(move |db| db.get_widget_modifier(&widget_id))
+            .await?;
         Ok(Json(modifiers))
     }
 
@@ -336,6 +1023,14 @@ pub mod api {
             modifier_id, widget_id
         );
 
+        state
+            .db
+            .write({
+                let (widget_id, modifier_id) = (widget_id.clone(), modifier_id.clone());
+                move |db| db.delete_widget_modifier(&widget_id, &modifier_id, ChangeSource::Api)
+            })
+            .await?;
+
         if state
             .event_sender
             .send_message(ApiAction::DeleteWidgetModifier {
@@ -349,14 +1044,61 @@ pub mod api {
             ));
         }
 
-        let db = state.db.lock().await;
-        db.delete_widget_modifier(&modifier_id)?;
         Ok(StatusCode::NO_CONTENT)
     }
 
+    #[axum::debug_handler]
+    pub(crate) async fn update_widget_modifier(
+        State(state): State<ApiState>,
+        Path((widget_id, modifier_id)): Path<(String, String)>,
+        Json(update): Json<UpdateModifierRequest>,
+    ) -> Result<Json<WidgetModifier>, ApiError> {
+        info!("Updating modifier {} of widget {}", modifier_id, widget_id);
+
+        let modifier = state
+            .db
+            .write(move |db| {
+                db.update_widget_modifier(&widget_id, &modifier_id, &update, ChangeSource::Api)
+            })
+            .await?;
+        Ok(Json(modifier))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn enable_widget_modifier(
+        State(state): State<ApiState>,
+        Path((widget_id, modifier_id)): Path<(String, String)>,
+    ) -> Result<Json<WidgetModifier>, ApiError> {
+        info!("Enabling modifier {} of widget {}", modifier_id, widget_id);
+
+        let modifier = state
+            .db
+            .write(move |db| {
+                db.set_modifier_enabled(&widget_id, &modifier_id, true, ChangeSource::Api)
+            })
+            .await?;
+        Ok(Json(modifier))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn disable_widget_modifier(
+        State(state): State<ApiState>,
+        Path((widget_id, modifier_id)): Path<(String, String)>,
+    ) -> Result<Json<WidgetModifier>, ApiError> {
+        info!("Disabling modifier {} of widget {}", modifier_id, widget_id);
+
+        let modifier = state
+            .db
+            .write(move |db| {
+                db.set_modifier_enabled(&widget_id, &modifier_id, false, ChangeSource::Api)
+            })
+            .await?;
+        Ok(Json(modifier))
+    }
+
     #[derive(Clone)]
     pub(crate) struct ApiState {
-        pub db: Arc<Mutex<crate::db::db::Database>>,
+        pub db: DbService,
         pub event_sender: EventSender,
     }
 
@@ -369,37 +1111,295 @@ pub mod api {
         info!("Adding modifier to widget {}: {:?}", widget_id, modifier);
 
         let widget_modifier = WidgetModifier {
-            id: 0,
-            widget_id: widget_types::NanoId(widget_id),
-            modifier_type: match modifier.modifier_type {
-                Modifier::Scrape {
-                    modifier_id,
-                    selector,
-                } => Modifier::Scrape {
-                    modifier_id,
-                    selector,
-                },
-                Modifier::Refresh {
-                    modifier_id,
-                    interval_sec,
-                } => Modifier::Refresh {
-                    modifier_id,
-                    interval_sec,
+            enabled: modifier.enabled,
+            ..WidgetModifier::new(
+                widget_types::NanoId(widget_id),
+                match modifier.modifier_type {
+                    Modifier::Scrape {
+                        modifier_id,
+                        selector,
+                    } => Modifier::Scrape {
+                        modifier_id,
+                        selector,
+                    },
+                    Modifier::Refresh {
+                        modifier_id,
+                        interval_sec,
+                    } => Modifier::Refresh {
+                        modifier_id,
+                        interval_sec,
+                    },
                 },
-            },
+            )
         };
 
-        let db = state.db.lock().await;
-        db.insert_widget_modifier(widget_modifier.clone())?;
+        let inserted = widget_modifier.clone();
+        state
+            .db
+            .write(move |db| db.insert_widget_modifier(inserted, ChangeSource::Api))
+            .await?;
         Ok((StatusCode::CREATED, Json(widget_modifier)))
     }
 
+    #[axum::debug_handler]
+    pub(crate) async fn get_retention_policy(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+    ) -> Result<Json<RetentionPolicy>, ApiError> {
+        let policy = state
+            .db
+            .read(move |db| db.get_retention_policy(&widget_id))
+            .await?;
+        Ok(Json(policy))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn set_retention_policy(
+        State(state): State<ApiState>,
+        Path(widget_id): Path<String>,
+        Json(policy): Json<RetentionPolicy>,
+    ) -> Result<Json<RetentionPolicy>, ApiError> {
+        info!(
+            "Setting retention policy for widget {}: {:?}",
+            widget_id, policy
+        );
+
+        let policy = state
+            .db
+            .write(move |db| {
+                db.set_retention_policy(&widget_id, &policy)?;
+                Ok(policy)
+            })
+            .await?;
+        Ok(Json(policy))
+    }
+
+    const DEFAULT_SEARCH_LIMIT: usize = 20;
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct SearchQuery {
+        q: String,
+        limit: Option<usize>,
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn search(
+        State(state): State<ApiState>,
+        Query(query): Query<SearchQuery>,
+    ) -> Result<Json<Vec<SearchResult>>, ApiError> {
+        if query.q.trim().is_empty() {
+            return Err(ApiError::InvalidRequest("q must not be empty".into()));
+        }
+
+        let results = state
+            .db
+            .read(move |db| db.search(&query.q, query.limit.unwrap_or(DEFAULT_SEARCH_LIMIT)))
+            .await?;
+        Ok(Json(results))
+    }
+
+    /// Server-sent events, one `data:` line of `LiveEvent` JSON per change.
+    pub(crate) async fn stream_events(
+        State(state): State<ApiState>,
+    ) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+        let events = live_events(state.db).map(|event| Event::default().json_data(event));
+        Sse::new(events).keep_alive(KeepAlive::default())
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn run_query(
+        State(state): State<ApiState>,
+        Json(request): Json<QueryRequest>,
+    ) -> Result<Json<QueryResult>, ApiError> {
+        if request.sql.trim().is_empty() {
+            return Err(ApiError::InvalidRequest("sql must not be empty".into()));
+        }
+
+        let result = state.db.query(request).await?;
+        Ok(Json(result))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn list_profiles(
+        State(state): State<ApiState>,
+    ) -> Result<Json<Vec<ProfileInfo>>, ApiError> {
+        let profiles = paths::list_profiles()?
+            .into_iter()
+            .map(|name| {
+                let active = paths::database_path(&name).ok().as_deref() == state.db.path();
+                ProfileInfo { name, active }
+            })
+            .collect();
+        Ok(Json(profiles))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn create_profile(
+        Json(request): Json<CreateProfileRequest>,
+    ) -> Result<(StatusCode, Json<ProfileInfo>), ApiError> {
+        info!("Creating profile {}", request.name);
+
+        if paths::list_profiles()?.contains(&request.name) {
+            return Err(ApiError::InvalidRequest(format!(
+                "Profile {} already exists",
+                request.name
+            )));
+        }
+        // Opening creates the directory and an empty, migrated database
+        crate::db::db::Database::open_profile(&request.name)?;
+
+        Ok((
+            StatusCode::CREATED,
+            Json(ProfileInfo {
+                name: request.name,
+                active: false,
+            }),
+        ))
+    }
+
+    /// Makes `name` the profile HoverPane starts with and asks the app to switch to it.
+    #[axum::debug_handler]
+    pub(crate) async fn activate_profile(
+        State(state): State<ApiState>,
+        Path(name): Path<String>,
+    ) -> Result<StatusCode, ApiError> {
+        info!("Activating profile {}", name);
+
+        if !paths::list_profiles()?.contains(&name) {
+            return Err(ApiError::Database(DbError::NotFound(format!(
+                "profile {}",
+                name
+            ))));
+        }
+        paths::set_active_profile(&name)?;
+
+        if state
+            .event_sender
+            .send_message(ApiAction::SwitchProfile { profile: name })
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send switch profile event".into(),
+            ));
+        }
+
+        Ok(StatusCode::ACCEPTED)
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn list_backups(
+        State(state): State<ApiState>,
+    ) -> Result<Json<Vec<BackupInfo>>, ApiError> {
+        let backups = state.db.read(|db| db.list_backups()).await?;
+        Ok(Json(backups))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn create_backup(
+        State(state): State<ApiState>,
+    ) -> Result<(StatusCode, Json<BackupInfo>), ApiError> {
+        let backup = state
+            .db
+            .write(|db| db.create_backup(Some("manual")))
+            .await?;
+        Ok((StatusCode::CREATED, Json(backup)))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn restore_backup(
+        State(state): State<ApiState>,
+        Path(name): Path<String>,
+    ) -> Result<StatusCode, ApiError> {
+        info!("Restoring backup {}", name);
+
+        state
+            .db
+            .write(move |db| {
+                db.restore_backup(&name)?;
+                db.add_message(
+                    MessageSeverity::Info,
+                    ChangeSource::Api,
+                    None,
+                    &format!(
+                        "Restored backup {}. Restart HoverPane to reload your widgets.",
+                        name
+                    ),
+                )
+            })
+            .await?;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct ExportQuery {
+        #[serde(default)]
+        history: bool,
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn export_workspace(
+        State(state): State<ApiState>,
+        Query(query): Query<ExportQuery>,
+    ) -> Result<Json<WorkspaceBundle>, ApiError> {
+        let bundle = state
+            .db
+            .read(move |db| db.export_workspace(query.history))
+            .await?;
+        Ok(Json(bundle))
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct ImportQuery {
+        #[serde(default)]
+        dry_run: bool,
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn import_workspace(
+        State(state): State<ApiState>,
+        Query(query): Query<ImportQuery>,
+        Json(bundle): Json<WorkspaceBundle>,
+    ) -> Result<Json<ImportReport>, ApiError> {
+        info!(
+            "Importing {} widgets (dry run: {})",
+            bundle.widgets.len(),
+            query.dry_run
+        );
+
+        let (report, widgets) = state
+            .db
+            .write(move |db| {
+                let report = db.import_workspace(bundle, query.dry_run)?;
+                let mut widgets = vec![];
+                if !report.dry_run {
+                    for widget_id in &report.widgets_created {
+                        widgets.push(db.get_widget_configuration_by_id(widget_id)?);
+                    }
+                }
+                Ok((report, widgets))
+            })
+            .await?;
+
+        for widget in widgets {
+            if state
+                .event_sender
+                .send_message(ApiAction::CreateWidget(widget))
+                .is_err()
+            {
+                return Err(ApiError::EventSender(
+                    "Failed to send create widget event".into(),
+                ));
+            }
+        }
+
+        Ok(Json(report))
+    }
+
     #[axum::debug_handler]
     pub(crate) async fn get_settings(
         State(state): State<ApiState>,
     ) -> Result<Json<AppSettings>, ApiError> {
-        let db = state.db.lock().await;
-        let settings = db.get_settings()?;
+        let settings = state.db.read(|db| db.get_settings()).await?;
         Ok(Json(settings))
     }
 
@@ -408,17 +1408,182 @@ pub mod api {
         State(state): State<ApiState>,
         Json(settings): Json<AppSettings>,
     ) -> Result<Json<AppSettings>, ApiError> {
-        let db = state.db.lock().await;
-        db.set_settings(&settings)?;
+        let settings = state
+            .db
+            .write(move |db| {
+                db.set_settings(&settings)?;
+                Ok(settings)
+            })
+            .await?;
+        if state
+            .event_sender
+            .send_message(ApiAction::SettingsChanged(settings.clone()))
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send settings changed event".into(),
+            ));
+        }
         Ok(Json(settings))
     }
 
+    #[axum::debug_handler]
+    pub(crate) async fn get_setting(
+        State(state): State<ApiState>,
+        Path(key): Path<String>,
+    ) -> Result<Json<SettingEntry>, ApiError> {
+        let entry = state.db.read(move |db| db.get_setting(&key)).await?;
+        Ok(Json(entry))
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct SettingQuery {
+        /// The version the change is based on, the write is refused when it is outdated.
+        version: Option<u32>,
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn set_setting(
+        State(state): State<ApiState>,
+        Path(key): Path<String>,
+        Query(query): Query<SettingQuery>,
+        Json(value): Json<serde_json::Value>,
+    ) -> Result<Json<SettingEntry>, ApiError> {
+        let (entry, settings) = state
+            .db
+            .write(move |db| {
+                let entry = db.set_setting(&key, value, query.version)?;
+                Ok((entry, db.get_settings()?))
+            })
+            .await?;
+        if state
+            .event_sender
+            .send_message(ApiAction::SettingsChanged(settings))
+            .is_err()
+        {
+            return Err(ApiError::EventSender(
+                "Failed to send settings changed event".into(),
+            ));
+        }
+        Ok(Json(entry))
+    }
+
     #[axum::debug_handler]
     pub(crate) async fn get_app_ui_state(
         State(state): State<ApiState>,
     ) -> Result<Json<AppUiState>, ApiError> {
-        let db = state.db.lock().await;
-        let app_ui_state = db.get_app_ui_state()?;
+        let app_ui_state = state.db.read(|db| db.get_app_ui_state()).await?;
         Ok(Json(app_ui_state))
     }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct MessagesQuery {
+        severity: Option<MessageSeverity>,
+        widget_id: Option<String>,
+        acknowledged: Option<bool>,
+        /// Epoch milliseconds
+        since: Option<i64>,
+        limit: Option<usize>,
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn get_messages(
+        State(state): State<ApiState>,
+        Query(query): Query<MessagesQuery>,
+    ) -> Result<Json<Vec<AppMessage>>, ApiError> {
+        if query.limit == Some(0) || query.limit > Some(MAX_MESSAGES) {
+            return Err(ApiError::InvalidRequest(format!(
+                "limit must be between 1 and {}",
+                MAX_MESSAGES
+            )));
+        }
+        let filter = MessageFilter {
+            severity: query.severity,
+            widget_id: query.widget_id,
+            acknowledged: query.acknowledged,
+            since: query.since,
+            limit: query.limit,
+        };
+        let messages = state.db.read(move |db| db.get_messages(&filter)).await?;
+        Ok(Json(messages))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn acknowledge_message(
+        State(state): State<ApiState>,
+        Path(id): Path<u32>,
+    ) -> Result<Json<AppMessage>, ApiError> {
+        let message = state.db.write(move |db| db.acknowledge_message(id)).await?;
+        Ok(Json(message))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn list_webhooks(
+        State(state): State<ApiState>,
+    ) -> Result<Json<Vec<Webhook>>, ApiError> {
+        let webhooks = state.db.read(|db| db.list_webhooks()).await?;
+        Ok(Json(webhooks))
+    }
+
+    /// The response is the only place the secret shows up, listing and getting webhooks leave
+    /// it out.
+    #[axum::debug_handler]
+    pub(crate) async fn create_webhook(
+        State(state): State<ApiState>,
+        Json(request): Json<CreateWebhookRequest>,
+    ) -> Result<(StatusCode, Json<CreatedWebhook>), ApiError> {
+        info!("Adding webhook for {}", request.url);
+        let webhook = state
+            .db
+            .write(move |db| db.create_webhook(&request))
+            .await?;
+        Ok((StatusCode::CREATED, Json(webhook)))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn get_webhook(
+        State(state): State<ApiState>,
+        Path(id): Path<u32>,
+    ) -> Result<Json<Webhook>, ApiError> {
+        let webhook = state.db.read(move |db| db.get_webhook(id)).await?;
+        Ok(Json(webhook))
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn delete_webhook(
+        State(state): State<ApiState>,
+        Path(id): Path<u32>,
+    ) -> Result<StatusCode, ApiError> {
+        state.db.write(move |db| db.delete_webhook(id)).await?;
+        Ok(StatusCode::NO_CONTENT)
+    }
+
+    #[derive(Debug, Deserialize)]
+    pub(crate) struct DeliveriesQuery {
+        status: Option<DeliveryStatus>,
+        limit: Option<usize>,
+    }
+
+    #[axum::debug_handler]
+    pub(crate) async fn get_webhook_deliveries(
+        State(state): State<ApiState>,
+        Path(id): Path<u32>,
+        Query(query): Query<DeliveriesQuery>,
+    ) -> Result<Json<Vec<WebhookDelivery>>, ApiError> {
+        if query.limit == Some(0) || query.limit > Some(MAX_DELIVERIES) {
+            return Err(ApiError::InvalidRequest(format!(
+                "limit must be between 1 and {}",
+                MAX_DELIVERIES
+            )));
+        }
+        let filter = DeliveryFilter {
+            status: query.status,
+            limit: query.limit,
+        };
+        let deliveries = state
+            .db
+            .read(move |db| db.get_webhook_deliveries(id, &filter))
+            .await?;
+        Ok(Json(deliveries))
+    }
 }
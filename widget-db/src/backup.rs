@@ -0,0 +1,307 @@
+pub mod backup {
+    use crate::{
+        db::db::Database,
+        error::error::{DbError, DbResult},
+        service::service::DbService,
+    };
+
+    use log::{error, info, warn};
+    use rusqlite::DatabaseName;
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        time::Duration,
+    };
+    use widget_types::{BackupInfo, DbChange};
+
+    pub const BACKUP_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+    /// Older unlabelled snapshots are deleted once there are more than this many.
+    pub const MAX_BACKUPS: usize = 10;
+    /// How many snapshots are kept per label, like the ones taken before a reset or restore.
+    /// They rotate separately so scheduled snapshots can't push them out.
+    pub const MAX_LABELLED_BACKUPS: usize = 3;
+
+    const BACKUP_DIR: &str = "backups";
+    const BACKUP_EXTENSION: &str = "db";
+    const BACKUP_TIMESTAMP: &str = "%Y%m%d-%H%M%S";
+
+    /// What a snapshot's file name, `<timestamp>[-<label>][-<attempt>].db`, says about it.
+    struct BackupName {
+        taken_at: jiff::civil::DateTime,
+        attempt: u32,
+        /// Empty for scheduled snapshots
+        label: String,
+    }
+
+    /// `None` for files in the backup folder that weren't written by `write_backup`.
+    fn parse_backup_name(path: &Path) -> Option<BackupName> {
+        let stem = path.file_stem()?.to_str()?;
+        let taken_at = jiff::civil::DateTime::strptime(BACKUP_TIMESTAMP, stem.get(..15)?).ok()?;
+        let rest = match &stem[15..] {
+            "" => "",
+            rest => rest.strip_prefix('-')?,
+        };
+        let (label, attempt) = match rest.rsplit_once('-') {
+            Some((label, attempt)) if attempt.parse::<u32>().is_ok() => (label, attempt),
+            None if rest.parse::<u32>().is_ok() => ("", rest),
+            _ => (rest, "1"),
+        };
+        Some(BackupName {
+            taken_at,
+            attempt: attempt.parse().ok()?,
+            label: label.to_string(),
+        })
+    }
+
+    fn backup_info(path: &Path) -> DbResult<BackupInfo> {
+        let metadata = std::fs::metadata(path)?;
+        let created_at = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| jiff::Timestamp::try_from(modified).ok())
+            .map(|modified| modified.to_string())
+            .unwrap_or_default();
+        Ok(BackupInfo {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default(),
+            created_at,
+            size_kb: (metadata.len() / 1024).try_into().unwrap_or(u32::MAX),
+        })
+    }
+
+    impl Database {
+        fn backup_dir(&self) -> DbResult<PathBuf> {
+            let path = self.path.as_ref().ok_or_else(|| {
+                DbError::InvalidInput("in-memory databases have no backups".into())
+            })?;
+            Ok(path
+                .parent()
+                .map(|parent| parent.join(BACKUP_DIR))
+                .unwrap_or_else(|| PathBuf::from(BACKUP_DIR)))
+        }
+
+        /// Every snapshot of this database, newest first by the time in their names. Files that
+        /// aren't named like snapshots come last.
+        fn backup_paths(&self) -> DbResult<Vec<PathBuf>> {
+            let dir = self.backup_dir()?;
+            if !dir.exists() {
+                return Ok(vec![]);
+            }
+
+            let mut paths = std::fs::read_dir(dir)?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| {
+                    path.extension()
+                        .is_some_and(|extension| extension == BACKUP_EXTENSION)
+                })
+                .collect::<Vec<_>>();
+            paths.sort_by_cached_key(|path| {
+                parse_backup_name(path).map(|name| (name.taken_at, name.attempt))
+            });
+            paths.reverse();
+            Ok(paths)
+        }
+
+        pub fn list_backups(&self) -> DbResult<Vec<BackupInfo>> {
+            self.backup_paths()?
+                .iter()
+                .map(|path| backup_info(path))
+                .collect()
+        }
+
+        /// Copies the live database into the backup folder with SQLite's online backup API, then
+        /// prunes the oldest snapshots beyond `MAX_BACKUPS`, or `MAX_LABELLED_BACKUPS` for the
+        /// ones with the same `label`. `label` ends up in the file name.
+        pub fn create_backup(&self, label: Option<&str>) -> DbResult<BackupInfo> {
+            let path = self.write_backup(label)?;
+            self.prune_backups()?;
+            backup_info(&path)
+        }
+
+        fn write_backup(&self, label: Option<&str>) -> DbResult<PathBuf> {
+            let dir = self.backup_dir()?;
+            std::fs::create_dir_all(&dir)?;
+
+            // Attempts count up across labels so snapshots from the same second keep their order
+            let stem = jiff::Zoned::now().strftime(BACKUP_TIMESTAMP).to_string();
+            let attempt = self
+                .backup_paths()?
+                .iter()
+                .filter_map(|path| parse_backup_name(path))
+                .filter(|name| name.taken_at.strftime(BACKUP_TIMESTAMP).to_string() == stem)
+                .map(|name| name.attempt + 1)
+                .max()
+                .unwrap_or(1);
+            let stem = [
+                Some(stem),
+                label.map(str::to_string),
+                (attempt > 1).then(|| attempt.to_string()),
+            ]
+            .into_iter()
+            .flatten()
+            .collect::<Vec<_>>()
+            .join("-");
+            let path = dir.join(format!("{}.{}", stem, BACKUP_EXTENSION));
+
+            // Write next to the final name first so a crash never leaves a half written backup
+            // that looks complete
+            let partial = path.with_extension("partial");
+            self.conn.backup(DatabaseName::Main, &partial, None)?;
+            std::fs::rename(&partial, &path)?;
+            info!("Backed up database to {:?}", path);
+
+            Ok(path)
+        }
+
+        fn prune_backups(&self) -> DbResult<()> {
+            // Paths come newest first, so each label keeps its first few
+            let mut kept = HashMap::<String, usize>::new();
+            for path in self.backup_paths()? {
+                let Some(name) = parse_backup_name(&path) else {
+                    continue;
+                };
+                let limit = if name.label.is_empty() {
+                    MAX_BACKUPS
+                } else {
+                    MAX_LABELLED_BACKUPS
+                };
+                let count = kept.entry(name.label).or_default();
+                if *count < limit {
+                    *count += 1;
+                } else if let Err(e) = std::fs::remove_file(&path) {
+                    warn!("Failed to remove old backup {:?}: {}", path, e);
+                }
+            }
+            Ok(())
+        }
+
+        /// Replaces the live database with the snapshot called `name`, after taking a backup
+        /// of the current state. Older snapshots are migrated to the current schema.
+        pub fn restore_backup(&mut self, name: &str) -> DbResult<()> {
+            let path = self
+                .backup_paths()?
+                .into_iter()
+                .find(|path| path.file_name().is_some_and(|file_name| file_name == name))
+                .ok_or_else(|| DbError::NotFound(format!("backup {}", name)))?;
+
+            // Pruned only once restored, the snapshot being restored may be one that rotates out
+            self.write_backup(Some("pre-restore"))?;
+            self.conn.restore(
+                DatabaseName::Main,
+                &path,
+                None::<fn(rusqlite::backup::Progress)>,
+            )?;
+            self.migrate()?;
+            info!("Restored database from {:?}", path);
+            self.prune_backups()?;
+            self.publish(DbChange::Reloaded);
+            Ok(())
+        }
+    }
+
+    /// Periodically snapshots the database for as long as the API is running. The first
+    /// snapshot is taken one `interval` after start, so relaunching doesn't rotate any out.
+    pub async fn run_backups(db: DbService, interval: Duration) {
+        if db.path().is_none() {
+            return;
+        }
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        loop {
+            ticker.tick().await;
+            if let Err(e) = db.write(|db| db.create_backup(None)).await {
+                error!("Backup failed: {}", e);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::fixtures::TempDb;
+        use widget_types::{ChangeSource, NanoId, WidgetConfiguration};
+
+        #[test]
+        fn test_backups_rotate() {
+            let temp = TempDb::new();
+            let db = temp.open();
+            db.create_backup(Some("pre-reset")).unwrap();
+            let first = db.create_backup(None).unwrap();
+            let first_manual = db.create_backup(Some("manual")).unwrap();
+            for _ in 0..MAX_BACKUPS + 1 {
+                db.create_backup(None).unwrap();
+                db.create_backup(Some("manual")).unwrap();
+            }
+
+            // Each label rotates on its own, oldest first even past `-9`
+            let names = db
+                .list_backups()
+                .unwrap()
+                .into_iter()
+                .map(|b| b.name)
+                .collect::<Vec<_>>();
+            assert_eq!(names.len(), MAX_BACKUPS + MAX_LABELLED_BACKUPS + 1);
+            assert!(names.iter().any(|name| name.contains("-pre-reset")));
+            assert!(!names.contains(&first.name));
+            assert!(!names.contains(&first_manual.name));
+            let parsed = names
+                .iter()
+                .filter_map(|name| parse_backup_name(Path::new(name)))
+                .collect::<Vec<_>>();
+            let label_count =
+                |label: &str| parsed.iter().filter(|name| name.label == label).count();
+            assert_eq!(label_count(""), MAX_BACKUPS);
+            assert_eq!(label_count("manual"), MAX_LABELLED_BACKUPS);
+            let order = parsed
+                .iter()
+                .map(|name| (name.taken_at, name.attempt))
+                .collect::<Vec<_>>();
+            assert!(order.windows(2).all(|pair| pair[0] > pair[1]));
+        }
+
+        #[test]
+        fn test_parse_backup_name() {
+            let parse = |name: &str| {
+                parse_backup_name(Path::new(name)).map(|name| (name.attempt, name.label))
+            };
+            assert_eq!(parse("20261018-090000.db"), Some((1, "".to_string())));
+            assert_eq!(parse("20261018-090000-12.db"), Some((12, "".to_string())));
+            assert_eq!(
+                parse("20261018-090000-pre-reset.db"),
+                Some((1, "pre-reset".to_string()))
+            );
+            assert_eq!(
+                parse("20261018-090000-pre-reset-3.db"),
+                Some((3, "pre-reset".to_string()))
+            );
+            assert_eq!(parse("widgets.db"), None);
+        }
+
+        #[test]
+        fn test_reset_can_be_undone() {
+            let temp = TempDb::new();
+            let mut db = temp.open();
+            db.insert_widget_configuration(
+                vec![WidgetConfiguration::new().with_widget_id(NanoId("w".to_string()))],
+                ChangeSource::Api,
+            )
+            .unwrap();
+
+            db.reset().unwrap();
+            assert!(db.get_configuration().unwrap().is_empty());
+
+            let backups = db.list_backups().unwrap();
+            assert!(backups[0].name.ends_with("pre-reset.db"));
+            db.restore_backup(&backups[0].name).unwrap();
+            assert_eq!(db.get_configuration().unwrap().len(), 1);
+            assert!(db.list_backups().unwrap()[0].name.contains("-pre-restore"));
+
+            assert!(matches!(
+                db.restore_backup("../widgets.db"),
+                Err(DbError::NotFound(_))
+            ));
+        }
+    }
+}
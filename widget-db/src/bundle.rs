@@ -0,0 +1,368 @@
+pub mod bundle {
+    use crate::{
+        db::db::{
+            insert_modifier_row, insert_scraped_data, insert_widget, validate_modifier, Database,
+        },
+        error::error::{DbError, DbResult},
+        revisions::revisions::record_revision,
+        settings::settings::write_setting,
+    };
+
+    use nanoid::nanoid_gen;
+    use rusqlite::{Connection, OptionalExtension};
+    use std::collections::HashMap;
+    use widget_types::{
+        BundledSettings, BundledWidget, ChangeSource, DbChange, ImportReport, NanoId, RemappedId,
+        WidgetModifier, WorkspaceBundle, CONTROLS_WIDGET_ID, WORKSPACE_BUNDLE_VERSION,
+    };
+
+    fn widget_id_taken(conn: &Connection, widget_id: &str) -> DbResult<bool> {
+        let taken = conn
+            .query_row(
+                "SELECT 1 FROM widgets WHERE widget_id = ?",
+                [widget_id],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(taken.is_some())
+    }
+
+    fn modifier_id_taken(conn: &Connection, modifier_id: &str) -> DbResult<bool> {
+        let taken = conn
+            .query_row(
+                "SELECT 1 FROM modifiers WHERE modifier_id = ?",
+                [modifier_id],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(taken.is_some())
+    }
+
+    /// Why each modifier of `widgets` that wouldn't pass `validate_modifier` is invalid.
+    fn invalid_modifiers(widgets: &[BundledWidget]) -> Vec<String> {
+        widgets
+            .iter()
+            .filter(|bundled| bundled.widget.widget_id.0 != CONTROLS_WIDGET_ID)
+            .flat_map(|bundled| {
+                bundled.modifiers.iter().filter_map(move |modifier| {
+                    let reason = match validate_modifier(modifier) {
+                        Ok(()) => return None,
+                        Err(DbError::InvalidInput(reason)) => reason,
+                        Err(e) => e.to_string(),
+                    };
+                    Some(format!(
+                        "modifier {} of widget {}: {}",
+                        modifier.modifier_id().0,
+                        bundled.widget.widget_id.0,
+                        reason
+                    ))
+                })
+            })
+            .collect()
+    }
+
+    impl Database {
+        pub fn export_workspace(&self, include_history: bool) -> DbResult<WorkspaceBundle> {
+            let mut modifiers = HashMap::<NanoId, (Vec<_>, Vec<_>)>::new();
+            for modifier in self.get_all_widget_modifiers()? {
+                let (all, disabled) = modifiers.entry(modifier.widget_id).or_default();
+                if !modifier.enabled {
+                    disabled.push(modifier.modifier_type.modifier_id().0.clone());
+                }
+                all.push(modifier.modifier_type);
+            }
+
+            let widgets = self
+                .get_configuration()?
+                .into_iter()
+                .filter(|widget| widget.widget_id.0 != CONTROLS_WIDGET_ID)
+                .map(|widget| {
+                    let (modifiers, disabled_modifiers) =
+                        modifiers.remove(&widget.widget_id).unwrap_or_default();
+                    BundledWidget {
+                        widget,
+                        modifiers,
+                        disabled_modifiers,
+                    }
+                })
+                .collect();
+
+            let settings = Some(BundledSettings {
+                show_tray_icon: self.get_settings()?.show_tray_icon,
+            });
+
+            Ok(WorkspaceBundle {
+                version: WORKSPACE_BUNDLE_VERSION,
+                exported_at: jiff::Timestamp::now().to_string(),
+                widgets,
+                settings,
+                history: if include_history {
+                    self.get_data()?
+                } else {
+                    vec![]
+                },
+            })
+        }
+
+        /// Adds everything in `bundle` next to the existing widgets. Widget and modifier ids that
+        /// are already in use get a fresh id, history follows its widget. With `dry_run` the
+        /// import runs in a transaction that is rolled back, so the report is exactly what a
+        /// real import would do. The exception is invalid modifiers: a real import refuses the
+        /// whole bundle, a dry run skips them and lists them in the report.
+        pub fn import_workspace(
+            &mut self,
+            bundle: WorkspaceBundle,
+            dry_run: bool,
+        ) -> DbResult<ImportReport> {
+            if bundle.version == 0 || bundle.version > WORKSPACE_BUNDLE_VERSION {
+                return Err(DbError::InvalidInput(format!(
+                    "unsupported bundle version {}, expected at most {}",
+                    bundle.version, WORKSPACE_BUNDLE_VERSION
+                )));
+            }
+
+            let invalid_modifiers = invalid_modifiers(&bundle.widgets);
+            if !dry_run && !invalid_modifiers.is_empty() {
+                return Err(DbError::InvalidInput(format!(
+                    "the bundle has invalid modifiers: {}",
+                    invalid_modifiers.join("; ")
+                )));
+            }
+
+            let current_settings = self.get_settings()?;
+
+            let mut report = ImportReport {
+                dry_run,
+                invalid_modifiers,
+                ..Default::default()
+            };
+            let tx = self.conn.transaction()?;
+
+            let mut widget_ids = HashMap::new();
+            for BundledWidget {
+                mut widget,
+                modifiers,
+                disabled_modifiers,
+            } in bundle.widgets
+            {
+                if widget.widget_id.0 == CONTROLS_WIDGET_ID {
+                    continue;
+                }
+                let original_id = widget.widget_id.0.clone();
+                if widget_id_taken(&tx, &original_id)? {
+                    widget.widget_id = NanoId(nanoid_gen(8));
+                    report.remapped_ids.push(RemappedId {
+                        from: original_id.clone(),
+                        to: widget.widget_id.0.clone(),
+                    });
+                }
+                insert_widget(&tx, &widget)?;
+                widget_ids.insert(original_id, widget.widget_id.0.clone());
+                report.widgets_created.push(widget.widget_id.0.clone());
+
+                for mut modifier in modifiers {
+                    // Only a dry run gets here with invalid modifiers, they are in the report
+                    if validate_modifier(&modifier).is_err() {
+                        continue;
+                    }
+                    let original_id = modifier.modifier_id().0.clone();
+                    let enabled = !disabled_modifiers.contains(&original_id);
+                    if modifier_id_taken(&tx, &original_id)? {
+                        modifier = modifier.with_modifier_id(NanoId(nanoid_gen(8)));
+                        report.remapped_ids.push(RemappedId {
+                            from: original_id,
+                            to: modifier.modifier_id().0.clone(),
+                        });
+                    }
+                    insert_modifier_row(
+                        &tx,
+                        &WidgetModifier {
+                            enabled,
+                            ..WidgetModifier::new(widget.widget_id.clone(), modifier)
+                        },
+                    )?;
+                    report.modifiers_created += 1;
+                }
+                record_revision(&tx, &widget.widget_id.0, ChangeSource::Import)?;
+            }
+
+            for mut data in bundle.history {
+                // History of widgets that aren't part of the bundle has nowhere to go
+                let Some(widget_id) = widget_ids.get(&data.widget_id) else {
+                    continue;
+                };
+                data.widget_id = widget_id.clone();
+                insert_scraped_data(&tx, data)?;
+                report.history_rows += 1;
+            }
+
+            if let Some(settings) = bundle.settings {
+                if settings.show_tray_icon != current_settings.show_tray_icon {
+                    write_setting(
+                        &tx,
+                        "show_tray_icon",
+                        &serde_json::Value::Bool(settings.show_tray_icon),
+                    )?;
+                    report.settings_updated = true;
+                }
+            }
+
+            if dry_run {
+                tx.rollback()?;
+                return Ok(report);
+            }
+            tx.commit()?;
+            for widget_id in &report.widgets_created {
+                self.publish(DbChange::WidgetSaved {
+                    widget_id: widget_id.clone(),
+                });
+                self.publish(DbChange::ModifiersChanged {
+                    widget_id: widget_id.clone(),
+                });
+            }
+            if report.settings_updated {
+                self.publish(DbChange::SettingsChanged);
+            }
+            Ok(report)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::{default_app_settings, fixtures::scraped};
+        use widget_types::{Modifier, WidgetConfiguration};
+
+        fn workspace() -> Database {
+            let mut db = Database::from(true).unwrap();
+            db.set_settings(&widget_types::AppSettings {
+                email: "me@example.com".to_string(),
+                licence_key: "secret".to_string(),
+                ..default_app_settings()
+            })
+            .unwrap();
+            db.insert_widget_configuration(
+                vec![
+                    WidgetConfiguration::new()
+                        .with_widget_id(NanoId("w".to_string()))
+                        .with_title("Price".to_string()),
+                    WidgetConfiguration::new()
+                        .with_widget_id(NanoId(CONTROLS_WIDGET_ID.to_string())),
+                ],
+                ChangeSource::Api,
+            )
+            .unwrap();
+            db.insert_widget_modifier(
+                WidgetModifier::new(
+                    NanoId("w".to_string()),
+                    Modifier::Refresh {
+                        modifier_id: NanoId("m".to_string()),
+                        interval_sec: 60,
+                    },
+                ),
+                ChangeSource::Api,
+            )
+            .unwrap();
+            db.insert_data(scraped("w", "$10", 1_700_000_000_000))
+                .unwrap();
+            db
+        }
+
+        #[test]
+        fn test_export_leaves_out_secrets() {
+            let bundle = workspace().export_workspace(false).unwrap();
+            assert_eq!(bundle.version, WORKSPACE_BUNDLE_VERSION);
+            assert_eq!(bundle.widgets.len(), 1);
+            assert_eq!(bundle.widgets[0].modifiers.len(), 1);
+            assert!(bundle.history.is_empty());
+
+            let json = serde_json::to_string(&bundle).unwrap();
+            assert!(!json.contains("secret"));
+            assert!(!json.contains("me@example.com"));
+        }
+
+        #[test]
+        fn test_import_into_empty_database() {
+            let mut source = workspace();
+            source
+                .set_modifier_enabled("w", "m", false, ChangeSource::Api)
+                .unwrap();
+            let bundle = source.export_workspace(true).unwrap();
+            assert_eq!(bundle.widgets[0].disabled_modifiers, vec!["m".to_string()]);
+            let mut db = Database::from(true).unwrap();
+
+            let report = db.import_workspace(bundle, false).unwrap();
+            assert_eq!(report.widgets_created, vec!["w".to_string()]);
+            assert!(report.remapped_ids.is_empty());
+            assert_eq!(report.modifiers_created, 1);
+            assert_eq!(report.history_rows, 1);
+
+            assert_eq!(db.get_configuration().unwrap()[0].title, "Price");
+            assert_eq!(db.get_data().unwrap()[0].numeric_value, Some(10.0));
+            assert!(!db.get_widget_modifier("w").unwrap()[0].enabled);
+        }
+
+        #[test]
+        fn test_import_remaps_conflicting_ids() {
+            let mut db = workspace();
+            let bundle = db.export_workspace(true).unwrap();
+
+            let report = db.import_workspace(bundle, false).unwrap();
+            assert_eq!(report.remapped_ids.len(), 2);
+            let new_id = &report.remapped_ids[0].to;
+            assert_eq!(report.widgets_created, vec![new_id.clone()]);
+
+            assert_eq!(db.get_configuration().unwrap().len(), 3);
+            assert_eq!(db.get_widget_modifier(new_id).unwrap().len(), 1);
+            assert_eq!(
+                db.get_data()
+                    .unwrap()
+                    .iter()
+                    .filter(|d| &d.widget_id == new_id)
+                    .count(),
+                1
+            );
+        }
+
+        #[test]
+        fn test_dry_run_changes_nothing() {
+            let mut db = workspace();
+            let mut bundle = db.export_workspace(false).unwrap();
+            bundle.settings = Some(BundledSettings {
+                show_tray_icon: false,
+            });
+
+            let report = db.import_workspace(bundle.clone(), true).unwrap();
+            assert!(report.dry_run);
+            assert_eq!(report.widgets_created.len(), 1);
+            assert!(report.settings_updated);
+            assert_eq!(db.get_configuration().unwrap().len(), 2);
+            assert!(db.get_settings().unwrap().show_tray_icon);
+
+            // Invalid modifiers are listed by a dry run and refuse a real import
+            bundle.widgets[0].modifiers.push(Modifier::Scrape {
+                modifier_id: NanoId("bad".to_string()),
+                selector: "div[".to_string(),
+            });
+            bundle.widgets[0].modifiers.push(Modifier::Refresh {
+                modifier_id: NanoId("never".to_string()),
+                interval_sec: 0,
+            });
+            let report = db.import_workspace(bundle.clone(), true).unwrap();
+            assert_eq!(report.modifiers_created, 1);
+            assert_eq!(report.invalid_modifiers.len(), 2);
+            assert!(report.invalid_modifiers[0].starts_with("modifier bad of widget w:"));
+            assert!(matches!(
+                db.import_workspace(bundle.clone(), false),
+                Err(DbError::InvalidInput(_))
+            ));
+            assert_eq!(db.get_configuration().unwrap().len(), 2);
+
+            bundle.version = WORKSPACE_BUNDLE_VERSION + 1;
+            assert!(matches!(
+                db.import_workspace(bundle, true),
+                Err(DbError::InvalidInput(_))
+            ));
+        }
+    }
+}
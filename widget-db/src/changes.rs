@@ -0,0 +1,127 @@
+pub mod changes {
+    use crate::db::db::Database;
+
+    use std::{
+        collections::HashMap,
+        path::{Path, PathBuf},
+        sync::{Mutex, OnceLock},
+    };
+    use tokio::sync::broadcast;
+    use widget_types::DbChange;
+
+    /// Subscribers that fall further behind than this miss changes and get
+    /// `RecvError::Lagged` instead, after which they should read everything again.
+    pub const CHANGE_CHANNEL_CAPACITY: usize = 256;
+
+    /// One channel per database file, so every connection to it in this process shares it.
+    static CHANNELS: OnceLock<Mutex<HashMap<PathBuf, broadcast::Sender<DbChange>>>> =
+        OnceLock::new();
+
+    /// The channel for the database at `path`. In-memory databases can't be shared between
+    /// connections and get a channel of their own.
+    pub(crate) fn channel_for(path: Option<&Path>) -> broadcast::Sender<DbChange> {
+        let Some(path) = path else {
+            return broadcast::channel(CHANGE_CHANNEL_CAPACITY).0;
+        };
+        let mut channels = CHANNELS
+            .get_or_init(Default::default)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        channels
+            .entry(path.to_path_buf())
+            .or_insert_with(|| broadcast::channel(CHANGE_CHANNEL_CAPACITY).0)
+            .clone()
+    }
+
+    impl Database {
+        /// Changes committed from now on by any connection to this database.
+        pub fn subscribe(&self) -> broadcast::Receiver<DbChange> {
+            self.changes.subscribe()
+        }
+
+        /// Tells subscribers about a committed change. Call it only after the transaction
+        /// making the change is committed, so they can read it right away.
+        pub(crate) fn publish(&self, change: DbChange) {
+            // Having nobody listening is fine
+            let _ = self.changes.send(change);
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::fixtures::TempDb;
+        use tokio::sync::broadcast::error::TryRecvError;
+        use widget_types::{ChangeSource, NanoId, WidgetConfiguration, WidgetModifier};
+
+        fn drain(changes: &mut broadcast::Receiver<DbChange>) -> Vec<DbChange> {
+            let mut received = vec![];
+            loop {
+                match changes.try_recv() {
+                    Ok(change) => received.push(change),
+                    Err(TryRecvError::Empty) => return received,
+                    Err(e) => panic!("{:?}", e),
+                }
+            }
+        }
+
+        #[test]
+        fn test_changes_are_published() {
+            let mut db = Database::from(true).unwrap();
+            let mut changes = db.subscribe();
+            let widget_id = NanoId("w".to_string());
+
+            db.insert_widget_configuration(
+                vec![WidgetConfiguration::new().with_widget_id(widget_id.clone())],
+                ChangeSource::Api,
+            )
+            .unwrap();
+            db.insert_widget_modifier(
+                WidgetModifier::new(
+                    widget_id.clone(),
+                    widget_types::Modifier::Refresh {
+                        modifier_id: NanoId("m".to_string()),
+                        interval_sec: 30,
+                    },
+                ),
+                ChangeSource::Api,
+            )
+            .unwrap();
+            db.delete_widget("w", ChangeSource::Api).unwrap();
+            db.purge_widget("w").unwrap();
+
+            let w = || "w".to_string();
+            assert_eq!(
+                drain(&mut changes),
+                vec![
+                    DbChange::WidgetCreated { widget_id: w() },
+                    DbChange::ModifiersChanged { widget_id: w() },
+                    DbChange::WidgetDeleted { widget_id: w() },
+                    DbChange::WidgetPurged { widget_id: w() },
+                ]
+            );
+
+            // Nothing is published for a rolled back import
+            let mut bundle = db.export_workspace(false).unwrap();
+            bundle.widgets = vec![];
+            db.import_workspace(bundle, true).unwrap();
+            assert!(drain(&mut changes).is_empty());
+        }
+
+        #[test]
+        fn test_connections_to_one_file_share_changes() {
+            let temp = TempDb::new();
+            let writer = temp.open();
+            let reader = temp.open();
+            let mut changes = reader.subscribe();
+            let in_memory = Database::from(true).unwrap();
+            let mut unrelated = in_memory.subscribe();
+
+            writer
+                .set_setting("show_tray_icon", false.into(), None)
+                .unwrap();
+            assert_eq!(drain(&mut changes), vec![DbChange::SettingsChanged]);
+            assert!(drain(&mut unrelated).is_empty());
+        }
+    }
+}
@@ -1,28 +1,31 @@
 pub mod db {
-    use crate::{api::api::delete_widget, db_impl::db_impl::DbTable};
+    use crate::{
+        api::api::delete_widget,
+        changes::changes::channel_for,
+        db_impl::db_impl::DbTable,
+        error::error::{DbError, DbResult},
+        parse::parse::parse_value,
+        paths::paths,
+        recovery::recovery,
+        revisions::revisions::record_revision,
+    };
 
-    use directories::ProjectDirs;
     use log::{debug, error, info};
     // use nanoid::NanoId;
-    use rusqlite::{types::FromSql, Connection, Result as SqliteResult, ToSql};
+    use rusqlite::{
+        types::FromSql, Connection, OpenFlags, OptionalExtension, Result as SqliteResult, ToSql,
+    };
     use rusqlite_migration::{Migrations, M};
-    use serde::{Deserialize, Serialize};
-    use std::fs;
-    use std::path::PathBuf;
+    use serde::{de::DeserializeOwned, Deserialize, Serialize};
+    use std::path::{Path, PathBuf};
+    use tokio::sync::broadcast;
     use widget_types::{
-        AppSettings, AppUiState, ConfigInformation, Level, LicenceTier, MonitorPosition, NanoId,
-        ScrapedData, WidgetBounds, WidgetConfiguration, WidgetModifier, DEFAULT_WIDGET_HEIGHT,
-        DEFAULT_WIDGET_WIDTH,
+        AppSettings, ChangeSource, ConfigInformation, DbChange, Level, LicenceTier,
+        MessageSeverity, Modifier, MonitorPosition, NanoId, ScrapedData, UpdateModifierRequest,
+        UpdateWidgetRequest, WidgetBounds, WidgetConfiguration, WidgetModifier, WidgetType,
+        DEFAULT_TRASH_RETENTION_DAYS, DEFAULT_WIDGET_HEIGHT, DEFAULT_WIDGET_WIDTH,
     };
 
-    fn get_db_path() -> PathBuf {
-        let proj_dirs = ProjectDirs::from("com", "hoverpane", "hoverpane")
-            .expect("Failed to get project directories");
-        let data_dir = proj_dirs.data_dir();
-        fs::create_dir_all(data_dir).expect("Failed to create data directory");
-        data_dir.join("widgets.db")
-    }
-
     impl DbTable for WidgetConfiguration {
         // fn get_create_table_sql() -> &'static str {
         //     r#"CREATE TABLE IF NOT EXISTS widgets (
@@ -55,7 +58,7 @@ pub mod db {
         // }
 
         fn get_insert_sql() -> &'static str {
-            "INSERT INTO modifiers (widget_id, modifier_type) VALUES (?, ?)"
+            "INSERT INTO modifiers (widget_id, modifier_type, modifier_id, enabled) VALUES (?, ?, ?, ?)"
         }
     }
 
@@ -73,440 +76,845 @@ pub mod db {
         // }
 
         fn get_insert_sql() -> &'static str {
-            "INSERT INTO scraped_data (widget_id, value, error, timestamp) VALUES (?, ?, ?, ?)"
+            "INSERT INTO scraped_data (widget_id, value, error, timestamp, numeric_value, unit) VALUES (?, ?, ?, ?, ?, ?)"
+        }
+    }
+
+    /// Every schema change, in the order it has to be applied. Only ever append to this list:
+    /// `rusqlite_migration` tracks progress through `PRAGMA user_version`, so reordering or
+    /// editing an entry would skip or replay migrations on existing databases.
+    fn migrations() -> Migrations<'static> {
+        Migrations::new(vec![
+            M::up(include_str!("../migrations/20240318000000_initial.sql")),
+            M::up(include_str!(
+                "../migrations/20261018090000_widget_bounds.sql"
+            )),
+            M::up(include_str!(
+                "../migrations/20261018090100_modifier_ids.sql"
+            )),
+            M::up(include_str!(
+                "../migrations/20261018090200_scraped_data_indexes.sql"
+            )),
+            M::up(include_str!(
+                "../migrations/20261018090300_retention_policies.sql"
+            )),
+            M::up_with_hook(
+                include_str!("../migrations/20261018090400_numeric_values.sql"),
+                backfill_numeric_values,
+            ),
+            M::up(include_str!(
+                "../migrations/20261018090500_search_index.sql"
+            )),
+            M::up(include_str!(
+                "../migrations/20261018090600_widget_trash.sql"
+            )),
+            M::up(include_str!(
+                "../migrations/20261018090700_widget_revisions.sql"
+            )),
+            M::up(include_str!(
+                "../migrations/20261018090800_modifier_state.sql"
+            )),
+            M::up(include_str!("../migrations/20261018090900_settings.sql")),
+            M::up(include_str!("../migrations/20261018091000_messages.sql")),
+            M::up(include_str!("../migrations/20261018091100_widget_tags.sql")),
+            M::up(include_str!("../migrations/20261018091200_layouts.sql")),
+            M::up(include_str!("../migrations/20261018091300_query_views.sql")),
+            M::up(include_str!("../migrations/20261018091400_webhooks.sql")),
+            M::up(include_str!(
+                "../migrations/20261018091500_scraped_rollups.sql"
+            )),
+            M::up(include_str!(
+                "../migrations/20261018091600_revision_disabled_modifiers.sql"
+            )),
+        ])
+    }
+
+    fn backfill_numeric_values(tx: &rusqlite::Transaction) -> rusqlite_migration::HookResult {
+        let rows = {
+            let mut stmt = tx.prepare("SELECT id, value FROM scraped_data WHERE error = ''")?;
+            let rows = stmt
+                .query_map([], |row| {
+                    Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+                })?
+                .collect::<SqliteResult<Vec<_>>>()?;
+            rows
+        };
+
+        let mut update =
+            tx.prepare("UPDATE scraped_data SET numeric_value = ?, unit = ? WHERE id = ?")?;
+        for (id, value) in rows {
+            if let Some(parsed) = parse_value(&value) {
+                update.execute(rusqlite::params![parsed.number, parsed.unit, id])?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) const WIDGET_COLUMNS: &str =
+        "id, widget_id, title, widget_type, level, transparent, decorations, is_open, bounds";
+    const MODIFIER_COLUMNS: &str =
+        "id, widget_id, modifier_type, enabled, last_run_at, last_success_at, consecutive_failures";
+    pub(crate) const SCRAPED_DATA_COLUMNS: &str =
+        "id, widget_id, value, error, timestamp, numeric_value, unit";
+
+    /// Decodes a JSON column, reporting the row as corrupt instead of panicking.
+    pub(crate) fn decode<T: DeserializeOwned>(
+        table: &'static str,
+        id: i64,
+        column: &str,
+        raw: &str,
+    ) -> DbResult<T> {
+        serde_json::from_str(raw).map_err(|e| DbError::CorruptRow {
+            table,
+            id,
+            reason: format!("{}: {}", column, e),
+        })
+    }
+
+    pub(crate) fn widget_from_row(
+        row: &rusqlite::Row,
+    ) -> SqliteResult<DbResult<WidgetConfiguration>> {
+        let id: i64 = row.get(0)?;
+        let widget_type: String = row.get(3)?;
+        let level: String = row.get(4)?;
+        let bounds: String = row.get(8)?;
+        let widget_id = NanoId(row.get(1)?);
+        let title = row.get(2)?;
+        let transparent = row.get::<_, i32>(5)? != 0;
+        let decorations = row.get::<_, i32>(6)? != 0;
+        let is_open = row.get::<_, i32>(7)? != 0;
+
+        Ok(
+            decode("widgets", id, "widget_type", &widget_type).and_then(|widget_type| {
+                Ok(WidgetConfiguration {
+                    id,
+                    widget_id,
+                    title,
+                    widget_type,
+                    level: decode("widgets", id, "level", &level)?,
+                    transparent,
+                    decorations,
+                    is_open,
+                    bounds: decode("widgets", id, "bounds", &bounds)?,
+                })
+            }),
+        )
+    }
+
+    fn modifier_from_row(row: &rusqlite::Row) -> SqliteResult<DbResult<WidgetModifier>> {
+        let id: i32 = row.get(0)?;
+        let widget_id = NanoId(row.get(1)?);
+        let modifier_type: String = row.get(2)?;
+        let enabled: bool = row.get(3)?;
+        let last_run_at: Option<i64> = row.get(4)?;
+        let last_success_at: Option<i64> = row.get(5)?;
+        let consecutive_failures: u32 = row.get(6)?;
+
+        Ok(
+            decode("modifiers", id as i64, "modifier_type", &modifier_type).map(|modifier_type| {
+                WidgetModifier {
+                    id,
+                    widget_id,
+                    modifier_type,
+                    enabled,
+                    last_run_at: last_run_at.map(|ms| ms.to_string()),
+                    last_success_at: last_success_at.map(|ms| ms.to_string()),
+                    consecutive_failures,
+                }
+            }),
+        )
+    }
+
+    pub(crate) fn scraped_data_from_row(row: &rusqlite::Row) -> SqliteResult<ScrapedData> {
+        Ok(ScrapedData {
+            id: row.get(0)?,
+            widget_id: row.get(1)?,
+            value: row.get(2)?,
+            error: row.get(3)?,
+            timestamp: row.get(4)?,
+            numeric_value: row.get(5)?,
+            unit: row.get(6)?,
+        })
+    }
+
+    pub(crate) fn insert_widget(conn: &Connection, config: &WidgetConfiguration) -> DbResult<()> {
+        let widget_type = serde_json::to_string(&config.widget_type)?;
+        let level = serde_json::to_string(&config.level)?;
+        let bounds = serde_json::to_string(&config.bounds)?;
+        conn.execute(
+            WidgetConfiguration::get_insert_sql(),
+            [
+                &config.widget_id.0,
+                &config.title,
+                &widget_type,
+                &level,
+                &(config.transparent as i32).to_string(),
+                &(config.decorations as i32).to_string(),
+                &(config.is_open as i32).to_string(),
+                &bounds,
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn insert_modifier_row(
+        conn: &Connection,
+        modifier: &WidgetModifier,
+    ) -> DbResult<()> {
+        let modifier_type = serde_json::to_string(&modifier.modifier_type)?;
+        conn.execute(
+            WidgetModifier::get_insert_sql(),
+            rusqlite::params![
+                modifier.widget_id.0,
+                modifier_type,
+                modifier.modifier_type.modifier_id().0,
+                modifier.enabled,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Checks what the scheduler can't run: a refresh interval below a second, or a selector
+    /// that isn't valid CSS.
+    pub fn validate_modifier(modifier: &Modifier) -> DbResult<()> {
+        match modifier {
+            Modifier::Refresh { interval_sec, .. } if *interval_sec <= 0 => {
+                Err(DbError::InvalidInput(format!(
+                    "interval_sec must be above 0, got {}",
+                    interval_sec
+                )))
+            }
+            Modifier::Scrape { selector, .. } => {
+                scraper::Selector::parse(selector).map(|_| ()).map_err(|e| {
+                    DbError::InvalidInput(format!("invalid selector {:?}: {}", selector, e))
+                })
+            }
+            Modifier::Refresh { .. } => Ok(()),
+        }
+    }
+
+    /// Stores a scrape result. `numeric_value` and `unit` are always derived from `value` here,
+    /// whatever the caller set them to.
+    pub(crate) fn insert_scraped_data(conn: &Connection, data: ScrapedData) -> DbResult<()> {
+        let error = data.error.unwrap_or_default();
+        let parsed = if error.is_empty() {
+            parse_value(&data.value)
+        } else {
+            None
+        };
+
+        conn.execute(
+            ScrapedData::get_insert_sql(),
+            rusqlite::params![
+                data.widget_id,
+                data.value,
+                error,
+                data.timestamp,
+                parsed.as_ref().map(|p| p.number),
+                parsed.and_then(|p| p.unit),
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Collects decoded rows, logging and skipping the ones whose stored JSON is unreadable so
+    /// that a single bad row doesn't take down every caller.
+    pub(crate) fn skip_corrupt<T>(
+        rows: impl Iterator<Item = SqliteResult<DbResult<T>>>,
+    ) -> DbResult<Vec<T>> {
+        let mut valid = vec![];
+        for row in rows {
+            match row? {
+                Ok(value) => valid.push(value),
+                Err(e @ DbError::CorruptRow { .. }) => error!("Skipping row: {}", e),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(valid)
+    }
+
+    pub(crate) fn default_app_settings() -> AppSettings {
+        AppSettings {
+            show_tray_icon: true,
+            email: "".to_string(),
+            licence_key: "".to_string(),
+            machine_id: "".to_string(),
+            licence_tier: LicenceTier::None,
+            trash_retention_days: DEFAULT_TRASH_RETENTION_DAYS,
         }
     }
 
     pub struct Database {
-        conn: Connection,
+        pub(crate) conn: Connection,
+        /// Where the database lives on disk, `None` when it is in memory.
+        pub(crate) path: Option<PathBuf>,
+        /// Shared with every other connection to the same file, see `Database::subscribe`.
+        pub(crate) changes: broadcast::Sender<DbChange>,
     }
 
     impl Database {
-        pub fn reset(&mut self) {
-            self.conn
-                .execute("DROP TABLE IF EXISTS widgets", [])
-                .unwrap();
-            self.conn
-                .execute("DROP TABLE IF EXISTS modifiers", [])
-                .unwrap();
-            self.conn
-                .execute("DROP TABLE IF EXISTS scraped_data", [])
-                .unwrap();
+        /// Drops every table and recreates an empty schema. A backup is taken first, and nothing
+        /// is dropped if that fails.
+        pub fn reset(&mut self) -> DbResult<()> {
+            if self.path.is_some() {
+                let backup = self.create_backup(Some("pre-reset"))?;
+                info!("Backed up database to {} before reset", backup.name);
+            }
 
-            self.conn
-                .execute("DROP TABLE IF EXISTS config", [])
-                .unwrap();
-            self.conn.execute("PRAGMA user_version = 0", []).unwrap();
-            let migrations = Migrations::new(vec![
-                M::up(include_str!("../migrations/20240318000000_initial.sql")),
-                // M::up(WidgetConfiguration::get_create_table_sql()),
-                // M::up(WidgetModifier::get_create_table_sql()),
-                // M::up(ScrapedData::get_create_table_sql()),
-            ]);
-            migrations.to_latest(&mut self.conn).unwrap();
-        }
-
-        pub fn from(in_memory: bool) -> SqliteResult<Self> {
-            // let conn = Connection::open_in_memory()?;
-            let mut conn = if in_memory {
-                Connection::open_in_memory()?
-            } else {
-                let directory =
-                    directories::ProjectDirs::from("com", "jarde", "hoverpane").unwrap();
-                let data_dir = directory.data_dir();
-                std::fs::create_dir_all(data_dir).unwrap();
-                let db_path = data_dir.join("widgets.db");
-
-                // Connection::open(db_path)?
-                match Connection::open(&db_path) {
-                    Ok(conn) => conn,
-                    Err(_) => {
-                        // Delete the database file if it exists
-                        if db_path.exists() {
-                            std::fs::remove_file(&db_path).unwrap();
-                        }
-                        // Try opening again after deletion
-                        Connection::open(&db_path)?
-                    }
-                }
+            // Drop everything the migrations created so they can be replayed from scratch. Virtual
+            // tables go first, they take their shadow tables with them.
+            let tables = {
+                let mut stmt = self.conn.prepare(
+                    r#"
+                    SELECT name FROM sqlite_master
+                    WHERE type = 'table' AND name NOT LIKE 'sqlite_%'
+                    ORDER BY sql LIKE 'CREATE VIRTUAL TABLE%' DESC
+                    "#,
+                )?;
+                let tables = stmt
+                    .query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<SqliteResult<Vec<_>>>()?;
+                tables
             };
-            conn.pragma_update_and_check(None, "journal_mode", &"WAL", |_| Ok(()))
-                .unwrap();
+            for table in tables {
+                self.conn
+                    .execute(&format!("DROP TABLE IF EXISTS \"{}\"", table), [])?;
+            }
+            self.conn.execute("PRAGMA user_version = 0", [])?;
+            self.migrate()?;
+            self.publish(DbChange::Reloaded);
+            Ok(())
+        }
 
-            let migrations = Migrations::new(vec![M::up(include_str!(
-                "../migrations/20240318000000_initial.sql"
-            ))]);
-            migrations.to_latest(&mut conn).unwrap();
+        pub(crate) fn migrate(&mut self) -> DbResult<()> {
+            migrations().to_latest(&mut self.conn)?;
+            Ok(())
+        }
 
-            Ok(Self { conn })
+        /// Opens an in-memory database, or the database of the profile picked by
+        /// `paths::resolve_profile`.
+        pub fn from(in_memory: bool) -> DbResult<Self> {
+            if in_memory {
+                return Self::from_connection(Connection::open_in_memory()?);
+            }
+
+            Self::open_profile(&paths::resolve_profile(None)?)
         }
 
-        pub fn set_settings(&self, settings: &AppSettings) -> SqliteResult<()> {
-            let json = serde_json::to_string(settings).unwrap();
-            // Upsert: delete all and insert new
-            self.conn.execute("DELETE FROM config", [])?;
-            self.conn
-                .execute("INSERT INTO config (json) VALUES (?)", [&json])?;
-            Ok(())
+        pub fn open_profile(profile: &str) -> DbResult<Self> {
+            let db_path = paths::database_path(profile)?;
+            if let Some(dir) = db_path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            info!("Opening profile {} at {:?}", profile, db_path);
+
+            Self::open(&db_path)
         }
 
-        pub fn get_settings(&self) -> SqliteResult<AppSettings> {
-            let mut stmt = self.conn.prepare("SELECT json FROM config LIMIT 1")?;
-            let mut rows = stmt.query([])?;
-            if let Some(row) = rows.next()? {
-                let json: String = row.get(0)?;
-                let settings: AppSettings = match serde_json::from_str(&json) {
-                    Ok(settings) => settings,
-                    Err(e) => {
-                        error!("Failed to parse settings: {:?}", e);
-                        let defaults = AppSettings {
-                            show_tray_icon: true,
-                            email: "".to_string(),
-                            licence_key: "".to_string(),
-                            machine_id: "".to_string(),
-                            licence_tier: LicenceTier::None,
-                        };
-                        self.set_settings(&defaults)?;
-                        defaults
+        /// Opens the database at `db_path`. A file that SQLite reports as corrupt is moved aside
+        /// and whatever can still be read from it is copied into a fresh database.
+        pub fn open(db_path: &Path) -> DbResult<Self> {
+            match Self::open_file(db_path) {
+                Ok(db) => Ok(db),
+                Err(e) if e.is_corruption() => {
+                    error!("Database {:?} is unreadable, recovering: {}", db_path, e);
+                    let (db, report) = recovery::recover(db_path)?;
+                    for message in report.messages() {
+                        db.add_message(
+                            MessageSeverity::Warning,
+                            ChangeSource::App,
+                            None,
+                            &message,
+                        )?;
                     }
-                };
-                Ok(settings)
-            } else {
-                Err(rusqlite::Error::QueryReturnedNoRows)
-            }
-        }
-
-        pub fn get_app_ui_state(&self) -> SqliteResult<AppUiState> {
-            let mut stmt = self.conn.prepare("SELECT json FROM app_ui_state LIMIT 1")?;
-            let mut rows = stmt.query([])?;
-            if let Some(row) = rows.next()? {
-                let json: String = row.get(0)?;
-                let app_ui_state: AppUiState = serde_json::from_str(&json).unwrap_or(AppUiState {
-                    app_settings: AppSettings {
-                        show_tray_icon: true,
-                        email: "".to_string(),
-                        licence_key: "".to_string(),
-                        machine_id: "".to_string(),
-                        licence_tier: LicenceTier::None,
-                    },
-                    messages: vec!["Failed to load app UI state".to_string()],
-                });
-                Ok(app_ui_state)
-            } else {
-                Err(rusqlite::Error::QueryReturnedNoRows)
+                    Ok(db)
+                }
+                Err(e) => Err(e),
             }
         }
 
-        pub fn set_app_ui_state(&self, app_ui_state: &AppUiState) -> SqliteResult<()> {
-            let json = serde_json::to_string(app_ui_state).unwrap();
-            self.conn.execute("DELETE FROM app_ui_state", [])?;
-            self.conn
-                .execute("INSERT INTO app_ui_state (json) VALUES (?)", [&json])?;
-            Ok(())
+        pub(crate) fn open_file(db_path: &Path) -> DbResult<Self> {
+            let conn = Connection::open(db_path)?;
+            conn.pragma_update_and_check(None, "journal_mode", &"WAL", |_| Ok(()))?;
+
+            let mut db = Self::from_connection(conn)?;
+            db.path = Some(db_path.to_path_buf());
+            db.changes = channel_for(db.path.as_deref());
+            Ok(db)
+        }
+
+        /// A read-only connection to a database that is already migrated, see `DbService`.
+        pub(crate) fn open_reader(db_path: &Path) -> DbResult<Self> {
+            let conn = Connection::open_with_flags(
+                db_path,
+                OpenFlags::SQLITE_OPEN_READ_ONLY
+                    | OpenFlags::SQLITE_OPEN_URI
+                    | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?;
+            Ok(Self {
+                conn,
+                path: Some(db_path.to_path_buf()),
+                changes: channel_for(Some(db_path)),
+            })
+        }
+
+        /// Brings an already opened connection up to the latest schema.
+        fn from_connection(mut conn: Connection) -> DbResult<Self> {
+            migrations().to_latest(&mut conn)?;
+
+            Ok(Self {
+                conn,
+                path: None,
+                changes: channel_for(None),
+            })
         }
 
         pub fn get_widget_configuration_by_id(
             &self,
             widget_id: &str,
-        ) -> SqliteResult<WidgetConfiguration> {
-            let mut stmt = self
-                .conn
-                .prepare("SELECT * FROM widgets WHERE widget_id = ?")?;
-            let widget = stmt
-                .query_map([widget_id], |row| {
-                    Ok(WidgetConfiguration {
-                        id: row.get(0)?,
-                        widget_id: NanoId(row.get(1)?),
-                        title: row.get(2)?,
-                        widget_type: serde_json::from_str(&row.get::<_, String>(3)?).unwrap(),
-                        level: serde_json::from_str(&row.get::<_, String>(4)?).unwrap(),
-                        transparent: row.get::<_, i32>(5)? != 0,
-                        decorations: row.get::<_, i32>(6)? != 0,
-                        is_open: row.get::<_, i32>(7)? != 0,
-                        bounds: serde_json::from_str(&row.get::<_, String>(8)?).unwrap(),
-                    })
-                })?
-                .next()
-                .unwrap();
+        ) -> DbResult<WidgetConfiguration> {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {} FROM widgets WHERE widget_id = ? AND deleted_at IS NULL",
+                WIDGET_COLUMNS
+            ))?;
+            let mut rows = stmt.query_map([widget_id], widget_from_row)?;
 
-            match widget {
-                Ok(widget) => Ok(widget),
-                Err(e) => {
-                    return Err(e);
-                }
+            match rows.next() {
+                Some(widget) => widget?,
+                None => Err(DbError::NotFound(format!("widget {}", widget_id))),
             }
         }
 
-        pub fn get_configuration(&self) -> SqliteResult<Vec<WidgetConfiguration>> {
-            let mut stmt = self.conn.prepare("SELECT * FROM widgets")?;
-            let widgets = stmt
-                .query_map([], |row| {
-                    Ok(WidgetConfiguration {
-                        id: row.get(0)?,
-                        widget_id: NanoId(row.get(1)?),
-                        title: row.get(2)?,
-                        widget_type: serde_json::from_str(&row.get::<_, String>(3)?).unwrap(),
-                        level: serde_json::from_str(&row.get::<_, String>(4)?).unwrap(),
-                        transparent: row.get::<_, i32>(5)? != 0,
-                        decorations: row.get::<_, i32>(6)? != 0,
-                        is_open: row.get::<_, i32>(7)? != 0,
-                        bounds: serde_json::from_str(&row.get::<_, String>(8)?).unwrap(),
-                    })
-                })?
-                .collect::<SqliteResult<Vec<_>>>()?;
-            Ok(widgets)
+        pub fn get_configuration(&self) -> DbResult<Vec<WidgetConfiguration>> {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {} FROM widgets WHERE deleted_at IS NULL",
+                WIDGET_COLUMNS
+            ))?;
+            let widgets = stmt.query_map([], widget_from_row)?;
+            skip_corrupt(widgets)
         }
+
+        /// Saves `config` over the widget with the same id, or adds it when there is none. The
+        /// widget's modifiers, history and trash state are left alone.
         pub fn upsert_widget_configuration(
             &mut self,
             config: WidgetConfiguration,
-        ) -> SqliteResult<()> {
+            source: ChangeSource,
+        ) -> DbResult<()> {
             let tx = self.conn.transaction()?;
-
-            // Delete existing widget if it exists
-            tx.execute(
-                "DELETE FROM widgets WHERE widget_id = ?",
-                [&config.widget_id.0],
-            )?;
-
-            // Delete associated modifiers
-            tx.execute(
-                "DELETE FROM modifiers WHERE widget_id = ?",
-                [&config.widget_id.0],
-            )?;
-
-            // Insert the new configuration
-            let widget_type = serde_json::to_string(&config.widget_type).unwrap();
-            let level = serde_json::to_string(&config.level).unwrap();
-            tx.execute(
-                WidgetConfiguration::get_insert_sql(),
-                [
-                    &config.widget_id.0,
-                    &config.title,
-                    &widget_type,
-                    &level,
-                    &(config.transparent as i32).to_string(),
-                    &(config.decorations as i32).to_string(),
-                    &(config.is_open as i32).to_string(),
-                    &serde_json::to_string(&config.bounds).unwrap(),
+            let updated = tx.execute(
+                "UPDATE widgets SET title = ?, widget_type = ?, level = ?, transparent = ?, decorations = ?, is_open = ?, bounds = ? WHERE widget_id = ?",
+                rusqlite::params![
+                    config.title,
+                    serde_json::to_string(&config.widget_type)?,
+                    serde_json::to_string(&config.level)?,
+                    config.transparent,
+                    config.decorations,
+                    config.is_open,
+                    serde_json::to_string(&config.bounds)?,
+                    config.widget_id.0
                 ],
             )?;
-
+            if updated == 0 {
+                insert_widget(&tx, &config)?;
+            }
+            record_revision(&tx, &config.widget_id.0, source)?;
             tx.commit()?;
+
+            let widget_id = config.widget_id.0;
+            self.publish(if updated == 0 {
+                DbChange::WidgetCreated { widget_id }
+            } else {
+                DbChange::WidgetSaved { widget_id }
+            });
             Ok(())
         }
 
         pub fn insert_widget_configuration(
             &mut self,
             configs: Vec<WidgetConfiguration>,
-        ) -> SqliteResult<()> {
+            source: ChangeSource,
+        ) -> DbResult<()> {
             let tx = self.conn.transaction()?;
-            let mut stmt = tx.prepare(WidgetConfiguration::get_insert_sql())?;
 
+            let mut inserted = vec![];
             for config in configs {
-                let widget_type = serde_json::to_string(&config.widget_type).unwrap();
-                let level = serde_json::to_string(&config.level).unwrap();
-                let position_json = serde_json::to_string(&config.bounds).unwrap();
-                match stmt.execute([
-                    &config.widget_id.0,
-                    &config.title,
-                    &widget_type,
-                    &level,
-                    &(config.transparent as i32).to_string(),
-                    &(config.decorations as i32).to_string(),
-                    &(config.is_open as i32).to_string(),
-                    &position_json,
-                ]) {
-                    Ok(_) => (),
-                    Err(e) => {
-                        if e.to_string().contains("UNIQUE constraint failed") {
-                            error!("Widget with ID '{}' already exists", config.widget_id.0);
-                        } else {
-                            return Err(e);
-                        }
+                match insert_widget(&tx, &config) {
+                    Ok(_) => {
+                        record_revision(&tx, &config.widget_id.0, source)?;
+                        inserted.push(config.widget_id.0);
                     }
+                    Err(DbError::Sqlite(rusqlite::Error::SqliteFailure(e, _)))
+                        if e.extended_code == rusqlite::ffi::SQLITE_CONSTRAINT_UNIQUE =>
+                    {
+                        error!("Widget with ID '{}' already exists", config.widget_id.0);
+                    }
+                    Err(e) => return Err(e),
                 }
             }
-            drop(stmt); // Drop the statement before committing
             tx.commit()?;
+            for widget_id in inserted {
+                self.publish(DbChange::WidgetCreated { widget_id });
+            }
             Ok(())
         }
 
-        pub fn get_data(&self) -> SqliteResult<Vec<ScrapedData>> {
-            let mut stmt = self.conn.prepare("SELECT * FROM scraped_data")?;
-            let rows = stmt.query_map([], |row| {
-                Ok(ScrapedData {
-                    id: row.get(0)?,
-                    widget_id: row.get(1)?,
-                    value: row.get(2)?,
-                    error: row.get(3)?,
-                    timestamp: row.get(4)?,
-                })
-            })?;
-
-            rows.collect()
+        /// Saves a new widget together with its modifiers, all or nothing. The modifiers are
+        /// validated before anything is written.
+        pub fn create_widget_with_modifiers(
+            &mut self,
+            config: &WidgetConfiguration,
+            modifiers: &[Modifier],
+            source: ChangeSource,
+        ) -> DbResult<()> {
+            for modifier in modifiers {
+                validate_modifier(modifier)?;
+            }
+            let tx = self.conn.transaction()?;
+            insert_widget(&tx, config)?;
+            for modifier in modifiers {
+                insert_modifier_row(
+                    &tx,
+                    &WidgetModifier::new(config.widget_id.clone(), modifier.clone()),
+                )?;
+            }
+            record_revision(&tx, &config.widget_id.0, source)?;
+            tx.commit()?;
+            self.publish(DbChange::WidgetCreated {
+                widget_id: config.widget_id.0.clone(),
+            });
+            if !modifiers.is_empty() {
+                self.publish(DbChange::ModifiersChanged {
+                    widget_id: config.widget_id.0.clone(),
+                });
+            }
+            Ok(())
         }
 
-        pub fn get_latest_data(&self) -> SqliteResult<Vec<ScrapedData>> {
-            let mut stmt = self.conn.prepare(
-                r#"
-                SELECT *
-                FROM scraped_data
-                ORDER BY timestamp DESC
-                LIMIT 1
-                "#,
-            )?;
-            let rows = stmt.query_map([], |row| {
-                Ok(ScrapedData {
-                    id: row.get(0)?,
-                    widget_id: row.get(1)?,
-                    value: row.get(2)?,
-                    error: row.get(3)?,
-                    timestamp: row.get(4)?,
-                })
-            })?;
+        pub fn get_data(&self) -> DbResult<Vec<ScrapedData>> {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {} FROM scraped_data",
+                SCRAPED_DATA_COLUMNS
+            ))?;
+            let rows = stmt.query_map([], scraped_data_from_row)?;
 
-            rows.collect()
+            Ok(rows.collect::<SqliteResult<Vec<_>>>()?)
         }
 
-        pub fn insert_data(&self, insert_data: ScrapedData) -> SqliteResult<()> {
-            let value = insert_data.value;
-            let error = insert_data.error.unwrap_or_default();
-            let timestamp = insert_data.timestamp.to_string();
-
-            self.conn.execute(
-                ScrapedData::get_insert_sql(),
-                [&insert_data.widget_id, &value, &error, &timestamp],
-            )?;
+        pub fn get_data_by_id(&self, id: u32) -> DbResult<ScrapedData> {
+            self.conn
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM scraped_data WHERE id = ?",
+                        SCRAPED_DATA_COLUMNS
+                    ),
+                    [id],
+                    scraped_data_from_row,
+                )
+                .optional()?
+                .ok_or_else(|| DbError::NotFound(format!("scraped data {}", id)))
+        }
 
+        /// Stores a scrape result. `numeric_value` and `unit` are always derived from `value`
+        /// here, whatever the caller set them to.
+        pub fn insert_data(&self, insert_data: ScrapedData) -> DbResult<()> {
+            let widget_id = insert_data.widget_id.clone();
+            insert_scraped_data(&self.conn, insert_data)?;
+            self.publish(DbChange::DataInserted {
+                widget_id,
+                id: self.conn.last_insert_rowid() as u32,
+            });
             Ok(())
         }
 
-        pub fn get_modifiers(&self) -> SqliteResult<Vec<WidgetModifier>> {
-            let mut stmt = self.conn.prepare("SELECT * FROM modifiers")?;
-            let rows = stmt.query_map([], |row| {
-                Ok(WidgetModifier {
-                    id: row.get(0)?,
-                    widget_id: NanoId(row.get(1)?),
-                    modifier_type: serde_json::from_str(&row.get::<_, String>(2)?).unwrap(),
-                })
-            })?;
-
-            rows.collect()
+        pub fn get_modifiers(&self) -> DbResult<Vec<WidgetModifier>> {
+            self.get_all_widget_modifiers()
         }
 
-        pub fn insert_modifier(&self, modifier: WidgetModifier) -> SqliteResult<()> {
-            let modifier_type = serde_json::to_string(&modifier.modifier_type).unwrap();
-            self.conn.execute(
-                WidgetModifier::get_insert_sql(),
-                [&modifier.widget_id.0, &modifier_type],
-            )?;
-            Ok(())
+        pub fn insert_modifier(
+            &mut self,
+            modifier: WidgetModifier,
+            source: ChangeSource,
+        ) -> DbResult<()> {
+            self.insert_widget_modifier(modifier, source)
         }
 
-        pub fn insert_widget_modifier(&self, widget_modifier: WidgetModifier) -> SqliteResult<()> {
-            let modifier_type = serde_json::to_string(&widget_modifier.modifier_type).unwrap();
-            self.conn.execute(
-                WidgetModifier::get_insert_sql(),
-                [&widget_modifier.widget_id.0, &modifier_type],
-            )?;
+        pub fn insert_widget_modifier(
+            &mut self,
+            widget_modifier: WidgetModifier,
+            source: ChangeSource,
+        ) -> DbResult<()> {
+            validate_modifier(&widget_modifier.modifier_type)?;
+            let tx = self.conn.transaction()?;
+            insert_modifier_row(&tx, &widget_modifier)?;
+            record_revision(&tx, &widget_modifier.widget_id.0, source)?;
+            tx.commit()?;
+            self.publish(DbChange::ModifiersChanged {
+                widget_id: widget_modifier.widget_id.0,
+            });
             Ok(())
         }
 
         pub fn insert_widget_modifiers(
             &mut self,
             widget_modifiers: Vec<WidgetModifier>,
-        ) -> SqliteResult<()> {
+            source: ChangeSource,
+        ) -> DbResult<()> {
+            for widget_modifier in &widget_modifiers {
+                validate_modifier(&widget_modifier.modifier_type)?;
+            }
             let tx = self.conn.transaction()?;
-            {
-                let mut stmt = tx.prepare(WidgetModifier::get_insert_sql())?;
-
-                for widget_modifier in widget_modifiers {
-                    let modifier_type =
-                        serde_json::to_string(&widget_modifier.modifier_type).unwrap();
-                    stmt.execute([&widget_modifier.widget_id.0, &modifier_type])?;
-                }
+            for widget_modifier in &widget_modifiers {
+                insert_modifier_row(&tx, widget_modifier)?;
+            }
+            let mut widget_ids = widget_modifiers
+                .iter()
+                .map(|m| m.widget_id.0.as_str())
+                .collect::<Vec<_>>();
+            widget_ids.sort();
+            widget_ids.dedup();
+            for widget_id in &widget_ids {
+                record_revision(&tx, widget_id, source)?;
             }
             tx.commit()?;
+            for widget_id in widget_ids {
+                self.publish(DbChange::ModifiersChanged {
+                    widget_id: widget_id.to_string(),
+                });
+            }
             Ok(())
         }
 
-        pub fn get_widget_modifier(&self, widget_id: &str) -> SqliteResult<Vec<WidgetModifier>> {
-            let mut stmt = self
-                .conn
-                .prepare("SELECT * FROM modifiers WHERE widget_id = ?")?;
-            let rows = stmt.query_map([widget_id], |row| {
-                Ok(WidgetModifier {
-                    id: row.get(0)?,
-                    widget_id: NanoId(row.get(1)?),
-                    modifier_type: serde_json::from_str(&row.get::<_, String>(2)?).unwrap(),
-                })
-            })?;
-
-            rows.collect()
+        pub fn get_widget_modifier(&self, widget_id: &str) -> DbResult<Vec<WidgetModifier>> {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {} FROM modifiers WHERE widget_id = ?",
+                MODIFIER_COLUMNS
+            ))?;
+            let rows = stmt.query_map([widget_id], modifier_from_row)?;
+            skip_corrupt(rows)
         }
 
-        pub fn get_all_widget_modifiers(&self) -> SqliteResult<Vec<WidgetModifier>> {
-            let mut stmt = self.conn.prepare("SELECT * FROM modifiers")?;
-            let rows = stmt.query_map([], |row| {
-                Ok(WidgetModifier {
-                    id: row.get(0)?,
-                    widget_id: NanoId(row.get(1)?),
-                    modifier_type: serde_json::from_str(&row.get::<_, String>(2)?).unwrap(),
-                })
-            })?;
+        /// Modifiers of every widget that isn't in the trash.
+        pub fn get_all_widget_modifiers(&self) -> DbResult<Vec<WidgetModifier>> {
+            let mut stmt = self.conn.prepare(&format!(
+                r#"
+                SELECT {} FROM modifiers
+                WHERE widget_id NOT IN (SELECT widget_id FROM widgets WHERE deleted_at IS NOT NULL)
+                "#,
+                MODIFIER_COLUMNS
+            ))?;
+            let rows = stmt.query_map([], modifier_from_row)?;
+            skip_corrupt(rows)
+        }
 
-            rows.collect()
+        /// Moves a widget to the trash. Its modifiers and history stay until it is purged, see
+        /// `purge_widget`.
+        pub fn delete_widget(&mut self, widget_id: &str, source: ChangeSource) -> DbResult<()> {
+            let rows_affected = self.conn.execute(
+                "UPDATE widgets SET deleted_at = ?, is_open = 0 WHERE widget_id = ? AND deleted_at IS NULL",
+                rusqlite::params![jiff::Timestamp::now().as_millisecond(), widget_id],
+            )?;
+            if rows_affected == 0 {
+                return Err(DbError::NotFound(format!("widget {}", widget_id)));
+            }
+            record_revision(&self.conn, widget_id, source)?;
+            self.publish(DbChange::WidgetDeleted {
+                widget_id: widget_id.to_string(),
+            });
+            Ok(())
         }
 
-        pub fn delete_widget(&mut self, widget_id: &str) -> SqliteResult<()> {
-            let tx = self.conn.transaction()?;
+        pub fn get_widget_modifier_by_id(
+            &self,
+            widget_id: &str,
+            modifier_id: &str,
+        ) -> DbResult<WidgetModifier> {
+            self.conn
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM modifiers WHERE widget_id = ? AND modifier_id = ?",
+                        MODIFIER_COLUMNS
+                    ),
+                    [widget_id, modifier_id],
+                    modifier_from_row,
+                )
+                .optional()?
+                .ok_or_else(|| {
+                    DbError::NotFound(format!("modifier {} of widget {}", modifier_id, widget_id))
+                })?
+        }
 
-            // Delete associated scraped data
-            tx.execute("DELETE FROM scraped_data WHERE widget_id = ?", [widget_id])?;
+        pub fn delete_widget_modifier(
+            &self,
+            widget_id: &str,
+            modifier_id: &str,
+            source: ChangeSource,
+        ) -> DbResult<()> {
+            let rows_affected = self.conn.execute(
+                "DELETE FROM modifiers WHERE widget_id = ? AND modifier_id = ?",
+                [widget_id, modifier_id],
+            )?;
+            if rows_affected == 0 {
+                return Err(DbError::NotFound(format!(
+                    "modifier {} of widget {}",
+                    modifier_id, widget_id
+                )));
+            }
+            record_revision(&self.conn, widget_id, source)?;
+            self.publish(DbChange::ModifiersChanged {
+                widget_id: widget_id.to_string(),
+            });
+            Ok(())
+        }
 
-            // Delete associated modifiers
-            tx.execute("DELETE FROM modifiers WHERE widget_id = ?", [widget_id])?;
+        /// Changes a modifier's selector or interval in place. It counts as never run afterwards,
+        /// so the scheduler runs it with the new settings as soon as it hears about it.
+        pub fn update_widget_modifier(
+            &mut self,
+            widget_id: &str,
+            modifier_id: &str,
+            update: &UpdateModifierRequest,
+            source: ChangeSource,
+        ) -> DbResult<WidgetModifier> {
+            let mut modifier = self.get_widget_modifier_by_id(widget_id, modifier_id)?;
+            match (&mut modifier.modifier_type, update) {
+                (
+                    _,
+                    UpdateModifierRequest {
+                        selector: None,
+                        interval_sec: None,
+                    },
+                ) => return Err(DbError::InvalidInput("nothing to update".to_string())),
+                (
+                    Modifier::Scrape { selector, .. },
+                    UpdateModifierRequest {
+                        selector: Some(new_selector),
+                        interval_sec: None,
+                    },
+                ) => *selector = new_selector.trim().to_string(),
+                (
+                    Modifier::Refresh { interval_sec, .. },
+                    UpdateModifierRequest {
+                        selector: None,
+                        interval_sec: Some(new_interval),
+                    },
+                ) => *interval_sec = *new_interval,
+                (Modifier::Scrape { .. }, _) => {
+                    return Err(DbError::InvalidInput(format!(
+                        "modifier {} scrapes, it only has a selector",
+                        modifier_id
+                    )))
+                }
+                (Modifier::Refresh { .. }, _) => {
+                    return Err(DbError::InvalidInput(format!(
+                        "modifier {} refreshes, it only has an interval_sec",
+                        modifier_id
+                    )))
+                }
+            }
+            validate_modifier(&modifier.modifier_type)?;
 
-            // Delete the widget itself
-            let rows_affected =
-                tx.execute("DELETE FROM widgets WHERE widget_id = ?", [widget_id])?;
+            let tx = self.conn.transaction()?;
+            tx.execute(
+                "UPDATE modifiers SET modifier_type = ?, last_run_at = NULL, consecutive_failures = 0 WHERE widget_id = ? AND modifier_id = ?",
+                rusqlite::params![
+                    serde_json::to_string(&modifier.modifier_type)?,
+                    widget_id,
+                    modifier_id
+                ],
+            )?;
+            record_revision(&tx, widget_id, source)?;
+            tx.commit()?;
+            self.publish(DbChange::ModifiersChanged {
+                widget_id: widget_id.to_string(),
+            });
+            self.get_widget_modifier_by_id(widget_id, modifier_id)
+        }
 
-            if rows_affected == 0 {
-                tx.rollback()?;
-                return Err(rusqlite::Error::QueryReturnedNoRows);
+        /// Disabled modifiers stay attached to their widget but are skipped by the scheduler.
+        pub fn set_modifier_enabled(
+            &mut self,
+            widget_id: &str,
+            modifier_id: &str,
+            enabled: bool,
+            source: ChangeSource,
+        ) -> DbResult<WidgetModifier> {
+            let tx = self.conn.transaction()?;
+            let rows_affected = tx.execute(
+                "UPDATE modifiers SET enabled = ? WHERE widget_id = ? AND modifier_id = ? AND enabled != ?1",
+                rusqlite::params![enabled, widget_id, modifier_id],
+            )?;
+            if rows_affected > 0 {
+                record_revision(&tx, widget_id, source)?;
             }
-
             tx.commit()?;
+            let modifier = self.get_widget_modifier_by_id(widget_id, modifier_id)?;
+            if rows_affected > 0 {
+                self.publish(DbChange::ModifiersChanged {
+                    widget_id: widget_id.to_string(),
+                });
+            }
+            Ok(modifier)
+        }
+
+        /// Notes that the scheduler started a modifier at `at_ms`.
+        pub fn record_modifier_run(&self, modifier_id: &str, at_ms: i64) -> DbResult<()> {
+            self.conn.execute(
+                "UPDATE modifiers SET last_run_at = ? WHERE modifier_id = ?",
+                rusqlite::params![at_ms, modifier_id],
+            )?;
             Ok(())
         }
 
-        pub fn delete_widget_modifier(&self, modifier_id: &str) -> SqliteResult<()> {
-            self.conn
-                .execute("DELETE FROM modifiers WHERE id = ?", [modifier_id])?;
+        /// Notes how a run of a modifier ended. Failures count up until the next success.
+        pub fn record_modifier_result(
+            &self,
+            modifier_id: &str,
+            success: bool,
+            at_ms: i64,
+        ) -> DbResult<()> {
+            if success {
+                self.conn.execute(
+                    "UPDATE modifiers SET last_success_at = ?, consecutive_failures = 0 WHERE modifier_id = ?",
+                    rusqlite::params![at_ms, modifier_id],
+                )?;
+            } else {
+                self.conn.execute(
+                    "UPDATE modifiers SET consecutive_failures = consecutive_failures + 1 WHERE modifier_id = ?",
+                    [modifier_id],
+                )?;
+            }
             Ok(())
         }
 
-        pub fn update_widget_open_state(&self, widget_id: NanoId, is_open: bool) {
+        /// Open state is window state rather than configuration, so it isn't recorded as a
+        /// revision.
+        pub fn update_widget_open_state(&self, widget_id: NanoId, is_open: bool) -> DbResult<()> {
             let is_open_int = is_open as i32;
-            self.conn
-                .execute(
-                    "UPDATE widgets SET is_open = ? WHERE widget_id = ?",
-                    [is_open_int.to_string(), widget_id.0],
-                )
-                .unwrap();
+            self.conn.execute(
+                "UPDATE widgets SET is_open = ? WHERE widget_id = ?",
+                [is_open_int.to_string(), widget_id.0.clone()],
+            )?;
+            self.publish(DbChange::VisibilityChanged {
+                widget_id: widget_id.0,
+                is_open,
+            });
+            Ok(())
         }
 
         pub fn update_widget_position(
             &self,
             widget_id: &str,
             new_position: &MonitorPosition,
-        ) -> SqliteResult<()> {
-            let position_json = serde_json::to_string(new_position).unwrap();
-            self.conn.execute(
-                "UPDATE widgets SET position = ? WHERE widget_id = ?",
-                [position_json, widget_id.to_string()],
-            )?;
-            Ok(())
+            source: ChangeSource,
+        ) -> DbResult<()> {
+            let bounds = WidgetBounds {
+                x: new_position.x.max(0) as u32,
+                y: new_position.y.max(0) as u32,
+                width: new_position.width.max(0) as u32,
+                height: new_position.height.max(0) as u32,
+            };
+            self.update_widget_bounds(widget_id.to_string(), bounds, source)
         }
 
         pub fn update_widget_position_property(
@@ -514,15 +922,20 @@ pub mod db {
             widget_id: &str,
             property: &str,
             value: &str,
-        ) -> SqliteResult<()> {
+            source: ChangeSource,
+        ) -> DbResult<()> {
             self.conn.execute(
-                "UPDATE widgets SET position = json_set(position, ?, ?) WHERE widget_id = ?",
+                "UPDATE widgets SET bounds = json_set(bounds, ?, json(?)) WHERE widget_id = ?",
                 [
                     format!("$.{}", property),
                     value.to_string(),
                     widget_id.to_string(),
                 ],
             )?;
+            record_revision(&self.conn, widget_id, source)?;
+            self.publish(DbChange::WidgetMoved {
+                widget_id: widget_id.to_string(),
+            });
             Ok(())
         }
 
@@ -530,43 +943,199 @@ pub mod db {
             &self,
             widget_id: String,
             bounds: widget_types::WidgetBounds,
-        ) -> SqliteResult<()> {
-            let position_json = serde_json::to_string(&bounds).unwrap();
+            source: ChangeSource,
+        ) -> DbResult<()> {
+            let bounds_json = serde_json::to_string(&bounds)?;
             self.conn.execute(
-                "UPDATE widgets SET position = ? WHERE widget_id = ?",
-                [position_json, widget_id.to_string()],
+                "UPDATE widgets SET bounds = ? WHERE widget_id = ?",
+                [bounds_json, widget_id.to_string()],
             )?;
+            record_revision(&self.conn, &widget_id, source)?;
+            self.publish(DbChange::WidgetMoved { widget_id });
             Ok(())
         }
 
-        pub fn set_config_information(&self, config_information: Vec<ConfigInformation>) {
-            let config_information_json = serde_json::to_string(&config_information).unwrap();
-            self.conn
-                .execute(
-                    "INSERT INTO config (json) VALUES (?)",
-                    [&config_information_json],
-                )
-                .unwrap();
+        pub fn update_widget_level(
+            &self,
+            widget_id: &str,
+            level: &Level,
+            source: ChangeSource,
+        ) -> DbResult<()> {
+            let level_json = serde_json::to_string(level)?;
+            let rows_affected = self.conn.execute(
+                "UPDATE widgets SET level = ? WHERE widget_id = ? AND deleted_at IS NULL",
+                [level_json, widget_id.to_string()],
+            )?;
+            if rows_affected == 0 {
+                return Err(DbError::NotFound(format!("widget {}", widget_id)));
+            }
+            record_revision(&self.conn, widget_id, source)?;
+            self.publish(DbChange::WidgetSaved {
+                widget_id: widget_id.to_string(),
+            });
+            Ok(())
+        }
+
+        /// Applies the fields set in `update` to a widget outside the trash and returns the
+        /// widget as saved.
+        pub fn update_widget(
+            &mut self,
+            widget_id: &str,
+            update: &UpdateWidgetRequest,
+            source: ChangeSource,
+        ) -> DbResult<WidgetConfiguration> {
+            if *update == UpdateWidgetRequest::default() {
+                return Err(DbError::InvalidInput("nothing to update".to_string()));
+            }
+            let mut widget = self.get_widget_configuration_by_id(widget_id)?;
+
+            if let Some(title) = &update.title {
+                widget.title = title.clone();
+            }
+            match (&mut widget.widget_type, &update.url, &update.html) {
+                (_, Some(content), _) | (_, _, Some(content)) if content.trim().is_empty() => {
+                    return Err(DbError::InvalidInput(
+                        "url and html can't be empty".to_string(),
+                    ));
+                }
+                (WidgetType::Url(config), Some(url), None) => config.url = url.trim().to_string(),
+                (WidgetType::File(config), None, Some(html)) => config.html = html.clone(),
+                (_, None, None) => {}
+                (WidgetType::Url(_), _, _) => {
+                    return Err(DbError::InvalidInput(format!(
+                        "widget {} shows a URL, it has no html to change",
+                        widget_id
+                    )));
+                }
+                (WidgetType::File(_), _, _) => {
+                    return Err(DbError::InvalidInput(format!(
+                        "widget {} shows html, it has no url to change",
+                        widget_id
+                    )));
+                }
+            }
+            if let Some(level) = &update.level {
+                widget.level = level.clone();
+            }
+            if let Some(transparent) = update.transparent {
+                widget.transparent = transparent;
+            }
+            if let Some(decorations) = update.decorations {
+                widget.decorations = decorations;
+            }
+            if let Some(bounds) = &update.bounds {
+                if bounds.width == 0 || bounds.height == 0 {
+                    return Err(DbError::InvalidInput(
+                        "width and height must be above 0".to_string(),
+                    ));
+                }
+                widget.bounds = bounds.clone();
+            }
+
+            let tx = self.conn.transaction()?;
+            tx.execute(
+                "UPDATE widgets SET title = ?, widget_type = ?, level = ?, transparent = ?, decorations = ?, bounds = ? WHERE widget_id = ?",
+                rusqlite::params![
+                    widget.title,
+                    serde_json::to_string(&widget.widget_type)?,
+                    serde_json::to_string(&widget.level)?,
+                    widget.transparent,
+                    widget.decorations,
+                    serde_json::to_string(&widget.bounds)?,
+                    widget_id
+                ],
+            )?;
+            record_revision(&tx, widget_id, source)?;
+            tx.commit()?;
+            self.publish(DbChange::WidgetSaved {
+                widget_id: widget_id.to_string(),
+            });
+            Ok(widget)
+        }
+
+        pub fn set_config_information(
+            &self,
+            config_information: Vec<ConfigInformation>,
+        ) -> DbResult<()> {
+            let config_information_json = serde_json::to_string(&config_information)?;
+            self.conn.execute(
+                "INSERT INTO config (json) VALUES (?)",
+                [&config_information_json],
+            )?;
+            Ok(())
+        }
+    }
+
+    /// Fixtures shared by the tests of every module.
+    #[cfg(test)]
+    pub(crate) mod fixtures {
+        use super::Database;
+        use std::path::PathBuf;
+        use widget_types::ScrapedData;
+
+        /// A fresh directory for an on-disk database, removed again when dropped so a failing
+        /// test doesn't leave it behind.
+        pub(crate) struct TempDb {
+            pub(crate) dir: PathBuf,
+        }
+
+        impl TempDb {
+            pub(crate) fn new() -> Self {
+                let dir = std::env::temp_dir().join(format!("widget-db-{}", nanoid::nanoid_gen(8)));
+                std::fs::create_dir_all(&dir).unwrap();
+                Self { dir }
+            }
+
+            /// Where the database file goes, nothing is created there until it is opened.
+            pub(crate) fn path(&self) -> PathBuf {
+                self.dir.join("widgets.db")
+            }
+
+            pub(crate) fn open(&self) -> Database {
+                Database::open(&self.path()).unwrap()
+            }
+        }
+
+        impl Drop for TempDb {
+            fn drop(&mut self) {
+                let _ = std::fs::remove_dir_all(&self.dir);
+            }
+        }
+
+        /// A successful scrape of `value` at `timestamp` epoch milliseconds.
+        pub(crate) fn scraped(widget_id: &str, value: &str, timestamp: i64) -> ScrapedData {
+            ScrapedData {
+                id: 0,
+                widget_id: widget_id.to_string(),
+                value: value.to_string(),
+                error: None,
+                timestamp: timestamp.to_string(),
+                numeric_value: None,
+                unit: None,
+            }
         }
     }
 
     #[cfg(test)]
     mod tests {
         use super::*;
-        use widget_types::{Modifier, UrlConfiguration, WidgetBounds, WidgetType};
+        use fixtures::scraped;
+        use widget_types::{
+            Modifier, UrlConfiguration, WidgetBounds, WidgetType, DEFAULT_WIDGET_X,
+            DEFAULT_WIDGET_Y,
+        };
 
         #[test]
         fn test_modifier_roundtrip() {
-            let db = Database::from(true).unwrap();
-            let modifier = WidgetModifier {
-                id: 1,
-                widget_id: NanoId(String::from("1")),
-                modifier_type: Modifier::Refresh {
+            let mut db = Database::from(true).unwrap();
+            let modifier = WidgetModifier::new(
+                NanoId(String::from("1")),
+                Modifier::Refresh {
                     modifier_id: NanoId(String::from("1")),
                     interval_sec: 30,
                 },
-            };
-            db.insert_modifier(modifier).unwrap();
+            );
+            db.insert_modifier(modifier, ChangeSource::Api).unwrap();
             let modifiers = db.get_modifiers().unwrap();
             assert_eq!(modifiers.len(), 1);
             assert_eq!(modifiers[0].id, 1);
@@ -580,6 +1149,180 @@ pub mod db {
             );
         }
 
+        #[test]
+        fn test_modifiers_are_keyed_by_modifier_id() {
+            let mut db = Database::from(true).unwrap();
+            let refresh = |widget_id: &str, modifier_id: &str| {
+                WidgetModifier::new(
+                    NanoId(widget_id.to_string()),
+                    Modifier::Refresh {
+                        modifier_id: NanoId(modifier_id.to_string()),
+                        interval_sec: 30,
+                    },
+                )
+            };
+            db.insert_widget_modifier(refresh("a", "m1"), ChangeSource::Api)
+                .unwrap();
+            db.insert_widget_modifier(refresh("b", "m2"), ChangeSource::Api)
+                .unwrap();
+            assert!(db
+                .insert_widget_modifier(refresh("b", "m1"), ChangeSource::Api)
+                .is_err());
+
+            let disabled = db
+                .set_modifier_enabled("a", "m1", false, ChangeSource::Api)
+                .unwrap();
+            assert!(!disabled.enabled);
+            assert!(matches!(
+                db.set_modifier_enabled("b", "m1", false, ChangeSource::Api),
+                Err(DbError::NotFound(_))
+            ));
+
+            db.record_modifier_run("m2", 1000).unwrap();
+            db.record_modifier_result("m2", false, 1000).unwrap();
+            db.record_modifier_result("m2", false, 2000).unwrap();
+            let failing = db.get_widget_modifier_by_id("b", "m2").unwrap();
+            assert_eq!(failing.last_run_at.as_deref(), Some("1000"));
+            assert_eq!(failing.last_success_at, None);
+            assert_eq!(failing.consecutive_failures, 2);
+
+            db.record_modifier_result("m2", true, 3000).unwrap();
+            let recovered = db.get_widget_modifier_by_id("b", "m2").unwrap();
+            assert_eq!(recovered.last_success_at.as_deref(), Some("3000"));
+            assert_eq!(recovered.consecutive_failures, 0);
+
+            assert!(matches!(
+                db.delete_widget_modifier("a", "m2", ChangeSource::Api),
+                Err(DbError::NotFound(_))
+            ));
+            db.delete_widget_modifier("b", "m2", ChangeSource::Api)
+                .unwrap();
+            assert_eq!(db.get_modifiers().unwrap().len(), 1);
+        }
+
+        #[test]
+        fn test_update_widget_modifier_validates() {
+            let mut db = Database::from(true).unwrap();
+            let scrape = WidgetModifier::new(
+                NanoId("w".to_string()),
+                Modifier::Scrape {
+                    modifier_id: NanoId("s".to_string()),
+                    selector: ".price".to_string(),
+                },
+            );
+            db.insert_widget_modifier(scrape, ChangeSource::Api)
+                .unwrap();
+            db.record_modifier_run("s", 1000).unwrap();
+            db.record_modifier_result("s", false, 1000).unwrap();
+
+            let updated = db
+                .update_widget_modifier(
+                    "w",
+                    "s",
+                    &UpdateModifierRequest {
+                        selector: Some("#main > span".to_string()),
+                        ..Default::default()
+                    },
+                    ChangeSource::Api,
+                )
+                .unwrap();
+            assert_eq!(
+                updated.modifier_type,
+                Modifier::Scrape {
+                    modifier_id: NanoId("s".to_string()),
+                    selector: "#main > span".to_string(),
+                }
+            );
+            // Runs again right away with the new selector
+            assert_eq!(updated.last_run_at, None);
+            assert_eq!(updated.consecutive_failures, 0);
+
+            for invalid in [
+                UpdateModifierRequest::default(),
+                UpdateModifierRequest {
+                    selector: Some("div[".to_string()),
+                    ..Default::default()
+                },
+                UpdateModifierRequest {
+                    interval_sec: Some(30),
+                    ..Default::default()
+                },
+            ] {
+                assert!(matches!(
+                    db.update_widget_modifier("w", "s", &invalid, ChangeSource::Api),
+                    Err(DbError::InvalidInput(_))
+                ));
+            }
+            let zero_interval = WidgetModifier::new(
+                NanoId("w".to_string()),
+                Modifier::Refresh {
+                    modifier_id: NanoId("r".to_string()),
+                    interval_sec: 0,
+                },
+            );
+            assert!(matches!(
+                db.insert_widget_modifier(zero_interval, ChangeSource::Api),
+                Err(DbError::InvalidInput(_))
+            ));
+        }
+
+        #[test]
+        fn test_upsert_keeps_modifiers() {
+            let mut db = Database::from(true).unwrap();
+            let widget = WidgetConfiguration::new().with_widget_id(NanoId("w".to_string()));
+            db.upsert_widget_configuration(widget.clone(), ChangeSource::App)
+                .unwrap();
+            db.insert_widget_modifier(
+                WidgetModifier::new(
+                    NanoId("w".to_string()),
+                    Modifier::Refresh {
+                        modifier_id: NanoId("m".to_string()),
+                        interval_sec: 30,
+                    },
+                ),
+                ChangeSource::Api,
+            )
+            .unwrap();
+
+            db.upsert_widget_configuration(
+                widget.with_title("Controls".to_string()),
+                ChangeSource::App,
+            )
+            .unwrap();
+            let widgets = db.get_configuration().unwrap();
+            assert_eq!(widgets.len(), 1);
+            assert_eq!(widgets[0].title, "Controls");
+            assert_eq!(db.get_widget_modifier("w").unwrap().len(), 1);
+            assert_eq!(db.list_revisions("w").unwrap().len(), 3);
+        }
+
+        #[test]
+        fn test_created_widget_needs_valid_modifiers() {
+            let mut db = Database::from(true).unwrap();
+            let widget = WidgetConfiguration::new().with_widget_id(NanoId("w".to_string()));
+            let scrape = |selector: &str| Modifier::Scrape {
+                modifier_id: NanoId(format!("s{}", selector.len())),
+                selector: selector.to_string(),
+            };
+
+            // Nothing is saved when one of the modifiers is invalid
+            assert!(matches!(
+                db.create_widget_with_modifiers(
+                    &widget,
+                    &[scrape(".price"), scrape("div[")],
+                    ChangeSource::Api
+                ),
+                Err(DbError::InvalidInput(_))
+            ));
+            assert!(db.get_configuration().unwrap().is_empty());
+            assert!(db.get_modifiers().unwrap().is_empty());
+
+            db.create_widget_with_modifiers(&widget, &[scrape(".price")], ChangeSource::Api)
+                .unwrap();
+            assert_eq!(db.get_configuration().unwrap().len(), 1);
+            assert_eq!(db.get_widget_modifier("w").unwrap().len(), 1);
+        }
+
         #[test]
         fn test_widget_configuration_roundtrip() {
             let mut db = Database::from(true).unwrap();
@@ -601,7 +1344,7 @@ pub mod db {
                     height: 100,
                 },
             };
-            db.insert_widget_configuration(vec![widget_configuration])
+            db.insert_widget_configuration(vec![widget_configuration], ChangeSource::Api)
                 .unwrap();
             let configurations = db.get_configuration().unwrap();
             assert_eq!(configurations.len(), 1);
@@ -615,5 +1358,343 @@ pub mod db {
             );
             assert_eq!(configurations[0].level, Level::Normal);
         }
+
+        /// Creates a database the way builds before the migration chain left it: only the
+        /// initial schema, tracked as version 1.
+        fn legacy_connection() -> Connection {
+            let conn = Connection::open_in_memory().unwrap();
+            conn.execute_batch(include_str!("../migrations/20240318000000_initial.sql"))
+                .unwrap();
+            conn.pragma_update(None, "user_version", 1).unwrap();
+            conn.execute(
+                WidgetConfiguration::get_insert_sql(),
+                [
+                    "w1",
+                    "Legacy",
+                    r#"{"type":"url","content":{"url":"https://example.com"}}"#,
+                    r#""normal""#,
+                    "0",
+                    "1",
+                    "1",
+                    r#"{"x":10,"y":20,"width":300,"height":400}"#,
+                ],
+            )
+            .unwrap();
+            conn.execute(
+                WidgetConfiguration::get_insert_sql(),
+                [
+                    "w2",
+                    "Broken bounds",
+                    r#"{"type":"url","content":{"url":"https://example.org"}}"#,
+                    r#""normal""#,
+                    "0",
+                    "0",
+                    "1",
+                    "",
+                ],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO modifiers (widget_id, modifier_type) VALUES (?, ?)",
+                [
+                    "w1",
+                    r#"{"type":"refresh","content":{"modifier_id":"m1","interval_sec":30}}"#,
+                ],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO scraped_data (widget_id, value, error, timestamp) VALUES (?, ?, ?, ?)",
+                ["w1", "42", "", "1742256000000"],
+            )
+            .unwrap();
+            conn
+        }
+
+        #[test]
+        fn test_corrupt_rows_are_skipped() {
+            let mut db = Database::from(true).unwrap();
+            db.insert_widget_configuration(
+                vec![
+                    WidgetConfiguration::new().with_widget_id(NanoId(String::from("good"))),
+                    WidgetConfiguration::new().with_widget_id(NanoId(String::from("bad"))),
+                ],
+                ChangeSource::Api,
+            )
+            .unwrap();
+            db.conn
+                .execute(
+                    "UPDATE widgets SET widget_type = 'not json' WHERE widget_id = 'bad'",
+                    [],
+                )
+                .unwrap();
+
+            let widgets = db.get_configuration().unwrap();
+            assert_eq!(widgets.len(), 1);
+            assert_eq!(widgets[0].widget_id, NanoId(String::from("good")));
+            assert!(matches!(
+                db.get_widget_configuration_by_id("bad"),
+                Err(DbError::CorruptRow {
+                    table: "widgets",
+                    ..
+                })
+            ));
+            assert!(matches!(
+                db.get_widget_configuration_by_id("missing"),
+                Err(DbError::NotFound(_))
+            ));
+        }
+
+        #[test]
+        fn test_migrations_are_valid() {
+            migrations().validate().unwrap();
+        }
+
+        #[test]
+        fn test_upgrade_legacy_database_keeps_data() {
+            let conn = legacy_connection();
+            // Only the whole JSON used to be unique, not the id inside it
+            conn.execute(
+                "INSERT INTO modifiers (widget_id, modifier_type) VALUES (?, ?)",
+                [
+                    "w2",
+                    r#"{"type":"refresh","content":{"modifier_id":"m1","interval_sec":60}}"#,
+                ],
+            )
+            .unwrap();
+            // Monitor information saved after the settings used to shadow them
+            conn.execute(
+                "INSERT INTO config (json) VALUES (?), (?)",
+                [
+                    r#"{"show_tray_icon":false,"email":"me@example.com","licence_key":"","machine_id":"abc","licence_tier":"Free"}"#,
+                    r#"[{"name":"Built-in","x":0,"y":0,"width":1440,"height":900}]"#,
+                ],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO app_ui_state (json) VALUES (?)",
+                [r#"{"messages":["Licence check successful","Licence check failed"]}"#],
+            )
+            .unwrap();
+            let db = Database::from_connection(conn).unwrap();
+
+            assert_eq!(
+                db.get_app_ui_state().unwrap().messages,
+                vec!["Licence check successful", "Licence check failed"]
+            );
+            let settings = db.get_settings().unwrap();
+            assert!(!settings.show_tray_icon);
+            assert_eq!(settings.email, "me@example.com");
+            assert_eq!(
+                settings.trash_retention_days,
+                widget_types::DEFAULT_TRASH_RETENTION_DAYS
+            );
+            let config_rows: usize = db
+                .conn
+                .query_row("SELECT COUNT(*) FROM config", [], |row| row.get(0))
+                .unwrap();
+            assert_eq!(config_rows, 1);
+
+            let widgets = db.get_configuration().unwrap();
+            assert_eq!(widgets.len(), 2);
+            assert_eq!(
+                widgets[0].bounds,
+                WidgetBounds {
+                    x: 10,
+                    y: 20,
+                    width: 300,
+                    height: 400
+                }
+            );
+            assert_eq!(
+                widgets[1].bounds,
+                WidgetBounds {
+                    x: DEFAULT_WIDGET_X,
+                    y: DEFAULT_WIDGET_Y,
+                    width: DEFAULT_WIDGET_WIDTH,
+                    height: DEFAULT_WIDGET_HEIGHT
+                }
+            );
+
+            let modifier_id: String = db
+                .conn
+                .query_row(
+                    "SELECT modifier_id FROM modifiers WHERE widget_id = 'w1'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(modifier_id, "m1");
+            let reassigned = db.get_widget_modifier("w2").unwrap();
+            assert_eq!(reassigned.len(), 1);
+            assert_ne!(reassigned[0].modifier_type.modifier_id().0, "m1");
+            assert!(reassigned[0].enabled);
+
+            let data = db.get_data().unwrap();
+            assert_eq!(data.len(), 1);
+            assert_eq!(data[0].value, "42");
+            assert_eq!(data[0].numeric_value, Some(42.0));
+
+            let revisions = db.list_revisions("w1").unwrap();
+            assert_eq!(revisions.len(), 1);
+            assert_eq!(revisions[0].widget.bounds, widgets[0].bounds);
+            assert_eq!(revisions[0].modifiers.len(), 1);
+        }
+
+        #[test]
+        fn test_insert_data_parses_value() {
+            let db = Database::from(true).unwrap();
+            for (value, error) in [
+                ("$1,234.50", None),
+                ("Sold out", None),
+                ("12", Some("timeout")),
+            ] {
+                db.insert_data(ScrapedData {
+                    error: error.map(str::to_string),
+                    ..scraped("w", value, 1_700_000_000_000)
+                })
+                .unwrap();
+            }
+
+            let parsed = db
+                .get_data()
+                .unwrap()
+                .into_iter()
+                .map(|d| (d.numeric_value, d.unit))
+                .collect::<Vec<_>>();
+            assert_eq!(
+                parsed,
+                vec![
+                    (Some(1234.5), Some("$".to_string())),
+                    (None, None),
+                    (None, None)
+                ]
+            );
+        }
+
+        #[test]
+        fn test_upgrade_untracked_database() {
+            // Builds that predate `rusqlite_migration` never set user_version at all
+            let conn = legacy_connection();
+            conn.pragma_update(None, "user_version", 0).unwrap();
+            let db = Database::from_connection(conn).unwrap();
+
+            assert_eq!(db.get_configuration().unwrap().len(), 2);
+            assert_eq!(db.get_all_widget_modifiers().unwrap().len(), 1);
+        }
+
+        #[test]
+        fn test_update_widget_bounds_persists() {
+            let mut db = Database::from(true).unwrap();
+            db.insert_widget_configuration(
+                vec![WidgetConfiguration::new().with_widget_id(NanoId(String::from("1")))],
+                ChangeSource::Api,
+            )
+            .unwrap();
+            let bounds = WidgetBounds {
+                x: 5,
+                y: 6,
+                width: 700,
+                height: 800,
+            };
+            db.update_widget_bounds(String::from("1"), bounds.clone(), ChangeSource::Api)
+                .unwrap();
+            assert_eq!(
+                db.get_widget_configuration_by_id("1").unwrap().bounds,
+                bounds
+            );
+
+            db.update_widget_position_property("1", "width", "640", ChangeSource::Api)
+                .unwrap();
+            assert_eq!(
+                db.get_widget_configuration_by_id("1").unwrap().bounds.width,
+                640
+            );
+        }
+
+        #[test]
+        fn test_update_widget_changes_only_given_fields() {
+            let mut db = Database::from(true).unwrap();
+            let original = WidgetConfiguration::new().with_widget_id(NanoId(String::from("1")));
+            db.insert_widget_configuration(vec![original.clone()], ChangeSource::Api)
+                .unwrap();
+
+            let updated = db
+                .update_widget(
+                    "1",
+                    &UpdateWidgetRequest {
+                        title: Some("Prices".to_string()),
+                        url: Some(" https://example.com ".to_string()),
+                        level: Some(Level::AlwaysOnTop),
+                        ..Default::default()
+                    },
+                    ChangeSource::Api,
+                )
+                .unwrap();
+            assert_eq!(db.get_widget_configuration_by_id("1").unwrap(), updated);
+            assert_eq!(updated.title, "Prices");
+            assert_eq!(
+                updated.widget_type,
+                WidgetType::Url(widget_types::UrlConfiguration {
+                    url: "https://example.com".to_string()
+                })
+            );
+            assert_eq!(updated.level, Level::AlwaysOnTop);
+            assert_eq!(updated.bounds, original.bounds);
+            assert_eq!(updated.transparent, original.transparent);
+            assert_eq!(db.list_revisions("1").unwrap().len(), 2);
+
+            for invalid in [
+                UpdateWidgetRequest::default(),
+                UpdateWidgetRequest {
+                    html: Some("<p>hi</p>".to_string()),
+                    ..Default::default()
+                },
+                UpdateWidgetRequest {
+                    url: Some(" ".to_string()),
+                    ..Default::default()
+                },
+                UpdateWidgetRequest {
+                    bounds: Some(WidgetBounds {
+                        x: 0,
+                        y: 0,
+                        width: 0,
+                        height: 10,
+                    }),
+                    ..Default::default()
+                },
+            ] {
+                assert!(matches!(
+                    db.update_widget("1", &invalid, ChangeSource::Api),
+                    Err(DbError::InvalidInput(_))
+                ));
+            }
+            assert!(matches!(
+                db.update_widget(
+                    "missing",
+                    &UpdateWidgetRequest {
+                        transparent: Some(true),
+                        ..Default::default()
+                    },
+                    ChangeSource::Api
+                ),
+                Err(DbError::NotFound(_))
+            ));
+        }
+
+        #[test]
+        fn test_reset_reapplies_migrations() {
+            let mut db = Database::from_connection(legacy_connection()).unwrap();
+            db.reset().unwrap();
+            assert!(db.get_configuration().unwrap().is_empty());
+            let user_version = |db: &Database| -> usize {
+                db.conn
+                    .pragma_query_value(None, "user_version", |row| row.get(0))
+                    .unwrap()
+            };
+            assert_eq!(
+                user_version(&db),
+                user_version(&Database::from(true).unwrap())
+            );
+        }
     }
 }
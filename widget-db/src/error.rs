@@ -0,0 +1,55 @@
+pub mod error {
+    use thiserror::Error;
+
+    #[derive(Debug, Error)]
+    pub enum DbError {
+        #[error("Sqlite error: {0}")]
+        Sqlite(#[from] rusqlite::Error),
+
+        #[error("Migration error: {0}")]
+        Migration(#[from] rusqlite_migration::Error),
+
+        #[error("Corrupt row {id} in {table}: {reason}")]
+        CorruptRow {
+            table: &'static str,
+            id: i64,
+            reason: String,
+        },
+
+        #[error("Not found: {0}")]
+        NotFound(String),
+
+        #[error("Invalid input: {0}")]
+        InvalidInput(String),
+
+        #[error("Conflict: {0}")]
+        Conflict(String),
+
+        #[error("Serialization error: {0}")]
+        Serialization(#[from] serde_json::Error),
+
+        #[error("IO error: {0}")]
+        Io(#[from] std::io::Error),
+
+        /// The blocking task running a query panicked or was cancelled.
+        #[error("Task error: {0}")]
+        Task(String),
+    }
+
+    impl DbError {
+        /// Whether SQLite refused the file itself, as opposed to a failing statement.
+        pub fn is_corruption(&self) -> bool {
+            let err = match self {
+                DbError::Sqlite(err) => err,
+                DbError::Migration(rusqlite_migration::Error::RusqliteError { err, .. }) => err,
+                _ => return false,
+            };
+            matches!(
+                err.sqlite_error_code(),
+                Some(rusqlite::ErrorCode::DatabaseCorrupt | rusqlite::ErrorCode::NotADatabase)
+            )
+        }
+    }
+
+    pub type DbResult<T> = Result<T, DbError>;
+}
@@ -0,0 +1,238 @@
+pub mod events {
+    use crate::{
+        db::db::Database,
+        error::error::{DbError, DbResult},
+        service::service::DbService,
+    };
+
+    use futures::{stream, Stream};
+    use log::debug;
+    use tokio::sync::broadcast::{self, error::RecvError};
+    use widget_types::{DbChange, LiveEvent, ScrapedData};
+
+    /// Reads a widget that may have been trashed or purged since the change was published.
+    fn still_there<T>(result: DbResult<T>) -> DbResult<Option<T>> {
+        match result {
+            Ok(value) => Ok(Some(value)),
+            Err(DbError::NotFound(_)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    impl Database {
+        /// What to tell `GET /events` listeners about a committed change, `None` for changes
+        /// they don't hear about.
+        pub fn live_event(&self, change: DbChange) -> DbResult<Option<LiveEvent>> {
+            Ok(match change {
+                DbChange::WidgetCreated { widget_id } => {
+                    still_there(self.get_widget_configuration_by_id(&widget_id))?
+                        .map(LiveEvent::WidgetCreated)
+                }
+                DbChange::WidgetSaved { widget_id } => {
+                    still_there(self.get_widget_configuration_by_id(&widget_id))?
+                        .map(LiveEvent::WidgetUpdated)
+                }
+                DbChange::WidgetMoved { widget_id } => {
+                    still_there(self.get_widget_configuration_by_id(&widget_id))?.map(|widget| {
+                        LiveEvent::WidgetMoved {
+                            widget_id,
+                            bounds: widget.bounds,
+                        }
+                    })
+                }
+                DbChange::VisibilityChanged { widget_id, is_open } => {
+                    Some(LiveEvent::VisibilityChanged {
+                        widget_id,
+                        visible: is_open,
+                    })
+                }
+                DbChange::WidgetDeleted { widget_id } => {
+                    Some(LiveEvent::WidgetDeleted { widget_id })
+                }
+                DbChange::DataInserted { id, .. } => {
+                    still_there(self.get_data_by_id(id))?.map(|data| {
+                        // Successful scrapes are stored with an empty error
+                        if data.error.as_deref().is_some_and(|e| !e.is_empty()) {
+                            LiveEvent::ScrapeError(data)
+                        } else {
+                            LiveEvent::ScrapeResult(data)
+                        }
+                    })
+                }
+                DbChange::SettingsChanged => Some(LiveEvent::SettingsChanged(self.get_settings()?)),
+                DbChange::Reloaded => Some(LiveEvent::Resync),
+                DbChange::WidgetPurged { .. }
+                | DbChange::ModifiersChanged { .. }
+                | DbChange::TagsChanged { .. }
+                | DbChange::LayoutsChanged
+                | DbChange::MessageAdded { .. }
+                | DbChange::MessageAcknowledged { .. } => None,
+            })
+        }
+    }
+
+    /// Every `LiveEvent` from now on, read from the pool as changes come in. Ends when the
+    /// database goes away.
+    pub fn live_events(db: DbService) -> impl Stream<Item = LiveEvent> {
+        stream::unfold((db.subscribe(), db), |(mut changes, db)| async move {
+            loop {
+                let event = match changes.recv().await {
+                    Ok(change) => match db.read(move |db| db.live_event(change)).await {
+                        Ok(Some(event)) => event,
+                        Ok(None) => continue,
+                        Err(e) => {
+                            debug!("Skipping an event that couldn't be read: {:?}", e);
+                            continue;
+                        }
+                    },
+                    Err(RecvError::Lagged(_)) => LiveEvent::Resync,
+                    Err(RecvError::Closed) => return None,
+                };
+                return Some((event, (changes, db)));
+            }
+        })
+    }
+
+    /// The next `count` scrape results stored for the widget after `changes` was subscribed, in
+    /// the order they were stored, `None` if the database goes away first. Subscribe before
+    /// asking for the scrape so no result can slip past.
+    pub async fn next_scrapes(
+        changes: &mut broadcast::Receiver<DbChange>,
+        db: &DbService,
+        widget_id: &str,
+        count: usize,
+    ) -> DbResult<Option<Vec<ScrapedData>>> {
+        let mut scraped = Vec::with_capacity(count);
+        while scraped.len() < count {
+            match changes.recv().await {
+                Ok(DbChange::DataInserted { widget_id: w, id }) if w == widget_id => {
+                    scraped.push(db.read(move |db| db.get_data_by_id(id)).await?);
+                }
+                Ok(_) => continue,
+                Err(RecvError::Lagged(skipped)) => {
+                    debug!("Missed {} changes while waiting for a scrape", skipped);
+                    continue;
+                }
+                Err(RecvError::Closed) => return Ok(None),
+            }
+        }
+        Ok(Some(scraped))
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::fixtures::scraped;
+        use futures::StreamExt;
+        use widget_types::{ChangeSource, NanoId, WidgetBounds, WidgetConfiguration};
+
+        #[tokio::test]
+        async fn test_live_events_follow_changes() {
+            let service = DbService::in_memory().unwrap();
+            let events = live_events(service.clone());
+            futures::pin_mut!(events);
+
+            let bounds = WidgetBounds {
+                x: 5,
+                y: 6,
+                width: 100,
+                height: 50,
+            };
+            service
+                .write(move |db| {
+                    db.insert_widget_configuration(
+                        vec![WidgetConfiguration::new().with_widget_id(NanoId("w".to_string()))],
+                        ChangeSource::Api,
+                    )?;
+                    db.update_widget_bounds("w".to_string(), bounds, ChangeSource::Api)?;
+                    db.update_widget_open_state(NanoId("w".to_string()), false)?;
+                    for error in [None, Some("timed out".to_string())] {
+                        db.insert_data(ScrapedData {
+                            error,
+                            ..scraped("w", "42", 1_700_000_000_000)
+                        })?;
+                    }
+                    db.set_widget_tags("w", &["quiet".to_string()])?;
+                    db.set_setting("trash_retention_days", 3.into(), None)
+                })
+                .await
+                .unwrap();
+
+            let mut received = vec![];
+            for _ in 0..6 {
+                received.push(events.next().await.unwrap());
+            }
+            // Events read the widget when they go out, and trashed widgets can't be read
+            service
+                .write(|db| db.delete_widget("w", ChangeSource::Api))
+                .await
+                .unwrap();
+            received.push(events.next().await.unwrap());
+            assert!(
+                matches!(&received[0], LiveEvent::WidgetCreated(widget) if widget.widget_id.0 == "w")
+            );
+            assert_eq!(
+                received[1],
+                LiveEvent::WidgetMoved {
+                    widget_id: "w".to_string(),
+                    bounds: WidgetBounds {
+                        x: 5,
+                        y: 6,
+                        width: 100,
+                        height: 50,
+                    },
+                }
+            );
+            assert_eq!(
+                received[2],
+                LiveEvent::VisibilityChanged {
+                    widget_id: "w".to_string(),
+                    visible: false,
+                }
+            );
+            assert!(
+                matches!(&received[3], LiveEvent::ScrapeResult(data) if data.numeric_value == Some(42.0))
+            );
+            assert!(
+                matches!(&received[4], LiveEvent::ScrapeError(data) if data.error.as_deref() == Some("timed out"))
+            );
+            assert!(
+                matches!(&received[5], LiveEvent::SettingsChanged(settings) if settings.trash_retention_days == 3)
+            );
+            assert_eq!(
+                received[6],
+                LiveEvent::WidgetDeleted {
+                    widget_id: "w".to_string()
+                }
+            );
+        }
+
+        #[tokio::test]
+        async fn test_next_scrapes_waits_for_the_widget() {
+            let service = DbService::in_memory().unwrap();
+            let mut changes = service.subscribe();
+            service
+                .write(move |db| {
+                    db.insert_data(scraped("w", "1", 1_700_000_000_000))?;
+                    db.insert_data(scraped("other", "2", 1_700_000_000_000))?;
+                    db.set_setting("trash_retention_days", 3.into(), None)?;
+                    db.insert_data(scraped("w", "3", 1_700_000_000_000))?;
+                    db.insert_data(scraped("w", "4", 1_700_000_000_000))
+                })
+                .await
+                .unwrap();
+
+            // Stops at the count, the last result is left for the next wait
+            let data = next_scrapes(&mut changes, &service, "w", 2)
+                .await
+                .unwrap()
+                .unwrap();
+            let values = data
+                .iter()
+                .map(|data| data.value.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(values, vec!["1", "3"]);
+            assert_eq!(data[1].numeric_value, Some(3.0));
+        }
+    }
+}
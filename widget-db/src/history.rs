@@ -0,0 +1,313 @@
+pub mod history {
+    use crate::{
+        db::db::{scraped_data_from_row, Database, SCRAPED_DATA_COLUMNS},
+        error::error::DbResult,
+    };
+
+    use rusqlite::{params, OptionalExtension};
+    use std::{fmt, str::FromStr};
+    use widget_types::{AggregateBucket, HistoryPage, ScrapedData};
+
+    /// Scrape timestamps are the `Date.now()` milliseconds reported by the webview.
+    pub(crate) const TIMESTAMP_MS: &str = "CAST(timestamp AS INTEGER)";
+
+    /// Position in a widget's history, encoded as `<timestamp>:<id>` of the last row returned.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct HistoryCursor {
+        pub timestamp: i64,
+        pub id: i64,
+    }
+
+    impl fmt::Display for HistoryCursor {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "{}:{}", self.timestamp, self.id)
+        }
+    }
+
+    impl FromStr for HistoryCursor {
+        type Err = String;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            let (timestamp, id) = s
+                .split_once(':')
+                .ok_or_else(|| format!("Invalid cursor: {}", s))?;
+            Ok(Self {
+                timestamp: timestamp
+                    .parse()
+                    .map_err(|_| format!("Invalid cursor: {}", s))?,
+                id: id.parse().map_err(|_| format!("Invalid cursor: {}", s))?,
+            })
+        }
+    }
+
+    impl Database {
+        /// Returns up to `limit` rows of a widget's history between `from` and `to` (inclusive,
+        /// in milliseconds), newest first, continuing after `cursor` if one is given.
+        pub fn get_widget_history(
+            &self,
+            widget_id: &str,
+            from: Option<i64>,
+            to: Option<i64>,
+            limit: usize,
+            cursor: Option<HistoryCursor>,
+        ) -> DbResult<HistoryPage> {
+            let mut stmt = self.conn.prepare(&format!(
+                r#"
+                SELECT {columns}
+                FROM scraped_data
+                WHERE widget_id = ?1
+                  AND (?2 IS NULL OR {ts} >= ?2)
+                  AND (?3 IS NULL OR {ts} <= ?3)
+                  AND (?4 IS NULL OR {ts} < ?4 OR ({ts} = ?4 AND id < ?5))
+                ORDER BY {ts} DESC, id DESC
+                LIMIT ?6
+                "#,
+                columns = SCRAPED_DATA_COLUMNS,
+                ts = TIMESTAMP_MS
+            ))?;
+            let mut items = stmt
+                .query_map(
+                    params![
+                        widget_id,
+                        from,
+                        to,
+                        cursor.map(|c| c.timestamp),
+                        cursor.map(|c| c.id),
+                        // one extra row tells us whether there is another page
+                        limit as i64 + 1,
+                    ],
+                    scraped_data_from_row,
+                )?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+
+            let next_cursor = if items.len() > limit {
+                items.truncate(limit);
+                items.last().map(|last| {
+                    HistoryCursor {
+                        timestamp: last.timestamp.parse().unwrap_or_default(),
+                        id: last.id,
+                    }
+                    .to_string()
+                })
+            } else {
+                None
+            };
+
+            Ok(HistoryPage { items, next_cursor })
+        }
+
+        pub fn get_latest_widget_data(&self, widget_id: &str) -> DbResult<Option<ScrapedData>> {
+            let latest = self
+                .conn
+                .query_row(
+                    &format!(
+                        r#"
+                        SELECT {}
+                        FROM scraped_data
+                        WHERE widget_id = ?
+                        ORDER BY {} DESC, id DESC
+                        LIMIT 1
+                        "#,
+                        SCRAPED_DATA_COLUMNS, TIMESTAMP_MS
+                    ),
+                    [widget_id],
+                    scraped_data_from_row,
+                )
+                .optional()?;
+            Ok(latest)
+        }
+
+        /// The most recent row of every widget that has scraped anything.
+        pub fn get_latest_data_per_widget(&self) -> DbResult<Vec<ScrapedData>> {
+            let mut stmt = self.conn.prepare(&format!(
+                r#"
+                SELECT {columns}
+                FROM (
+                    SELECT {columns}, ROW_NUMBER() OVER (
+                        PARTITION BY widget_id
+                        ORDER BY {ts} DESC, id DESC
+                    ) AS position
+                    FROM scraped_data
+                )
+                WHERE position = 1
+                ORDER BY widget_id
+                "#,
+                columns = SCRAPED_DATA_COLUMNS,
+                ts = TIMESTAMP_MS
+            ))?;
+            let rows = stmt.query_map([], scraped_data_from_row)?;
+
+            Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+        }
+
+        /// Buckets a widget's numeric values into `interval_ms` wide intervals. Rows without a
+        /// `numeric_value` are left out. Downsampled ranges are counted through their rollups,
+        /// each of which lands whole in the interval its hour or day starts in.
+        pub fn get_widget_aggregates(
+            &self,
+            widget_id: &str,
+            from: Option<i64>,
+            to: Option<i64>,
+            interval_ms: i64,
+        ) -> DbResult<Vec<AggregateBucket>> {
+            let mut stmt = self.conn.prepare(&format!(
+                r#"
+                WITH points AS (
+                    SELECT {ts} AS at, id, 1 AS count, numeric_value AS total,
+                           numeric_value AS min_value, numeric_value AS max_value,
+                           numeric_value AS first_value, {ts} AS first_at,
+                           numeric_value AS last_value, {ts} AS last_at
+                    FROM scraped_data
+                    WHERE widget_id = ?1
+                      AND numeric_value IS NOT NULL
+                      AND (?2 IS NULL OR {ts} >= ?2)
+                      AND (?3 IS NULL OR {ts} <= ?3)
+                    UNION ALL
+                    SELECT bucket_start, 0, count, total, min_value, max_value,
+                           first_value, first_at, last_value, last_at
+                    FROM scraped_rollups
+                    WHERE widget_id = ?1
+                      AND (?2 IS NULL OR bucket_start >= ?2)
+                      AND (?3 IS NULL OR bucket_start <= ?3)
+                ),
+                bucketed AS (
+                    SELECT (at / ?4) * ?4 AS bucket_start, *,
+                           ROW_NUMBER() OVER (
+                               PARTITION BY at / ?4 ORDER BY first_at ASC, id ASC
+                           ) AS from_start,
+                           ROW_NUMBER() OVER (
+                               PARTITION BY at / ?4 ORDER BY last_at DESC, id DESC
+                           ) AS from_end
+                    FROM points
+                )
+                SELECT bucket_start,
+                       SUM(count),
+                       MIN(min_value),
+                       MAX(max_value),
+                       SUM(total) / SUM(count),
+                       MAX(CASE WHEN from_start = 1 THEN first_value END),
+                       MAX(CASE WHEN from_end = 1 THEN last_value END)
+                FROM bucketed
+                GROUP BY bucket_start
+                ORDER BY bucket_start
+                "#,
+                ts = TIMESTAMP_MS
+            ))?;
+            let buckets = stmt.query_map(params![widget_id, from, to, interval_ms], |row| {
+                Ok(AggregateBucket {
+                    bucket_start: row.get::<_, i64>(0)?.to_string(),
+                    count: row.get(1)?,
+                    min: row.get(2)?,
+                    max: row.get(3)?,
+                    avg: row.get(4)?,
+                    first: row.get(5)?,
+                    last: row.get(6)?,
+                })
+            })?;
+
+            Ok(buckets.collect::<rusqlite::Result<Vec<_>>>()?)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::fixtures::scraped;
+
+        fn db_with_values(rows: &[(&str, i64, &str)]) -> Database {
+            let db = Database::from(true).unwrap();
+            for (widget_id, timestamp, value) in rows {
+                db.insert_data(scraped(widget_id, value, *timestamp))
+                    .unwrap();
+            }
+            db
+        }
+
+        #[test]
+        fn test_history_pagination() {
+            let db = db_with_values(&[
+                ("a", 1000, "1"),
+                ("a", 2000, "2"),
+                ("b", 2500, "x"),
+                ("a", 3000, "3"),
+                ("a", 4000, "4"),
+            ]);
+
+            let page = db.get_widget_history("a", None, None, 2, None).unwrap();
+            let values = page
+                .items
+                .iter()
+                .map(|d| d.value.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(values, vec!["4", "3"]);
+
+            let cursor = page.next_cursor.unwrap().parse().unwrap();
+            let page = db
+                .get_widget_history("a", None, None, 2, Some(cursor))
+                .unwrap();
+            let values = page
+                .items
+                .iter()
+                .map(|d| d.value.as_str())
+                .collect::<Vec<_>>();
+            assert_eq!(values, vec!["2", "1"]);
+            assert_eq!(page.next_cursor, None);
+
+            let page = db
+                .get_widget_history("a", Some(2000), Some(3000), 10, None)
+                .unwrap();
+            assert_eq!(page.items.len(), 2);
+        }
+
+        #[test]
+        fn test_latest_values() {
+            let db = db_with_values(&[("a", 1000, "1"), ("b", 2000, "2"), ("a", 3000, "3")]);
+
+            assert_eq!(db.get_latest_widget_data("b").unwrap().unwrap().value, "2");
+            assert_eq!(db.get_latest_widget_data("c").unwrap(), None);
+
+            let latest = db.get_latest_data_per_widget().unwrap();
+            let values = latest
+                .iter()
+                .map(|d| (d.widget_id.as_str(), d.value.as_str()))
+                .collect::<Vec<_>>();
+            assert_eq!(values, vec![("a", "3"), ("b", "2")]);
+        }
+
+        #[test]
+        fn test_aggregates() {
+            let db = db_with_values(&[
+                ("a", 0, "4"),
+                ("a", 10, "2"),
+                ("a", 20, "not a number"),
+                ("a", 30, "6"),
+                ("a", 100, "1"),
+            ]);
+
+            let buckets = db.get_widget_aggregates("a", None, None, 100).unwrap();
+            assert_eq!(
+                buckets,
+                vec![
+                    AggregateBucket {
+                        bucket_start: "0".to_string(),
+                        count: 3,
+                        min: 2.0,
+                        max: 6.0,
+                        avg: 4.0,
+                        first: 4.0,
+                        last: 6.0,
+                    },
+                    AggregateBucket {
+                        bucket_start: "100".to_string(),
+                        count: 1,
+                        min: 1.0,
+                        max: 1.0,
+                        avg: 1.0,
+                        first: 1.0,
+                        last: 1.0,
+                    },
+                ]
+            );
+        }
+    }
+}
@@ -0,0 +1,197 @@
+pub mod layouts {
+    use crate::{
+        db::db::{decode, skip_corrupt, Database},
+        error::error::{DbError, DbResult},
+    };
+
+    use rusqlite::{OptionalExtension, Result as SqliteResult, Row};
+    use widget_types::{DbChange, LayoutEntry, LayoutPreset};
+
+    pub const MAX_LAYOUT_NAME_LENGTH: usize = 64;
+
+    /// The name a preset is stored under. Every lookup goes through this too, so a name is
+    /// found however it was padded when it was saved.
+    fn validate_layout_name(name: &str) -> DbResult<String> {
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(DbError::InvalidInput(
+                "layout names can't be empty".to_string(),
+            ));
+        }
+        if name.chars().count() > MAX_LAYOUT_NAME_LENGTH {
+            return Err(DbError::InvalidInput(format!(
+                "layout name {:?} is longer than {} characters",
+                name, MAX_LAYOUT_NAME_LENGTH
+            )));
+        }
+        Ok(name.to_string())
+    }
+
+    fn entry_from_row(row: &Row) -> SqliteResult<DbResult<LayoutEntry>> {
+        let id: i64 = row.get(0)?;
+        let widget_id: String = row.get(1)?;
+        let bounds: String = row.get(2)?;
+        let is_open: bool = row.get(3)?;
+        let level: String = row.get(4)?;
+
+        Ok(
+            decode("layout_widgets", id, "bounds", &bounds).and_then(|bounds| {
+                Ok(LayoutEntry {
+                    widget_id,
+                    bounds,
+                    is_open,
+                    level: decode("layout_widgets", id, "level", &level)?,
+                })
+            }),
+        )
+    }
+
+    impl Database {
+        /// Saves where every widget outside the trash is right now as `name`, replacing a
+        /// preset of the same name.
+        pub fn save_layout(&mut self, name: &str) -> DbResult<LayoutPreset> {
+            let name = validate_layout_name(name)?;
+            let tx = self.conn.transaction()?;
+            tx.execute(
+                "INSERT OR REPLACE INTO layouts (name, created_at) VALUES (?, ?)",
+                rusqlite::params![name, jiff::Timestamp::now().as_millisecond()],
+            )?;
+            tx.execute("DELETE FROM layout_widgets WHERE layout_name = ?", [&name])?;
+            tx.execute(
+                r#"
+                INSERT INTO layout_widgets (layout_name, widget_id, bounds, is_open, level)
+                SELECT ?, widget_id, bounds, is_open, level FROM widgets
+                WHERE deleted_at IS NULL
+                "#,
+                [&name],
+            )?;
+            tx.commit()?;
+            self.publish(DbChange::LayoutsChanged);
+            self.get_layout(&name)
+        }
+
+        pub fn get_layout(&self, name: &str) -> DbResult<LayoutPreset> {
+            let name = validate_layout_name(name)?;
+            let created_at: i64 = self
+                .conn
+                .query_row(
+                    "SELECT created_at FROM layouts WHERE name = ?",
+                    [&name],
+                    |row| row.get(0),
+                )
+                .optional()?
+                .ok_or_else(|| DbError::NotFound(format!("layout {}", name)))?;
+
+            let mut stmt = self.conn.prepare(
+                r#"
+                SELECT l.rowid, l.widget_id, l.bounds, l.is_open, l.level FROM layout_widgets l
+                JOIN widgets w ON w.widget_id = l.widget_id
+                WHERE l.layout_name = ? AND w.deleted_at IS NULL
+                ORDER BY l.widget_id
+                "#,
+            )?;
+            let widgets = skip_corrupt(stmt.query_map([&name], entry_from_row)?)?;
+            Ok(LayoutPreset {
+                name,
+                created_at: created_at.to_string(),
+                widgets,
+            })
+        }
+
+        /// Every preset, by name.
+        pub fn list_layouts(&self) -> DbResult<Vec<LayoutPreset>> {
+            let names = {
+                let mut stmt = self
+                    .conn
+                    .prepare("SELECT name FROM layouts ORDER BY name")?;
+                let names = stmt
+                    .query_map([], |row| row.get::<_, String>(0))?
+                    .collect::<SqliteResult<Vec<_>>>()?;
+                names
+            };
+            names.iter().map(|name| self.get_layout(name)).collect()
+        }
+
+        pub fn delete_layout(&mut self, name: &str) -> DbResult<()> {
+            let name = validate_layout_name(name)?;
+            let tx = self.conn.transaction()?;
+            if tx.execute("DELETE FROM layouts WHERE name = ?", [&name])? == 0 {
+                return Err(DbError::NotFound(format!("layout {}", name)));
+            }
+            tx.execute("DELETE FROM layout_widgets WHERE layout_name = ?", [&name])?;
+            tx.commit()?;
+            self.publish(DbChange::LayoutsChanged);
+            Ok(())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use widget_types::{ChangeSource, Level, NanoId, WidgetBounds, WidgetConfiguration};
+
+        #[test]
+        fn test_save_and_load_layout() {
+            let mut db = Database::from(true).unwrap();
+            db.insert_widget_configuration(
+                vec![
+                    WidgetConfiguration::new().with_widget_id(NanoId("a".to_string())),
+                    WidgetConfiguration::new().with_widget_id(NanoId("b".to_string())),
+                ],
+                ChangeSource::Api,
+            )
+            .unwrap();
+            let laptop = WidgetBounds {
+                x: 10,
+                y: 20,
+                width: 300,
+                height: 200,
+            };
+            db.update_widget_bounds("a".to_string(), laptop.clone(), ChangeSource::Api)
+                .unwrap();
+            db.update_widget_level("a", &Level::AlwaysOnTop, ChangeSource::Api)
+                .unwrap();
+            db.update_widget_open_state(NanoId("a".to_string()), true)
+                .unwrap();
+            let saved = db.save_layout(" laptop ").unwrap();
+            assert_eq!(saved.name, "laptop");
+
+            // Later changes don't touch the preset
+            db.update_widget_bounds(
+                "a".to_string(),
+                WidgetBounds {
+                    x: 2000,
+                    ..laptop.clone()
+                },
+                ChangeSource::Api,
+            )
+            .unwrap();
+            let layout = db.get_layout("laptop").unwrap();
+            assert_eq!(layout, saved);
+            assert_eq!(db.get_layout(" laptop ").unwrap(), saved);
+            assert_eq!(
+                layout.widgets[0],
+                LayoutEntry {
+                    widget_id: "a".to_string(),
+                    bounds: laptop,
+                    is_open: true,
+                    level: Level::AlwaysOnTop,
+                }
+            );
+
+            // Trashed widgets are left out, and saving again replaces the preset
+            db.delete_widget("b", ChangeSource::Api).unwrap();
+            assert_eq!(db.get_layout("laptop").unwrap().widgets.len(), 1);
+            db.save_layout("laptop").unwrap();
+            assert_eq!(db.list_layouts().unwrap().len(), 1);
+
+            db.delete_layout("laptop ").unwrap();
+            assert!(matches!(db.get_layout("laptop"), Err(DbError::NotFound(_))));
+            assert!(matches!(
+                db.delete_layout("laptop"),
+                Err(DbError::NotFound(_))
+            ));
+            assert!(matches!(db.save_layout(" "), Err(DbError::InvalidInput(_))));
+        }
+    }
+}
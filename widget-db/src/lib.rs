@@ -1,12 +1,50 @@
 mod api;
+mod backup;
+mod bundle;
+mod changes;
 mod db;
 mod db_impl;
 mod deserializer;
+mod error;
+mod events;
+mod history;
+mod layouts;
+mod messages;
+mod parse;
+mod paths;
+mod query;
+mod recovery;
+mod retention;
+mod revisions;
+mod search;
+mod service;
+mod settings;
+mod tags;
+mod trash;
+mod webhooks;
 
 use std::{path::PathBuf, sync::Arc};
 
 use directories::ProjectDirs;
 use tokio::sync::Mutex;
 
-pub use api::api::run_api;
+pub use api::api::{router, run_api};
+pub use changes::changes::CHANGE_CHANNEL_CAPACITY;
 pub use db::db::Database;
+pub use error::error::{DbError, DbResult};
+pub use events::events::{live_events, next_scrapes};
+pub use history::history::HistoryCursor;
+pub use layouts::layouts::MAX_LAYOUT_NAME_LENGTH;
+pub use messages::messages::{MessageFilter, MAX_MESSAGES};
+pub use parse::parse::{parse_value, ParsedValue};
+pub use paths::paths::{resolve_profile, DATA_DIR_ENV, DEFAULT_PROFILE, PROFILE_ENV};
+pub use query::query::{
+    ColumnType, QueryColumn, QueryRequest, QueryResult, MAX_QUERY_ROWS, QUERY_TIME_LIMIT,
+    QUERY_VIEWS,
+};
+pub use recovery::recovery::RecoveryReport;
+pub use service::service::{DbService, DEFAULT_READERS};
+pub use tags::tags::MAX_TAG_LENGTH;
+pub use webhooks::webhooks::{
+    sign, DeliveryFilter, MAX_DELIVERIES, MAX_DELIVERY_ATTEMPTS, RETRY_DELAY, WEBHOOK_EVENTS,
+};
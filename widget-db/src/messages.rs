@@ -0,0 +1,226 @@
+pub mod messages {
+    use crate::{
+        db::db::{decode, skip_corrupt, Database},
+        error::error::{DbError, DbResult},
+    };
+
+    use rusqlite::{
+        params, params_from_iter, types::Value, OptionalExtension, Result as SqliteResult, Row,
+    };
+    use serde_json::Value as JsonValue;
+    use widget_types::{AppMessage, AppUiState, ChangeSource, DbChange, MessageSeverity};
+
+    /// Older messages are dropped once the log grows past this.
+    pub const MAX_MESSAGES: usize = 1000;
+
+    const MESSAGE_COLUMNS: &str =
+        "id, severity, source, widget_id, message, created_at, acknowledged";
+
+    /// Which messages `Database::get_messages` returns. Unset fields don't filter.
+    #[derive(Debug, Clone, Default)]
+    pub struct MessageFilter {
+        pub severity: Option<MessageSeverity>,
+        pub widget_id: Option<String>,
+        pub acknowledged: Option<bool>,
+        /// Only messages created at or after this, in epoch milliseconds
+        pub since: Option<i64>,
+        pub limit: Option<usize>,
+    }
+
+    pub(crate) fn to_sql_name<T: serde::Serialize>(value: &T) -> DbResult<String> {
+        Ok(serde_json::to_value(value)?
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    fn message_from_row(row: &Row) -> SqliteResult<DbResult<AppMessage>> {
+        let id: u32 = row.get(0)?;
+        // Stored as bare names, quoted so they decode like the JSON columns
+        let severity = JsonValue::String(row.get(1)?).to_string();
+        let source = JsonValue::String(row.get(2)?).to_string();
+        let widget_id: Option<String> = row.get(3)?;
+        let message: String = row.get(4)?;
+        let created_at: i64 = row.get(5)?;
+        let acknowledged: bool = row.get(6)?;
+
+        Ok(
+            decode("messages", id as i64, "severity", &severity).and_then(|severity| {
+                Ok(AppMessage {
+                    id,
+                    severity,
+                    source: decode("messages", id as i64, "source", &source)?,
+                    widget_id,
+                    message,
+                    created_at: created_at.to_string(),
+                    acknowledged,
+                })
+            }),
+        )
+    }
+
+    impl Database {
+        pub fn add_message(
+            &self,
+            severity: MessageSeverity,
+            source: ChangeSource,
+            widget_id: Option<&str>,
+            message: &str,
+        ) -> DbResult<AppMessage> {
+            let tx = self.conn.unchecked_transaction()?;
+            tx.execute(
+                "INSERT INTO messages (severity, source, widget_id, message, created_at) VALUES (?, ?, ?, ?, ?)",
+                params![
+                    to_sql_name(&severity)?,
+                    to_sql_name(&source)?,
+                    widget_id,
+                    message,
+                    jiff::Timestamp::now().as_millisecond()
+                ],
+            )?;
+            let id = tx.last_insert_rowid();
+            tx.execute(
+                "DELETE FROM messages WHERE id <= (SELECT id FROM messages ORDER BY id DESC LIMIT 1 OFFSET ?)",
+                [MAX_MESSAGES],
+            )?;
+            tx.commit()?;
+            self.publish(DbChange::MessageAdded { id: id as u32 });
+            self.get_message(id as u32)
+        }
+
+        pub fn get_message(&self, id: u32) -> DbResult<AppMessage> {
+            self.conn
+                .query_row(
+                    &format!("SELECT {} FROM messages WHERE id = ?", MESSAGE_COLUMNS),
+                    [id],
+                    message_from_row,
+                )
+                .optional()?
+                .ok_or_else(|| DbError::NotFound(format!("message {}", id)))?
+        }
+
+        /// Messages matching `filter`, newest first.
+        pub fn get_messages(&self, filter: &MessageFilter) -> DbResult<Vec<AppMessage>> {
+            let mut conditions = vec![];
+            let mut values: Vec<Value> = vec![];
+            if let Some(severity) = &filter.severity {
+                conditions.push("severity = ?");
+                values.push(Value::Text(to_sql_name(severity)?));
+            }
+            if let Some(widget_id) = &filter.widget_id {
+                conditions.push("widget_id = ?");
+                values.push(Value::Text(widget_id.clone()));
+            }
+            if let Some(acknowledged) = filter.acknowledged {
+                conditions.push("acknowledged = ?");
+                values.push(Value::Integer(acknowledged as i64));
+            }
+            if let Some(since) = filter.since {
+                conditions.push("created_at >= ?");
+                values.push(Value::Integer(since));
+            }
+            let mut sql = format!("SELECT {} FROM messages", MESSAGE_COLUMNS);
+            if !conditions.is_empty() {
+                sql.push_str(" WHERE ");
+                sql.push_str(&conditions.join(" AND "));
+            }
+            sql.push_str(" ORDER BY id DESC LIMIT ?");
+            values.push(Value::Integer(
+                filter.limit.unwrap_or(MAX_MESSAGES).min(MAX_MESSAGES) as i64,
+            ));
+
+            let mut stmt = self.conn.prepare(&sql)?;
+            let rows = stmt.query_map(params_from_iter(values), message_from_row)?;
+            skip_corrupt(rows)
+        }
+
+        pub fn acknowledge_message(&self, id: u32) -> DbResult<AppMessage> {
+            let message = self.get_message(id)?;
+            if message.acknowledged {
+                return Ok(message);
+            }
+            self.conn
+                .execute("UPDATE messages SET acknowledged = 1 WHERE id = ?", [id])?;
+            self.publish(DbChange::MessageAcknowledged { id });
+            self.get_message(id)
+        }
+
+        /// The settings together with the messages that haven't been acknowledged yet.
+        pub fn get_app_ui_state(&self) -> DbResult<AppUiState> {
+            let messages = self.get_messages(&MessageFilter {
+                acknowledged: Some(false),
+                ..Default::default()
+            })?;
+            Ok(AppUiState {
+                app_settings: self.get_settings()?,
+                messages: messages
+                    .into_iter()
+                    .rev()
+                    .map(|message| message.message)
+                    .collect(),
+            })
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_filter_and_acknowledge() {
+            let db = Database::from(true).unwrap();
+            db.add_message(MessageSeverity::Info, ChangeSource::App, None, "first")
+                .unwrap();
+            let failed = db
+                .add_message(
+                    MessageSeverity::Warning,
+                    ChangeSource::App,
+                    Some("w"),
+                    "scrape failed",
+                )
+                .unwrap();
+            assert_eq!(failed.widget_id.as_deref(), Some("w"));
+            assert!(!failed.acknowledged);
+
+            let warnings = db
+                .get_messages(&MessageFilter {
+                    severity: Some(MessageSeverity::Warning),
+                    ..Default::default()
+                })
+                .unwrap();
+            assert_eq!(warnings, vec![failed.clone()]);
+
+            assert!(db.acknowledge_message(failed.id).unwrap().acknowledged);
+            assert_eq!(db.get_app_ui_state().unwrap().messages, vec!["first"]);
+            let unread = db
+                .get_messages(&MessageFilter {
+                    acknowledged: Some(false),
+                    ..Default::default()
+                })
+                .unwrap();
+            assert_eq!(unread.len(), 1);
+            assert!(matches!(
+                db.acknowledge_message(failed.id + 1),
+                Err(DbError::NotFound(_))
+            ));
+        }
+
+        #[test]
+        fn test_log_is_bounded() {
+            let db = Database::from(true).unwrap();
+            for i in 0..MAX_MESSAGES + 5 {
+                db.add_message(
+                    MessageSeverity::Info,
+                    ChangeSource::App,
+                    None,
+                    &i.to_string(),
+                )
+                .unwrap();
+            }
+            let messages = db.get_messages(&MessageFilter::default()).unwrap();
+            assert_eq!(messages.len(), MAX_MESSAGES);
+            assert_eq!(messages[0].message, (MAX_MESSAGES + 4).to_string());
+            assert_eq!(messages[MAX_MESSAGES - 1].message, "5");
+        }
+    }
+}
@@ -0,0 +1,255 @@
+pub mod parse {
+    /// A scraped value read as a number, e.g. `"$1,234.50"` becomes `1234.5` in `"$"`.
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct ParsedValue {
+        pub number: f64,
+        /// Currency symbol or code, `%`, or whatever unit followed the number.
+        pub unit: Option<String>,
+    }
+
+    /// Longest first, so `US$` wins over `$`.
+    const CURRENCY_SYMBOLS: [&str; 12] = [
+        "US$", "CA$", "A$", "R$", "$", "€", "£", "¥", "₹", "₩", "₽", "₿",
+    ];
+
+    const MULTIPLIERS: [(&str, f64); 6] = [
+        ("bn", 1e9),
+        ("k", 1e3),
+        ("K", 1e3),
+        ("M", 1e6),
+        ("B", 1e9),
+        ("T", 1e12),
+    ];
+
+    /// Units longer than this are more likely a sentence than a unit.
+    const MAX_UNIT_LEN: usize = 16;
+
+    /// Reads a scraped string as a number. Understands `,` `.` `'` and (narrow) no-break spaces
+    /// as thousand separators, `k`/`M`/`B`/`T` suffixes, currencies before or after the number,
+    /// accounting style `(12.50)` negatives and a trailing unit such as `%` or `°C`. Percentages
+    /// keep their displayed value, so `"12%"` is `12` in `"%"`.
+    ///
+    /// A lone `,` followed by exactly three digits is taken as a thousand separator (`1,234`),
+    /// a lone `.` as the decimal point (`1.234`), since there is no way to tell them apart
+    /// without knowing the page's locale.
+    ///
+    /// Returns `None` for anything that isn't a single number.
+    pub fn parse_value(raw: &str) -> Option<ParsedValue> {
+        let mut rest = raw.trim();
+        let mut negative = false;
+
+        if let Some(inner) = rest.strip_prefix('(').and_then(|r| r.strip_suffix(')')) {
+            negative = true;
+            rest = inner.trim();
+        }
+        rest = strip_sign(rest, &mut negative);
+
+        let mut unit = None;
+        if let Some((currency, after)) = strip_currency(rest) {
+            unit = Some(currency.to_string());
+            rest = strip_sign(after.trim_start(), &mut negative);
+        }
+
+        let (number, after) = split_number(rest);
+        let mut number = normalize_number(number)?;
+        rest = after.trim();
+
+        for (suffix, factor) in MULTIPLIERS {
+            if let Some(after) = rest.strip_prefix(suffix) {
+                if after.is_empty() || after.starts_with(char::is_whitespace) {
+                    number *= factor;
+                    rest = after.trim_start();
+                    break;
+                }
+            }
+        }
+
+        if !rest.is_empty() {
+            if unit.is_some()
+                || rest.chars().count() > MAX_UNIT_LEN
+                || rest.contains(|c: char| c.is_ascii_digit() || c.is_whitespace())
+            {
+                return None;
+            }
+            unit = Some(rest.to_string());
+        }
+
+        Some(ParsedValue {
+            number: if negative { -number } else { number },
+            unit,
+        })
+    }
+
+    fn strip_sign<'a>(s: &'a str, negative: &mut bool) -> &'a str {
+        for minus in ['-', '−'] {
+            if let Some(rest) = s.strip_prefix(minus) {
+                *negative = !*negative;
+                return rest.trim_start();
+            }
+        }
+        s.strip_prefix('+').map(str::trim_start).unwrap_or(s)
+    }
+
+    fn strip_currency(s: &str) -> Option<(&str, &str)> {
+        if let Some(symbol) = CURRENCY_SYMBOLS
+            .iter()
+            .find(|symbol| s.starts_with(*symbol))
+        {
+            return Some((symbol, &s[symbol.len()..]));
+        }
+
+        // ISO 4217 codes such as `USD 12` or `CHF12`
+        let code = s.get(..3)?;
+        let after = &s[3..];
+        let next = after.chars().next()?;
+        if code.chars().all(|c| c.is_ascii_uppercase())
+            && (next.is_whitespace() || next.is_ascii_digit())
+        {
+            return Some((code, after));
+        }
+        None
+    }
+
+    fn is_separator(c: char) -> bool {
+        matches!(c, ',' | '.' | '\'' | ' ' | '\u{a0}' | '\u{202f}')
+    }
+
+    /// Splits off the leading run of digits and separators. A separator only counts when a digit
+    /// follows it, so `12 k` stops before the space.
+    fn split_number(s: &str) -> (&str, &str) {
+        let mut end = 0;
+        let mut chars = s.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            let next_is_digit = chars.peek().is_some_and(|(_, next)| next.is_ascii_digit());
+            if c.is_ascii_digit() || (is_separator(c) && next_is_digit) {
+                end = i + c.len_utf8();
+            } else {
+                break;
+            }
+        }
+        s.split_at(end)
+    }
+
+    fn normalize_number(number: &str) -> Option<f64> {
+        if !number.contains(|c: char| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let commas = number.matches(',').count();
+        let dots = number.matches('.').count();
+        let decimal_at = if commas > 0 && dots > 0 {
+            // `1,234.50` or `1.234,50`: whichever comes last is the decimal point
+            number.rfind([',', '.'])
+        } else if dots == 1 {
+            number.find('.')
+        } else if commas == 1 {
+            let at = number.find(',')?;
+            let (integer, fraction) = (&number[..at], &number[at + 1..]);
+            let grouping = fraction.len() == 3 && !integer.is_empty() && integer != "0";
+            (!grouping).then_some(at)
+        } else {
+            None
+        };
+
+        let (integer, fraction) = match decimal_at {
+            Some(at) => (&number[..at], &number[at + 1..]),
+            None => (number, ""),
+        };
+        if !fraction.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+
+        let groups = integer.split(is_separator).collect::<Vec<_>>();
+        if groups.len() > 1
+            && (groups[0].is_empty()
+                || groups[0].len() > 3
+                || groups[1..].iter().any(|group| group.len() != 3))
+        {
+            return None;
+        }
+
+        format!("{}.{}", groups.concat(), fraction).parse().ok()
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Raw input and the expected number and unit, `None` when it shouldn't parse.
+        type Case = (&'static str, Option<(f64, Option<&'static str>)>);
+
+        #[test]
+        fn test_parse_value() {
+            let cases: &[Case] = &[
+                ("42", Some((42.0, None))),
+                ("  2.75 ", Some((2.75, None))),
+                (".5", Some((0.5, None))),
+                ("-7", Some((-7.0, None))),
+                ("−7", Some((-7.0, None))),
+                ("+7", Some((7.0, None))),
+                ("(12.50)", Some((-12.5, None))),
+                // thousand separators
+                ("1,234", Some((1234.0, None))),
+                ("1,234,567.89", Some((1234567.89, None))),
+                ("1.234.567,89", Some((1234567.89, None))),
+                ("1 234,50", Some((1234.5, None))),
+                ("1\u{a0}234,50", Some((1234.5, None))),
+                ("1\u{202f}234", Some((1234.0, None))),
+                ("1'234.50", Some((1234.5, None))),
+                ("1,5", Some((1.5, None))),
+                ("0,125", Some((0.125, None))),
+                ("1.234", Some((1.234, None))),
+                // currencies
+                ("$1,234.50", Some((1234.5, Some("$")))),
+                ("-$5", Some((-5.0, Some("$")))),
+                ("$-5", Some((-5.0, Some("$")))),
+                ("US$ 10", Some((10.0, Some("US$")))),
+                ("€ 9,99", Some((9.99, Some("€")))),
+                ("9,99 €", Some((9.99, Some("€")))),
+                ("USD 1,000", Some((1000.0, Some("USD")))),
+                ("1,000 USD", Some((1000.0, Some("USD")))),
+                ("CHF1'000", Some((1000.0, Some("CHF")))),
+                // suffixes
+                ("12k", Some((12000.0, None))),
+                ("1.5M", Some((1500000.0, None))),
+                ("$2.1B", Some((2.1e9, Some("$")))),
+                ("3bn", Some((3e9, None))),
+                ("12 K", Some((12000.0, None))),
+                ("1.2M USD", Some((1200000.0, Some("USD")))),
+                ("45%", Some((45.0, Some("%")))),
+                ("-0.8 %", Some((-0.8, Some("%")))),
+                ("21.5°C", Some((21.5, Some("°C")))),
+                ("12km", Some((12.0, Some("km")))),
+                ("512 GB", Some((512.0, Some("GB")))),
+                // not a number
+                ("", None),
+                ("N/A", None),
+                ("--", None),
+                ("$", None),
+                ("Sold out", None),
+                ("12 items left today", None),
+                ("1,23,456", None),
+                ("12,34.5", None),
+                ("$12 USD", None),
+                ("v1.2.3", None),
+            ];
+
+            for (raw, expected) in cases {
+                let parsed = parse_value(raw).map(|p| (p.number, p.unit));
+                let expected = expected.map(|(number, unit)| (number, unit.map(str::to_string)));
+                match (&parsed, &expected) {
+                    (Some((number, unit)), Some((expected_number, expected_unit))) => {
+                        assert!(
+                            (number - expected_number).abs() < 1e-9 && unit == expected_unit,
+                            "{:?}: got {:?}, expected {:?}",
+                            raw,
+                            parsed,
+                            expected
+                        );
+                    }
+                    _ => assert_eq!(parsed, expected, "{:?}", raw),
+                }
+            }
+        }
+    }
+}
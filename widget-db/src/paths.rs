@@ -0,0 +1,114 @@
+pub mod paths {
+    use crate::error::error::{DbError, DbResult};
+
+    use std::path::PathBuf;
+
+    /// Overrides the base data directory, mostly useful for tests and portable installs.
+    pub const DATA_DIR_ENV: &str = "HOVERPANE_DATA_DIR";
+    /// Selects the profile when no `--profile` flag is given.
+    pub const PROFILE_ENV: &str = "HOVERPANE_PROFILE";
+    pub const DEFAULT_PROFILE: &str = "default";
+
+    const DATABASE_FILE: &str = "widgets.db";
+    const PROFILES_DIR: &str = "profiles";
+    /// Remembers the profile picked through the API for the next start.
+    const ACTIVE_PROFILE_FILE: &str = "active_profile";
+    const MAX_PROFILE_NAME_LEN: usize = 32;
+
+    /// Root of everything HoverPane stores, `$HOVERPANE_DATA_DIR` or the platform data dir.
+    pub fn base_data_dir() -> DbResult<PathBuf> {
+        if let Some(dir) = std::env::var_os(DATA_DIR_ENV).filter(|dir| !dir.is_empty()) {
+            return Ok(PathBuf::from(dir));
+        }
+        let directory = directories::ProjectDirs::from("com", "jarde", "hoverpane")
+            .ok_or_else(|| DbError::NotFound("home directory".to_string()))?;
+        Ok(directory.data_dir().to_path_buf())
+    }
+
+    pub fn validate_profile_name(name: &str) -> DbResult<()> {
+        let valid = !name.is_empty()
+            && name.len() <= MAX_PROFILE_NAME_LEN
+            && name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid {
+            return Err(DbError::InvalidInput(format!(
+                "profile names use up to {} letters, digits, '-' or '_', got {:?}",
+                MAX_PROFILE_NAME_LEN, name
+            )));
+        }
+        Ok(())
+    }
+
+    /// Data directory of a profile. The default profile lives directly in the base directory,
+    /// where the single database used to be, so existing installs keep their widgets.
+    pub fn profile_dir(profile: &str) -> DbResult<PathBuf> {
+        validate_profile_name(profile)?;
+        let base = base_data_dir()?;
+        Ok(if profile == DEFAULT_PROFILE {
+            base
+        } else {
+            base.join(PROFILES_DIR).join(profile)
+        })
+    }
+
+    pub fn database_path(profile: &str) -> DbResult<PathBuf> {
+        Ok(profile_dir(profile)?.join(DATABASE_FILE))
+    }
+
+    /// The default profile plus every profile that has been created, sorted by name.
+    pub fn list_profiles() -> DbResult<Vec<String>> {
+        let mut profiles = vec![DEFAULT_PROFILE.to_string()];
+        let dir = base_data_dir()?.join(PROFILES_DIR);
+        if dir.exists() {
+            for entry in std::fs::read_dir(dir)? {
+                let entry = entry?;
+                let name = entry.file_name().to_string_lossy().to_string();
+                if entry.file_type()?.is_dir() && validate_profile_name(&name).is_ok() {
+                    profiles.push(name);
+                }
+            }
+        }
+        profiles[1..].sort();
+        Ok(profiles)
+    }
+
+    pub fn set_active_profile(profile: &str) -> DbResult<()> {
+        validate_profile_name(profile)?;
+        let base = base_data_dir()?;
+        std::fs::create_dir_all(&base)?;
+        std::fs::write(base.join(ACTIVE_PROFILE_FILE), profile)?;
+        Ok(())
+    }
+
+    /// Picks the profile to open: the `--profile` flag, then `$HOVERPANE_PROFILE`, then the last
+    /// profile activated through the API, then the default.
+    pub fn resolve_profile(flag: Option<String>) -> DbResult<String> {
+        let profile = match flag.or_else(|| std::env::var(PROFILE_ENV).ok()) {
+            Some(profile) if !profile.is_empty() => profile,
+            _ => base_data_dir()
+                .ok()
+                .and_then(|base| std::fs::read_to_string(base.join(ACTIVE_PROFILE_FILE)).ok())
+                .map(|profile| profile.trim().to_string())
+                .filter(|profile| validate_profile_name(profile).is_ok())
+                .unwrap_or_else(|| DEFAULT_PROFILE.to_string()),
+        };
+        validate_profile_name(&profile)?;
+        Ok(profile)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_profile_names() {
+            for name in ["default", "trading", "ops-on_call", "A1"] {
+                assert!(validate_profile_name(name).is_ok(), "{}", name);
+            }
+            for name in ["", "../escape", "with space", "dots.db", &"x".repeat(33)] {
+                assert!(validate_profile_name(name).is_err(), "{}", name);
+            }
+        }
+    }
+}
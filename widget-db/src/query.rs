@@ -0,0 +1,345 @@
+pub mod query {
+    use crate::{
+        db::db::Database,
+        error::error::{DbError, DbResult},
+    };
+
+    use rusqlite::{
+        hooks::{AuthAction, AuthContext, Authorization},
+        params_from_iter,
+        types::{Value, ValueRef},
+        ErrorCode,
+    };
+    use serde::{Deserialize, Serialize};
+    use serde_json::Value as JsonValue;
+    use std::{
+        path::Path,
+        sync::mpsc::{self, RecvTimeoutError},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    /// Queries still running after this are interrupted.
+    pub const QUERY_TIME_LIMIT: Duration = Duration::from_secs(2);
+    /// At most this many rows are returned, the rest is cut off and `truncated` set.
+    pub const MAX_QUERY_ROWS: usize = 1000;
+    /// The views created for queries, the only things they may read.
+    pub const QUERY_VIEWS: &[&str] = &["query_widgets", "query_values"];
+
+    #[derive(Debug, Clone, Deserialize)]
+    pub struct QueryRequest {
+        pub sql: String,
+        /// Bound to the `?` placeholders in order. Only scalars are allowed.
+        #[serde(default)]
+        pub params: Vec<JsonValue>,
+        pub limit: Option<usize>,
+    }
+
+    #[derive(Debug, Clone, Copy, Serialize, PartialEq, Eq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum ColumnType {
+        Integer,
+        Real,
+        Text,
+        /// Returned as lowercase hex strings
+        Blob,
+        /// Every returned value was NULL, or no rows were returned
+        Null,
+        /// Values of different types, which SQLite allows in any column
+        Mixed,
+    }
+
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    pub struct QueryColumn {
+        pub name: String,
+        pub column_type: ColumnType,
+    }
+
+    #[derive(Debug, Clone, Serialize, PartialEq)]
+    pub struct QueryResult {
+        pub columns: Vec<QueryColumn>,
+        pub rows: Vec<Vec<JsonValue>>,
+        pub truncated: bool,
+        pub elapsed_ms: u64,
+    }
+
+    fn authorize(ctx: AuthContext<'_>) -> Authorization {
+        let in_view = |name: Option<&str>| name.is_some_and(|name| QUERY_VIEWS.contains(&name));
+        match ctx.action {
+            AuthAction::Select | AuthAction::Function { .. } | AuthAction::Recursive => {
+                Authorization::Allow
+            }
+            // Reading through a view reports the view as the accessor
+            AuthAction::Read { table_name, .. }
+                if in_view(Some(table_name)) || in_view(ctx.accessor) =>
+            {
+                Authorization::Allow
+            }
+            _ => Authorization::Deny,
+        }
+    }
+
+    fn sql_param(value: &JsonValue) -> DbResult<Value> {
+        Ok(match value {
+            JsonValue::Null => Value::Null,
+            JsonValue::Bool(b) => Value::Integer(*b as i64),
+            JsonValue::Number(n) => match n.as_i64() {
+                Some(i) => Value::Integer(i),
+                None => Value::Real(n.as_f64().unwrap_or(f64::NAN)),
+            },
+            JsonValue::String(s) => Value::Text(s.clone()),
+            _ => {
+                return Err(DbError::InvalidInput(format!(
+                    "query parameters must be scalars, got {}",
+                    value
+                )))
+            }
+        })
+    }
+
+    fn json_value(value: ValueRef<'_>) -> (JsonValue, ColumnType) {
+        match value {
+            ValueRef::Null => (JsonValue::Null, ColumnType::Null),
+            ValueRef::Integer(i) => (i.into(), ColumnType::Integer),
+            ValueRef::Real(f) => (
+                serde_json::Number::from_f64(f).map_or(JsonValue::Null, JsonValue::Number),
+                ColumnType::Real,
+            ),
+            ValueRef::Text(text) => (
+                String::from_utf8_lossy(text).into_owned().into(),
+                ColumnType::Text,
+            ),
+            ValueRef::Blob(blob) => (
+                blob.iter()
+                    .map(|byte| format!("{:02x}", byte))
+                    .collect::<String>()
+                    .into(),
+                ColumnType::Blob,
+            ),
+        }
+    }
+
+    fn merge(column: ColumnType, value: ColumnType) -> ColumnType {
+        match (column, value) {
+            (column, ColumnType::Null) => column,
+            (ColumnType::Null, value) => value,
+            (column, value) if column == value => column,
+            _ => ColumnType::Mixed,
+        }
+    }
+
+    fn query_error(e: rusqlite::Error, time_limit: Duration) -> DbError {
+        match e.sqlite_error_code() {
+            Some(ErrorCode::OperationInterrupted) => DbError::InvalidInput(format!(
+                "query took longer than {} ms",
+                time_limit.as_millis()
+            )),
+            Some(ErrorCode::AuthorizationForStatementDenied) => DbError::InvalidInput(format!(
+                "queries can only read {}",
+                QUERY_VIEWS.join(" and ")
+            )),
+            _ => DbError::InvalidInput(e.to_string()),
+        }
+    }
+
+    impl Database {
+        /// A read-only connection that can only read the query views, for `run_query`.
+        pub(crate) fn open_query(db_path: &Path) -> DbResult<Self> {
+            let db = Self::open_reader(db_path)?;
+            db.conn.authorizer(Some(authorize));
+            Ok(db)
+        }
+
+        /// Runs a statement that only reads, interrupting it after `time_limit`.
+        pub fn run_query(
+            &self,
+            request: &QueryRequest,
+            time_limit: Duration,
+        ) -> DbResult<QueryResult> {
+            let limit = request.limit.unwrap_or(MAX_QUERY_ROWS).min(MAX_QUERY_ROWS);
+            let params = request
+                .params
+                .iter()
+                .map(sql_param)
+                .collect::<DbResult<Vec<_>>>()?;
+            let started = Instant::now();
+
+            let interrupt = self.conn.get_interrupt_handle();
+            let (finished, wait_for_finish) = mpsc::channel::<()>();
+            let watchdog = thread::spawn(move || {
+                if let Err(RecvTimeoutError::Timeout) = wait_for_finish.recv_timeout(time_limit) {
+                    interrupt.interrupt();
+                }
+            });
+            let result = self.collect_query(&request.sql, params, limit);
+            drop(finished);
+            let _ = watchdog.join();
+
+            let (columns, rows, truncated) = result.map_err(|e| match e {
+                DbError::Sqlite(e) => query_error(e, time_limit),
+                e => e,
+            })?;
+            Ok(QueryResult {
+                columns,
+                rows,
+                truncated,
+                elapsed_ms: started.elapsed().as_millis() as u64,
+            })
+        }
+
+        fn collect_query(
+            &self,
+            sql: &str,
+            params: Vec<Value>,
+            limit: usize,
+        ) -> DbResult<(Vec<QueryColumn>, Vec<Vec<JsonValue>>, bool)> {
+            let mut stmt = self.conn.prepare(sql)?;
+            if !stmt.readonly() {
+                return Err(DbError::InvalidInput(
+                    "only statements that read are allowed".to_string(),
+                ));
+            }
+            let mut columns = stmt
+                .column_names()
+                .into_iter()
+                .map(|name| QueryColumn {
+                    name: name.to_string(),
+                    column_type: ColumnType::Null,
+                })
+                .collect::<Vec<_>>();
+
+            let mut rows = stmt.query(params_from_iter(params))?;
+            let mut values = vec![];
+            let mut truncated = false;
+            while let Some(row) = rows.next()? {
+                if values.len() == limit {
+                    truncated = true;
+                    break;
+                }
+                let mut row_values = Vec::with_capacity(columns.len());
+                for (i, column) in columns.iter_mut().enumerate() {
+                    let (value, value_type) = json_value(row.get_ref(i)?);
+                    column.column_type = merge(column.column_type, value_type);
+                    row_values.push(value);
+                }
+                values.push(row_values);
+            }
+            Ok((columns, values, truncated))
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::fixtures::{scraped, TempDb};
+        use serde_json::json;
+        use widget_types::{ChangeSource, NanoId, WidgetConfiguration};
+
+        fn query(db: &Database, sql: &str, params: Vec<JsonValue>) -> DbResult<QueryResult> {
+            db.run_query(
+                &QueryRequest {
+                    sql: sql.to_string(),
+                    params,
+                    limit: None,
+                },
+                QUERY_TIME_LIMIT,
+            )
+        }
+
+        #[test]
+        fn test_queries_read_only_the_views() {
+            let temp = TempDb::new();
+            let mut db = temp.open();
+            db.insert_widget_configuration(
+                vec![WidgetConfiguration::new().with_widget_id(NanoId("w".to_string()))],
+                ChangeSource::Api,
+            )
+            .unwrap();
+            for (value, timestamp) in [
+                ("$10.50", 1_700_000_000_000i64),
+                ("oops", 1_700_000_060_000),
+            ] {
+                db.insert_data(scraped("w", value, timestamp)).unwrap();
+            }
+            let query_db = Database::open_query(&temp.path()).unwrap();
+
+            let result = query(
+                &query_db,
+                "SELECT widget_id, numeric_value, timestamp, weekday FROM query_values WHERE widget_id = ? ORDER BY id",
+                vec![json!("w")],
+            )
+            .unwrap();
+            assert_eq!(
+                result
+                    .columns
+                    .iter()
+                    .map(|c| c.column_type)
+                    .collect::<Vec<_>>(),
+                vec![
+                    ColumnType::Text,
+                    ColumnType::Real,
+                    ColumnType::Integer,
+                    ColumnType::Integer
+                ]
+            );
+            assert_eq!(result.rows[0][1], json!(10.5));
+            assert_eq!(result.rows[1][1], JsonValue::Null);
+            assert_eq!(result.rows[0][2], json!(1_700_000_000_000i64));
+
+            let joined = query(
+                &query_db,
+                "WITH v AS (SELECT widget_id, avg(numeric_value) AS average FROM query_values GROUP BY widget_id) SELECT w.title, v.average FROM v JOIN query_widgets w USING (widget_id)",
+                vec![],
+            )
+            .unwrap();
+            assert_eq!(joined.rows, vec![vec![json!(""), json!(10.5)]]);
+
+            let truncated = query_db
+                .run_query(
+                    &QueryRequest {
+                        sql: "SELECT * FROM query_values".to_string(),
+                        params: vec![],
+                        limit: Some(1),
+                    },
+                    QUERY_TIME_LIMIT,
+                )
+                .unwrap();
+            assert_eq!(truncated.rows.len(), 1);
+            assert!(truncated.truncated);
+
+            for sql in [
+                "SELECT * FROM settings",
+                "SELECT * FROM scraped_data",
+                "DELETE FROM scraped_data",
+                "PRAGMA table_info(widgets)",
+                "CREATE TEMP VIEW leak AS SELECT * FROM settings",
+            ] {
+                assert!(
+                    matches!(query(&query_db, sql, vec![]), Err(DbError::InvalidInput(_))),
+                    "{}",
+                    sql
+                );
+            }
+            assert_eq!(db.get_data().unwrap().len(), 2);
+        }
+
+        #[test]
+        fn test_slow_queries_are_interrupted() {
+            let db = Database::from(true).unwrap();
+            let endless = QueryRequest {
+                sql: "WITH RECURSIVE n(i) AS (SELECT 1 UNION ALL SELECT i + 1 FROM n) SELECT count(*) FROM n".to_string(),
+                params: vec![],
+                limit: None,
+            };
+            let started = Instant::now();
+            let result = db.run_query(&endless, Duration::from_millis(100));
+            assert!(matches!(result, Err(DbError::InvalidInput(_))));
+            assert!(started.elapsed() < Duration::from_secs(5));
+            // The connection works again afterwards
+            assert_eq!(
+                query(&db, "SELECT 1", vec![]).unwrap().rows,
+                vec![vec![json!(1)]]
+            );
+        }
+    }
+}
@@ -0,0 +1,432 @@
+pub mod recovery {
+    use crate::{db::db::Database, error::error::DbResult};
+
+    use log::{error, info, warn};
+    use rusqlite::{
+        types::{Value, ValueRef},
+        Connection, OpenFlags, OptionalExtension, Result as SqliteResult,
+    };
+    use std::path::{Path, PathBuf};
+
+    /// Tables worth copying out of a broken database, parents before children.
+    const SALVAGED_TABLES: [&str; 5] = [
+        "widgets",
+        "modifiers",
+        "scraped_data",
+        "scraped_rollups",
+        "settings",
+    ];
+
+    /// Gives up on a table after this many failed scans without a readable row in between.
+    const MAX_CONSECUTIVE_FAILURES: usize = 1000;
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct TableRecovery {
+        pub table: &'static str,
+        pub recovered: usize,
+        pub lost: usize,
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub struct RecoveryReport {
+        pub quarantined_path: PathBuf,
+        pub integrity_problems: Vec<String>,
+        pub tables: Vec<TableRecovery>,
+    }
+
+    impl RecoveryReport {
+        /// Human readable summary for `AppUiState.messages`.
+        pub fn messages(&self) -> Vec<String> {
+            let recovered = self
+                .tables
+                .iter()
+                .map(|t| format!("{} {} rows", t.recovered, t.table))
+                .collect::<Vec<_>>()
+                .join(", ");
+            let mut messages = vec![format!(
+                "The widget database was damaged and has been moved to {}. Recovered {}.",
+                self.quarantined_path.display(),
+                if recovered.is_empty() {
+                    "nothing".to_string()
+                } else {
+                    recovered
+                }
+            )];
+
+            let lost = self.tables.iter().map(|t| t.lost).sum::<usize>();
+            if lost > 0 {
+                messages.push(format!(
+                    "{} rows were unreadable or no longer fit the database and could not be recovered.",
+                    lost
+                ));
+            }
+            if let Some(problem) = self.integrity_problems.first() {
+                messages.push(format!(
+                    "Integrity check reported {} problem(s), first: {}",
+                    self.integrity_problems.len(),
+                    problem
+                ));
+            }
+            messages
+        }
+    }
+
+    /// Moves the broken database (and its WAL/SHM sidecars) out of the way, then copies every
+    /// readable row into a fresh database created at the original path.
+    pub(crate) fn recover(db_path: &Path) -> DbResult<(Database, RecoveryReport)> {
+        let quarantined_path = quarantine(db_path)?;
+        warn!("Quarantined broken database to {:?}", quarantined_path);
+
+        let db = Database::open_file(db_path)?;
+        let mut report = RecoveryReport {
+            quarantined_path: quarantined_path.clone(),
+            integrity_problems: vec![],
+            tables: vec![],
+        };
+
+        let source = match Connection::open_with_flags(
+            &quarantined_path,
+            OpenFlags::SQLITE_OPEN_READ_ONLY | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        ) {
+            Ok(source) => source,
+            Err(e) => {
+                error!("Could not open quarantined database: {}", e);
+                report.integrity_problems.push(e.to_string());
+                return Ok((db, report));
+            }
+        };
+
+        report.integrity_problems = integrity_check(&source);
+        for table in SALVAGED_TABLES {
+            let (recovered, lost) = salvage_table(&source, &db.conn, table);
+            info!(
+                "Recovered {} rows from {} ({} lost)",
+                recovered, table, lost
+            );
+            report.tables.push(TableRecovery {
+                table,
+                recovered,
+                lost,
+            });
+        }
+
+        Ok((db, report))
+    }
+
+    fn quarantine(db_path: &Path) -> DbResult<PathBuf> {
+        let file_name = db_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_else(|| "widgets.db".to_string());
+        let quarantined_path = db_path.with_file_name(format!(
+            "{}.corrupt-{}",
+            file_name,
+            jiff::Zoned::now().strftime("%Y%m%d-%H%M%S")
+        ));
+
+        std::fs::rename(db_path, &quarantined_path)?;
+        for suffix in ["-wal", "-shm"] {
+            let sidecar = PathBuf::from(format!("{}{}", db_path.display(), suffix));
+            if sidecar.exists() {
+                std::fs::rename(
+                    &sidecar,
+                    format!("{}{}", quarantined_path.display(), suffix),
+                )?;
+            }
+        }
+        Ok(quarantined_path)
+    }
+
+    fn integrity_check(conn: &Connection) -> Vec<String> {
+        let problems = conn.prepare("PRAGMA integrity_check").and_then(|mut stmt| {
+            stmt.query_map([], |row| row.get::<_, String>(0))?
+                .collect::<SqliteResult<Vec<_>>>()
+        });
+        match problems {
+            Ok(problems) => problems.into_iter().filter(|p| p != "ok").collect(),
+            Err(e) => vec![e.to_string()],
+        }
+    }
+
+    fn table_columns(conn: &Connection, table: &str) -> SqliteResult<Vec<String>> {
+        let mut stmt = conn.prepare(&format!("PRAGMA table_info(\"{}\")", table))?;
+        let columns = stmt
+            .query_map([], |row| row.get::<_, String>(1))?
+            .collect::<SqliteResult<Vec<_>>>()?;
+        Ok(columns)
+    }
+
+    /// Like `Value::from`, but text that isn't UTF-8 fails the row instead of panicking.
+    fn owned_value(value: ValueRef<'_>) -> SqliteResult<Value> {
+        Ok(match value {
+            ValueRef::Null => Value::Null,
+            ValueRef::Integer(i) => Value::Integer(i),
+            ValueRef::Real(f) => Value::Real(f),
+            ValueRef::Text(text) => Value::Text(
+                std::str::from_utf8(text)
+                    .map_err(rusqlite::Error::Utf8Error)?
+                    .to_string(),
+            ),
+            ValueRef::Blob(blob) => Value::Blob(blob.to_vec()),
+        })
+    }
+
+    /// The first rowid after `after`, read from the table's b-tree without decoding the row.
+    fn next_rowid(conn: &Connection, table: &str, after: i64) -> SqliteResult<Option<i64>> {
+        conn.query_row(
+            &format!(
+                "SELECT rowid FROM \"{}\" WHERE rowid > ? ORDER BY rowid LIMIT 1",
+                table
+            ),
+            [after],
+            |row| row.get(0),
+        )
+        .optional()
+    }
+
+    /// Copies rows by ascending rowid, stepping over the ones SQLite can't decode and the ones
+    /// the current schema rejects. Only the columns both schemas share are copied, so older
+    /// database versions can be salvaged too. Returns how many rows were recovered and how many
+    /// were lost either way.
+    fn salvage_table(source: &Connection, target: &Connection, table: &str) -> (usize, usize) {
+        let target_columns = match table_columns(target, table) {
+            Ok(columns) => columns,
+            Err(e) => {
+                error!("Could not read {} columns in new database: {}", table, e);
+                return (0, 0);
+            }
+        };
+        let columns = match table_columns(source, table) {
+            Ok(columns) => columns
+                .into_iter()
+                .filter(|c| target_columns.contains(c))
+                .collect::<Vec<_>>(),
+            Err(e) => {
+                error!("Could not read {} columns in broken database: {}", table, e);
+                return (0, 0);
+            }
+        };
+        if columns.is_empty() {
+            return (0, 0);
+        }
+
+        let column_list = columns
+            .iter()
+            .map(|c| format!("\"{}\"", c))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let select_sql = format!(
+            "SELECT rowid, {} FROM \"{}\" WHERE rowid > ? ORDER BY rowid",
+            column_list, table
+        );
+        let insert_sql = format!(
+            "INSERT INTO \"{}\" ({}) VALUES ({})",
+            table,
+            column_list,
+            vec!["?"; columns.len()].join(", ")
+        );
+
+        let mut recovered = 0;
+        let mut lost = 0;
+        let mut consecutive_failures = 0;
+        let mut last_rowid: i64 = 0;
+        loop {
+            // Re-run the scan after every failure, resuming just past the unreadable row
+            let scan = (|| -> SqliteResult<()> {
+                let mut select = source.prepare(&select_sql)?;
+                let mut insert = target.prepare_cached(&insert_sql)?;
+                let mut rows = select.query([last_rowid])?;
+                while let Some(row) = rows.next()? {
+                    let rowid: i64 = row.get(0)?;
+                    let values = (1..=columns.len())
+                        .map(|i| owned_value(row.get_ref(i)?))
+                        .collect::<SqliteResult<Vec<_>>>()?;
+                    // A row that reads fine but breaks a constraint is lost on its own, it
+                    // doesn't mean the scan has to restart
+                    match insert.execute(rusqlite::params_from_iter(values)) {
+                        Ok(_) => recovered += 1,
+                        Err(e) => {
+                            warn!(
+                                "Row {} in {} doesn't fit the new schema: {}",
+                                rowid, table, e
+                            );
+                            lost += 1;
+                        }
+                    }
+                    last_rowid = rowid;
+                    consecutive_failures = 0;
+                }
+                Ok(())
+            })();
+
+            match scan {
+                Ok(()) => break,
+                Err(e) => {
+                    consecutive_failures += 1;
+                    if consecutive_failures >= MAX_CONSECUTIVE_FAILURES || last_rowid == i64::MAX {
+                        error!("Giving up on {} after repeated errors: {}", table, e);
+                        break;
+                    }
+                    // Rowids have gaps, so the unreadable row is the next one that exists rather
+                    // than the one right after the last good row
+                    match next_rowid(source, table, last_rowid) {
+                        Ok(Some(rowid)) => {
+                            warn!("Skipping unreadable row {} in {}: {}", rowid, table, e);
+                            lost += 1;
+                            last_rowid = rowid;
+                        }
+                        Ok(None) => break,
+                        // Not even the rowid can be read here, edge forward until it can
+                        Err(_) => last_rowid += 1,
+                    }
+                }
+            }
+        }
+
+        (recovered, lost)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::fixtures::{scraped, TempDb};
+        use widget_types::{ChangeSource, NanoId, WidgetConfiguration};
+
+        #[test]
+        fn test_garbage_file_is_quarantined() {
+            let temp = TempDb::new();
+            let db_path = temp.path();
+            std::fs::write(&db_path, vec![0x42; 8192]).unwrap();
+
+            let db = Database::open(&db_path).unwrap();
+            assert!(db.get_configuration().unwrap().is_empty());
+
+            let quarantined = std::fs::read_dir(&temp.dir)
+                .unwrap()
+                .filter_map(|entry| entry.ok())
+                .filter(|entry| {
+                    entry
+                        .file_name()
+                        .to_string_lossy()
+                        .starts_with("widgets.db.corrupt-")
+                })
+                .count();
+            assert_eq!(quarantined, 1);
+
+            let messages = db.get_app_ui_state().unwrap().messages;
+            assert!(messages[0].contains("has been moved to"));
+        }
+
+        #[test]
+        fn test_readable_rows_are_salvaged() {
+            let temp = TempDb::new();
+            let db_path = temp.path();
+            {
+                let mut db = Database::open(&db_path).unwrap();
+                db.insert_widget_configuration(
+                    vec![
+                        WidgetConfiguration::new().with_widget_id(NanoId("a".to_string())),
+                        WidgetConfiguration::new().with_widget_id(NanoId("b".to_string())),
+                    ],
+                    ChangeSource::Api,
+                )
+                .unwrap();
+                for i in 0..3 {
+                    db.insert_data(scraped("a", &i.to_string(), 1_700_000_000_000 + i))
+                        .unwrap();
+                }
+                db.set_setting("email", "me@example.com".into(), None)
+                    .unwrap();
+            }
+
+            let (db, report) = recover(&db_path).unwrap();
+            assert!(report.integrity_problems.is_empty());
+            assert_eq!(
+                report.tables[0],
+                TableRecovery {
+                    table: "widgets",
+                    recovered: 2,
+                    lost: 0
+                }
+            );
+            assert_eq!(report.tables[2].recovered, 3);
+            assert_eq!(db.get_configuration().unwrap().len(), 2);
+            assert_eq!(db.get_data().unwrap().len(), 3);
+            assert_eq!(db.get_settings().unwrap().email, "me@example.com");
+            assert!(report.quarantined_path.exists());
+        }
+
+        #[test]
+        fn test_unreadable_row_after_a_rowid_gap_is_skipped_once() {
+            let temp = TempDb::new();
+            let db_path = temp.path();
+            {
+                let db = Database::open(&db_path).unwrap();
+                // Compaction and purges leave gaps like this one before the broken row
+                db.conn
+                    .execute_batch(
+                        r#"
+                        INSERT INTO scraped_data (id, widget_id, value, error, timestamp)
+                        VALUES (1, 'a', '1', '', 1700000000000);
+                        INSERT INTO scraped_data (id, widget_id, value, error, timestamp)
+                        VALUES (5000, 'a', CAST(x'ff' AS TEXT), '', 1700000001000);
+                        INSERT INTO scraped_data (id, widget_id, value, error, timestamp)
+                        VALUES (5001, 'a', '3', '', 1700000002000);
+                        "#,
+                    )
+                    .unwrap();
+            }
+
+            let (db, report) = recover(&db_path).unwrap();
+            assert_eq!(
+                report.tables[2],
+                TableRecovery {
+                    table: "scraped_data",
+                    recovered: 2,
+                    lost: 1
+                }
+            );
+            let values = db
+                .get_data()
+                .unwrap()
+                .into_iter()
+                .map(|data| data.value)
+                .collect::<Vec<_>>();
+            assert_eq!(values, vec!["1", "3"]);
+        }
+
+        #[test]
+        fn test_rows_the_schema_rejects_are_lost() {
+            let temp = TempDb::new();
+            let db_path = temp.path();
+            {
+                // Modifiers from before they had ids can't be copied into the current table
+                let conn = Connection::open(&db_path).unwrap();
+                conn.execute_batch(
+                    r#"
+                    CREATE TABLE modifiers (id INTEGER PRIMARY KEY, widget_id TEXT, modifier_type TEXT);
+                    INSERT INTO modifiers (widget_id, modifier_type) VALUES ('a', '{}');
+                    INSERT INTO modifiers (widget_id, modifier_type) VALUES ('b', '{}');
+                    CREATE TABLE scraped_data (id INTEGER PRIMARY KEY, widget_id TEXT, value TEXT, error TEXT, timestamp INTEGER);
+                    INSERT INTO scraped_data (widget_id, value, error, timestamp) VALUES ('a', '1', '', 1700000000000);
+                    "#,
+                )
+                .unwrap();
+            }
+
+            let (db, report) = recover(&db_path).unwrap();
+            assert_eq!(
+                report.tables[1],
+                TableRecovery {
+                    table: "modifiers",
+                    recovered: 0,
+                    lost: 2
+                }
+            );
+            assert_eq!(report.tables[2].recovered, 1);
+            assert_eq!(report.tables[2].lost, 0);
+            assert_eq!(db.get_data().unwrap().len(), 1);
+            assert!(report.messages()[1].starts_with("2 rows"));
+        }
+    }
+}
@@ -0,0 +1,462 @@
+pub mod retention {
+    use crate::{
+        db::db::{decode, Database},
+        error::error::{DbError, DbResult},
+        history::history::TIMESTAMP_MS,
+        service::service::DbService,
+    };
+
+    use log::{error, info};
+    use rusqlite::{params, OptionalExtension};
+    use std::time::Duration;
+    use widget_types::{NanoId, RetentionPolicy};
+
+    pub const COMPACTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    const HOUR_MS: i64 = 60 * 60 * 1000;
+    const DAY_MS: i64 = 24 * HOUR_MS;
+
+    impl Database {
+        pub fn get_retention_policy(&self, widget_id: &str) -> DbResult<RetentionPolicy> {
+            let row = self
+                .conn
+                .query_row(
+                    "SELECT id, retention_policy FROM widgets WHERE widget_id = ?",
+                    [widget_id],
+                    |row| Ok((row.get::<_, i64>(0)?, row.get::<_, Option<String>>(1)?)),
+                )
+                .optional()?;
+
+            match row {
+                Some((_, None)) => Ok(RetentionPolicy::KeepAll),
+                Some((id, Some(policy))) => decode("widgets", id, "retention_policy", &policy),
+                None => Err(DbError::NotFound(format!("widget {}", widget_id))),
+            }
+        }
+
+        pub fn set_retention_policy(
+            &self,
+            widget_id: &str,
+            policy: &RetentionPolicy,
+        ) -> DbResult<()> {
+            let keeps_something = match policy {
+                RetentionPolicy::KeepAll => true,
+                RetentionPolicy::KeepDays { days } => *days > 0,
+                RetentionPolicy::KeepRows { rows } => *rows > 0,
+                RetentionPolicy::Downsample { raw_days, .. } => *raw_days > 0,
+            };
+            if !keeps_something {
+                return Err(DbError::InvalidInput(
+                    "retention policy must keep at least one day or row".to_string(),
+                ));
+            }
+            let policy_json = match policy {
+                RetentionPolicy::KeepAll => None,
+                policy => Some(serde_json::to_string(policy)?),
+            };
+            let rows_affected = self.conn.execute(
+                "UPDATE widgets SET retention_policy = ? WHERE widget_id = ?",
+                params![policy_json, widget_id],
+            )?;
+            if rows_affected == 0 {
+                return Err(DbError::NotFound(format!("widget {}", widget_id)));
+            }
+            Ok(())
+        }
+
+        /// Applies every widget's retention policy to `scraped_data` as of `now_ms` and returns
+        /// how many rows were removed.
+        pub fn compact_scraped_data(&mut self, now_ms: i64) -> DbResult<usize> {
+            let policies = {
+                let mut stmt = self.conn.prepare(
+                    "SELECT id, widget_id, retention_policy FROM widgets WHERE retention_policy IS NOT NULL",
+                )?;
+                let rows = stmt.query_map([], |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        NanoId(row.get(1)?),
+                        row.get::<_, String>(2)?,
+                    ))
+                })?;
+                let mut policies = vec![];
+                for row in rows {
+                    let (id, widget_id, policy) = row?;
+                    match decode::<RetentionPolicy>("widgets", id, "retention_policy", &policy) {
+                        Ok(policy) => policies.push((widget_id, policy)),
+                        Err(e) => error!("Skipping retention policy: {}", e),
+                    }
+                }
+                policies
+            };
+
+            let tx = self.conn.transaction()?;
+            let mut removed = 0;
+            for (widget_id, policy) in policies {
+                removed += match policy {
+                    RetentionPolicy::KeepAll => 0,
+                    RetentionPolicy::KeepDays { days } => {
+                        let cutoff = now_ms - days as i64 * DAY_MS;
+                        let removed = tx.execute(
+                            &format!(
+                                "DELETE FROM scraped_data WHERE widget_id = ? AND {} < ?",
+                                TIMESTAMP_MS
+                            ),
+                            params![widget_id.0, cutoff],
+                        )?;
+                        expire_rollups(&tx, &widget_id, cutoff)?;
+                        removed
+                    }
+                    RetentionPolicy::KeepRows { rows } => {
+                        let removed = tx.execute(
+                            &format!(
+                                r#"
+                                DELETE FROM scraped_data
+                                WHERE widget_id = ?1
+                                  AND id NOT IN (
+                                    SELECT id FROM scraped_data
+                                    WHERE widget_id = ?1
+                                    ORDER BY {} DESC, id DESC
+                                    LIMIT ?2
+                                  )
+                                "#,
+                                TIMESTAMP_MS
+                            ),
+                            params![widget_id.0, rows],
+                        )?;
+                        // Rollups only hold values older than the rows that are kept
+                        let oldest_kept: Option<i64> = tx.query_row(
+                            &format!(
+                                "SELECT MIN({}) FROM scraped_data WHERE widget_id = ?",
+                                TIMESTAMP_MS
+                            ),
+                            [&widget_id.0],
+                            |row| row.get(0),
+                        )?;
+                        expire_rollups(&tx, &widget_id, oldest_kept.unwrap_or(i64::MAX))?;
+                        removed
+                    }
+                    RetentionPolicy::Downsample {
+                        raw_days,
+                        hourly_days,
+                    } => {
+                        let raw_cutoff = now_ms - raw_days as i64 * DAY_MS;
+                        let hourly_cutoff = raw_cutoff - hourly_days as i64 * DAY_MS;
+                        downsample(&tx, &widget_id, hourly_cutoff, raw_cutoff, HOUR_MS)?
+                            + downsample(&tx, &widget_id, i64::MIN, hourly_cutoff, DAY_MS)?
+                    }
+                };
+            }
+            tx.commit()?;
+
+            Ok(removed)
+        }
+    }
+
+    /// Drops the widget's rollups of buckets that start before `cutoff_ms`, like the raw rows
+    /// from before then, so switching away from `Downsample` doesn't keep counting them.
+    fn expire_rollups(
+        tx: &rusqlite::Transaction,
+        widget_id: &NanoId,
+        cutoff_ms: i64,
+    ) -> DbResult<()> {
+        tx.execute(
+            "DELETE FROM scraped_rollups WHERE widget_id = ? AND bucket_start < ?",
+            params![widget_id.0, cutoff_ms],
+        )?;
+        Ok(())
+    }
+
+    /// Keeps only the latest row of each `bucket_ms` wide bucket in `[from_ms, to_ms)`. The
+    /// numeric values of the removed rows, and any finer rollups in the range, are merged into
+    /// the bucket's rollup.
+    fn downsample(
+        tx: &rusqlite::Transaction,
+        widget_id: &NanoId,
+        from_ms: i64,
+        to_ms: i64,
+        bucket_ms: i64,
+    ) -> DbResult<usize> {
+        let args = params![widget_id.0, from_ms, to_ms, bucket_ms];
+        tx.execute(
+            &format!(
+                r#"
+                WITH removed AS (
+                    SELECT * FROM (
+                        SELECT {ts} AS at, numeric_value, ROW_NUMBER() OVER (
+                            PARTITION BY {ts} / ?4
+                            ORDER BY {ts} DESC, id DESC
+                        ) AS position
+                        FROM scraped_data
+                        WHERE widget_id = ?1 AND {ts} >= ?2 AND {ts} < ?3
+                    )
+                    WHERE position > 1 AND numeric_value IS NOT NULL
+                ),
+                source AS (
+                    SELECT (at / ?4) * ?4 AS bucket, 1 AS count, numeric_value AS total,
+                           numeric_value AS min_value, numeric_value AS max_value,
+                           numeric_value AS first_value, at AS first_at,
+                           numeric_value AS last_value, at AS last_at
+                    FROM removed
+                    UNION ALL
+                    SELECT (bucket_start / ?4) * ?4, count, total, min_value, max_value,
+                           first_value, first_at, last_value, last_at
+                    FROM scraped_rollups
+                    WHERE widget_id = ?1 AND bucket_ms < ?4
+                      AND bucket_start >= ?2 AND bucket_start < ?3
+                ),
+                ranked AS (
+                    SELECT *,
+                           ROW_NUMBER() OVER (PARTITION BY bucket ORDER BY first_at ASC)
+                               AS from_start,
+                           ROW_NUMBER() OVER (PARTITION BY bucket ORDER BY last_at DESC)
+                               AS from_end
+                    FROM source
+                )
+                INSERT INTO scraped_rollups (
+                    widget_id, bucket_ms, bucket_start, count, total, min_value, max_value,
+                    first_value, first_at, last_value, last_at
+                )
+                SELECT ?1, ?4, bucket, SUM(count), SUM(total), MIN(min_value), MAX(max_value),
+                       MAX(CASE WHEN from_start = 1 THEN first_value END), MIN(first_at),
+                       MAX(CASE WHEN from_end = 1 THEN last_value END), MAX(last_at)
+                FROM ranked
+                WHERE true
+                GROUP BY bucket
+                ON CONFLICT (widget_id, bucket_ms, bucket_start) DO UPDATE SET
+                    count = count + excluded.count,
+                    total = total + excluded.total,
+                    min_value = MIN(min_value, excluded.min_value),
+                    max_value = MAX(max_value, excluded.max_value),
+                    first_value = CASE WHEN excluded.first_at < first_at
+                        THEN excluded.first_value ELSE first_value END,
+                    first_at = MIN(first_at, excluded.first_at),
+                    last_value = CASE WHEN excluded.last_at >= last_at
+                        THEN excluded.last_value ELSE last_value END,
+                    last_at = MAX(last_at, excluded.last_at)
+                "#,
+                ts = TIMESTAMP_MS
+            ),
+            args,
+        )?;
+        tx.execute(
+            r#"
+            DELETE FROM scraped_rollups
+            WHERE widget_id = ?1 AND bucket_ms < ?4 AND bucket_start >= ?2 AND bucket_start < ?3
+            "#,
+            args,
+        )?;
+
+        let removed = tx.execute(
+            &format!(
+                r#"
+                DELETE FROM scraped_data
+                WHERE id IN (
+                    SELECT id FROM (
+                        SELECT id, ROW_NUMBER() OVER (
+                            PARTITION BY {ts} / ?4
+                            ORDER BY {ts} DESC, id DESC
+                        ) AS position
+                        FROM scraped_data
+                        WHERE widget_id = ?1 AND {ts} >= ?2 AND {ts} < ?3
+                    )
+                    WHERE position > 1
+                )
+                "#,
+                ts = TIMESTAMP_MS
+            ),
+            args,
+        )?;
+        Ok(removed)
+    }
+
+    /// Periodically enforces retention policies and empties expired trash for as long as the API
+    /// is running.
+    pub async fn run_compaction(db: DbService, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let now_ms = jiff::Timestamp::now().as_millisecond();
+            match db.write(move |db| db.compact_scraped_data(now_ms)).await {
+                Ok(removed) => info!("Compaction removed {} scraped rows", removed),
+                Err(e) => error!("Compaction failed: {}", e),
+            }
+            if let Err(e) = db.write(move |db| db.purge_expired_trash(now_ms)).await {
+                error!("Purging the trash failed: {}", e);
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::fixtures::scraped;
+        use widget_types::{ChangeSource, WidgetConfiguration};
+
+        const NOW: i64 = 1_760_000_000_000;
+
+        fn db_with_history(timestamps: &[i64]) -> Database {
+            let mut db = Database::from(true).unwrap();
+            db.insert_widget_configuration(
+                vec![WidgetConfiguration::new().with_widget_id(NanoId("w".to_string()))],
+                ChangeSource::Api,
+            )
+            .unwrap();
+            for timestamp in timestamps {
+                db.insert_data(scraped("w", &timestamp.to_string(), *timestamp))
+                    .unwrap();
+            }
+            db
+        }
+
+        fn remaining(db: &Database) -> Vec<i64> {
+            let mut timestamps = db
+                .get_data()
+                .unwrap()
+                .into_iter()
+                .map(|d| d.timestamp.parse().unwrap())
+                .collect::<Vec<_>>();
+            timestamps.sort();
+            timestamps
+        }
+
+        #[test]
+        fn test_policy_roundtrip() {
+            let db = db_with_history(&[]);
+            assert_eq!(
+                db.get_retention_policy("w").unwrap(),
+                RetentionPolicy::KeepAll
+            );
+            let policy = RetentionPolicy::Downsample {
+                raw_days: 1,
+                hourly_days: 7,
+            };
+            db.set_retention_policy("w", &policy).unwrap();
+            assert_eq!(db.get_retention_policy("w").unwrap(), policy);
+            assert!(matches!(
+                db.set_retention_policy("missing", &policy),
+                Err(DbError::NotFound(_))
+            ));
+            for keeps_nothing in [
+                RetentionPolicy::KeepDays { days: 0 },
+                RetentionPolicy::KeepRows { rows: 0 },
+            ] {
+                assert!(matches!(
+                    db.set_retention_policy("w", &keeps_nothing),
+                    Err(DbError::InvalidInput(_))
+                ));
+            }
+            assert_eq!(db.get_retention_policy("w").unwrap(), policy);
+        }
+
+        #[test]
+        fn test_keep_days() {
+            let mut db = db_with_history(&[NOW - 3 * DAY_MS, NOW - DAY_MS - 1, NOW - 10]);
+            db.set_retention_policy("w", &RetentionPolicy::KeepDays { days: 1 })
+                .unwrap();
+            assert_eq!(db.compact_scraped_data(NOW).unwrap(), 2);
+            assert_eq!(remaining(&db), vec![NOW - 10]);
+        }
+
+        #[test]
+        fn test_keep_rows() {
+            let mut db = db_with_history(&[NOW - 3, NOW - 2, NOW - 1]);
+            db.set_retention_policy("w", &RetentionPolicy::KeepRows { rows: 2 })
+                .unwrap();
+            assert_eq!(db.compact_scraped_data(NOW).unwrap(), 1);
+            assert_eq!(remaining(&db), vec![NOW - 2, NOW - 1]);
+        }
+
+        #[test]
+        fn test_downsample() {
+            let day_start = (NOW - 5 * DAY_MS) / DAY_MS * DAY_MS;
+            let hour_start = (NOW - 2 * DAY_MS) / HOUR_MS * HOUR_MS;
+            let mut db = db_with_history(&[
+                // beyond the hourly window: one value per day survives
+                day_start + 1,
+                day_start + 2 * HOUR_MS,
+                // inside the hourly window: one value per hour survives
+                hour_start + 1,
+                hour_start + 2,
+                hour_start + HOUR_MS + 1,
+                // raw window is untouched
+                NOW - 2,
+                NOW - 1,
+            ]);
+            db.set_retention_policy(
+                "w",
+                &RetentionPolicy::Downsample {
+                    raw_days: 1,
+                    hourly_days: 2,
+                },
+            )
+            .unwrap();
+            assert_eq!(db.compact_scraped_data(NOW).unwrap(), 2);
+            assert_eq!(
+                remaining(&db),
+                vec![
+                    day_start + 2 * HOUR_MS,
+                    hour_start + 2,
+                    hour_start + HOUR_MS + 1,
+                    NOW - 2,
+                    NOW - 1
+                ]
+            );
+        }
+
+        #[test]
+        fn test_downsample_keeps_aggregates() {
+            let hour_start = (NOW - 2 * DAY_MS) / HOUR_MS * HOUR_MS;
+            let mut db = db_with_history(&[]);
+            for (offset, value) in [(1, "10"), (2, "30"), (3, "20")] {
+                db.insert_data(scraped("w", value, hour_start + offset))
+                    .unwrap();
+            }
+            db.set_retention_policy(
+                "w",
+                &RetentionPolicy::Downsample {
+                    raw_days: 1,
+                    hourly_days: 2,
+                },
+            )
+            .unwrap();
+            let summary = |db: &Database, interval_ms| {
+                let buckets = db
+                    .get_widget_aggregates("w", None, None, interval_ms)
+                    .unwrap();
+                assert_eq!(buckets.len(), 1);
+                let b = &buckets[0];
+                (b.count, b.min, b.max, b.avg, b.first, b.last)
+            };
+
+            assert_eq!(db.compact_scraped_data(NOW).unwrap(), 2);
+            assert_eq!(remaining(&db), vec![hour_start + 3]);
+            assert_eq!(summary(&db, HOUR_MS), (3, 10.0, 30.0, 20.0, 10.0, 20.0));
+
+            // Once the hour leaves the hourly window its rollup is folded into the day's
+            assert_eq!(db.compact_scraped_data(NOW + 2 * DAY_MS).unwrap(), 0);
+            assert_eq!(summary(&db, DAY_MS), (3, 10.0, 30.0, 20.0, 10.0, 20.0));
+            let rollups: Vec<i64> = db
+                .conn
+                .prepare("SELECT bucket_ms FROM scraped_rollups")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<rusqlite::Result<_>>()
+                .unwrap();
+            assert_eq!(rollups, vec![DAY_MS]);
+
+            // Switching away from downsampling expires rollups with the raw rows
+            db.set_retention_policy("w", &RetentionPolicy::KeepRows { rows: 1 })
+                .unwrap();
+            assert_eq!(db.compact_scraped_data(NOW + 2 * DAY_MS).unwrap(), 0);
+            assert_eq!(summary(&db, DAY_MS), (1, 20.0, 20.0, 20.0, 20.0, 20.0));
+            db.set_retention_policy("w", &RetentionPolicy::KeepDays { days: 1 })
+                .unwrap();
+            assert_eq!(db.compact_scraped_data(NOW + 2 * DAY_MS).unwrap(), 1);
+            assert!(db
+                .get_widget_aggregates("w", None, None, DAY_MS)
+                .unwrap()
+                .is_empty());
+        }
+    }
+}
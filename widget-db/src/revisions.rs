@@ -0,0 +1,588 @@
+pub mod revisions {
+    use crate::{
+        db::db::{
+            decode, insert_modifier_row, skip_corrupt, widget_from_row, Database, WIDGET_COLUMNS,
+        },
+        error::error::{DbError, DbResult},
+    };
+
+    use log::error;
+    use rusqlite::{params, Connection, OptionalExtension, Result as SqliteResult};
+    use serde_json::{json, Value};
+    use std::collections::{BTreeMap, BTreeSet};
+    use widget_types::{
+        ChangeSource, DbChange, FieldChange, Modifier, RevisionDiff, WidgetConfiguration,
+        WidgetModifier, WidgetRevision,
+    };
+
+    /// Bounds changes from the same source within this window are folded into the latest
+    /// revision, so moving a widget around doesn't leave a revision per step.
+    const COALESCE_WINDOW_MS: i64 = 60 * 1000;
+
+    const REVISION_COLUMNS: &str =
+        "id, revision, source, created_at, deleted, widget, modifiers, disabled_modifiers";
+
+    fn source_name(source: ChangeSource) -> DbResult<String> {
+        Ok(serde_json::to_value(source)?
+            .as_str()
+            .unwrap_or_default()
+            .to_string())
+    }
+
+    fn revision_from_row(row: &rusqlite::Row) -> SqliteResult<DbResult<WidgetRevision>> {
+        let id: i64 = row.get(0)?;
+        let revision: u32 = row.get(1)?;
+        let source: String = row.get(2)?;
+        let created_at: i64 = row.get(3)?;
+        let deleted: bool = row.get(4)?;
+        let widget: String = row.get(5)?;
+        let modifiers: String = row.get(6)?;
+        let disabled_modifiers: String = row.get(7)?;
+
+        Ok(decode(
+            "widget_revisions",
+            id,
+            "source",
+            &Value::String(source).to_string(),
+        )
+        .and_then(|source| {
+            Ok(WidgetRevision {
+                revision,
+                source,
+                created_at: created_at.to_string(),
+                deleted,
+                widget: decode("widget_revisions", id, "widget", &widget)?,
+                modifiers: decode("widget_revisions", id, "modifiers", &modifiers)?,
+                disabled_modifiers: decode(
+                    "widget_revisions",
+                    id,
+                    "disabled_modifiers",
+                    &disabled_modifiers,
+                )?,
+            })
+        }))
+    }
+
+    /// Whether the widget is in the trash, its configuration, its modifiers and the ids of the
+    /// disabled ones.
+    type Snapshot = (bool, WidgetConfiguration, Vec<Modifier>, Vec<String>);
+
+    /// The widget and its modifiers as they are stored right now, `None` once it is purged or
+    /// when its row can't be read.
+    fn current_revision(conn: &Connection, widget_id: &str) -> DbResult<Option<Snapshot>> {
+        let row = conn
+            .query_row(
+                &format!(
+                    "SELECT {}, deleted_at IS NOT NULL FROM widgets WHERE widget_id = ?",
+                    WIDGET_COLUMNS
+                ),
+                [widget_id],
+                |row| Ok((widget_from_row(row)?, row.get::<_, bool>(9)?)),
+            )
+            .optional()?;
+        let (mut widget, deleted) = match row {
+            Some((Ok(widget), deleted)) => (widget, deleted),
+            Some((Err(e @ DbError::CorruptRow { .. }), _)) => {
+                error!("Not recording a revision of {}: {}", widget_id, e);
+                return Ok(None);
+            }
+            Some((Err(e), _)) => return Err(e),
+            None => return Ok(None),
+        };
+        widget.is_open = false;
+
+        let mut stmt = conn.prepare(
+            "SELECT id, modifier_type, enabled FROM modifiers WHERE widget_id = ? ORDER BY id",
+        )?;
+        let rows = stmt.query_map([widget_id], |row| {
+            let id: i64 = row.get(0)?;
+            let modifier_type: String = row.get(1)?;
+            let enabled: bool = row.get(2)?;
+            Ok(
+                decode::<Modifier>("modifiers", id, "modifier_type", &modifier_type)
+                    .map(|modifier| (modifier, enabled)),
+            )
+        })?;
+        let (modifiers, enabled): (Vec<_>, Vec<_>) = skip_corrupt(rows)?.into_iter().unzip();
+        let disabled = modifiers
+            .iter()
+            .zip(enabled)
+            .filter(|(_, enabled)| !enabled)
+            .map(|(modifier, _)| modifier.modifier_id().0.clone())
+            .collect();
+
+        Ok(Some((deleted, widget, modifiers, disabled)))
+    }
+
+    /// What a revision is compared and diffed as. Modifiers are keyed by their id so that
+    /// reordering them isn't a change.
+    fn revision_value(
+        deleted: bool,
+        widget: &WidgetConfiguration,
+        modifiers: &[Modifier],
+        disabled: &[String],
+    ) -> DbResult<Value> {
+        let mut by_id = serde_json::Map::new();
+        for modifier in modifiers {
+            let id = &modifier.modifier_id().0;
+            let mut value = serde_json::to_value(modifier)?;
+            if let Some(value) = value.as_object_mut() {
+                value.insert("enabled".to_string(), (!disabled.contains(id)).into());
+            }
+            by_id.insert(id.clone(), value);
+        }
+        Ok(json!({
+            "deleted": deleted,
+            "widget": widget,
+            "modifiers": by_id,
+        }))
+    }
+
+    fn without_bounds(value: &Value) -> Value {
+        let mut value = value.clone();
+        if let Some(widget) = value.get_mut("widget").and_then(Value::as_object_mut) {
+            widget.remove("bounds");
+        }
+        value
+    }
+
+    /// Stores the widget's current configuration as its next revision. Nothing is written when
+    /// it is the same as the latest revision, and repeated bounds changes are coalesced, see
+    /// `COALESCE_WINDOW_MS`.
+    pub(crate) fn record_revision(
+        conn: &Connection,
+        widget_id: &str,
+        source: ChangeSource,
+    ) -> DbResult<()> {
+        let Some((deleted, widget, modifiers, disabled)) = current_revision(conn, widget_id)?
+        else {
+            return Ok(());
+        };
+        let current = revision_value(deleted, &widget, &modifiers, &disabled)?;
+        let now = jiff::Timestamp::now().as_millisecond();
+
+        let latest = {
+            let mut stmt = conn.prepare(&format!(
+                "SELECT {} FROM widget_revisions WHERE widget_id = ? ORDER BY revision DESC LIMIT 2",
+                REVISION_COLUMNS
+            ))?;
+            let rows = stmt.query_map([widget_id], |row| {
+                Ok((row.get::<_, i64>(0)?, revision_from_row(row)?))
+            })?;
+            let mut latest = vec![];
+            for row in rows {
+                let (id, revision) = row?;
+                let revision = revision?;
+                let value = revision_value(
+                    revision.deleted,
+                    &revision.widget,
+                    &revision.modifiers,
+                    &revision.disabled_modifiers,
+                )?;
+                latest.push((id, revision, value));
+            }
+            latest
+        };
+
+        if let Some((id, last, last_value)) = latest.first() {
+            if *last_value == current {
+                return Ok(());
+            }
+
+            let bounds_only = |a: &Value, b: &Value| without_bounds(a) == without_bounds(b);
+            let last_created_at = last.created_at.parse::<i64>().unwrap_or_default();
+            let coalesce = last.source == source
+                && now - last_created_at < COALESCE_WINDOW_MS
+                && bounds_only(last_value, &current)
+                && latest
+                    .get(1)
+                    .is_some_and(|(_, _, previous)| bounds_only(previous, last_value));
+            if coalesce {
+                conn.execute(
+                    "UPDATE widget_revisions SET widget = ?, created_at = ? WHERE id = ?",
+                    params![serde_json::to_string(&widget)?, now, id],
+                )?;
+                return Ok(());
+            }
+        }
+
+        let revision = latest.first().map_or(1, |(_, last, _)| last.revision + 1);
+        conn.execute(
+            r#"
+            INSERT INTO widget_revisions (
+                widget_id, revision, source, created_at, deleted, widget, modifiers,
+                disabled_modifiers
+            )
+            VALUES (?, ?, ?, ?, ?, ?, ?, ?)
+            "#,
+            params![
+                widget_id,
+                revision,
+                source_name(source)?,
+                now,
+                deleted,
+                serde_json::to_string(&widget)?,
+                serde_json::to_string(&modifiers)?,
+                serde_json::to_string(&disabled)?,
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Flattens nested objects into dotted paths, anything else is a leaf.
+    fn flatten(prefix: String, value: Value, out: &mut BTreeMap<String, Value>) {
+        match value {
+            Value::Object(map) if !map.is_empty() => {
+                for (key, value) in map {
+                    let path = if prefix.is_empty() {
+                        key
+                    } else {
+                        format!("{}.{}", prefix, key)
+                    };
+                    flatten(path, value, out);
+                }
+            }
+            value => {
+                out.insert(prefix, value);
+            }
+        }
+    }
+
+    impl Database {
+        fn flat_revision(
+            &self,
+            widget_id: &str,
+            revision: u32,
+        ) -> DbResult<BTreeMap<String, Value>> {
+            let revision = self.get_revision(widget_id, revision)?;
+            let mut fields = BTreeMap::new();
+            flatten(
+                String::new(),
+                revision_value(
+                    revision.deleted,
+                    &revision.widget,
+                    &revision.modifiers,
+                    &revision.disabled_modifiers,
+                )?,
+                &mut fields,
+            );
+            Ok(fields)
+        }
+
+        /// Every revision of a widget, newest first. Revisions are kept while the widget is in
+        /// the trash and removed when it is purged.
+        pub fn list_revisions(&self, widget_id: &str) -> DbResult<Vec<WidgetRevision>> {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {} FROM widget_revisions WHERE widget_id = ? ORDER BY revision DESC",
+                REVISION_COLUMNS
+            ))?;
+            let rows = stmt.query_map([widget_id], revision_from_row)?;
+            let revisions = skip_corrupt(rows)?;
+            if revisions.is_empty() {
+                return Err(DbError::NotFound(format!(
+                    "revisions of widget {}",
+                    widget_id
+                )));
+            }
+            Ok(revisions)
+        }
+
+        pub fn get_revision(&self, widget_id: &str, revision: u32) -> DbResult<WidgetRevision> {
+            self.conn
+                .query_row(
+                    &format!(
+                        "SELECT {} FROM widget_revisions WHERE widget_id = ? AND revision = ?",
+                        REVISION_COLUMNS
+                    ),
+                    params![widget_id, revision],
+                    revision_from_row,
+                )
+                .optional()?
+                .ok_or_else(|| {
+                    DbError::NotFound(format!("revision {} of widget {}", revision, widget_id))
+                })?
+        }
+
+        /// Every field that differs between two revisions of a widget.
+        pub fn diff_revisions(
+            &self,
+            widget_id: &str,
+            from: u32,
+            to: u32,
+        ) -> DbResult<RevisionDiff> {
+            let mut before = self.flat_revision(widget_id, from)?;
+            let mut after = self.flat_revision(widget_id, to)?;
+
+            let paths = before
+                .keys()
+                .chain(after.keys())
+                .cloned()
+                .collect::<BTreeSet<_>>();
+            let changes = paths
+                .into_iter()
+                .filter_map(|path| {
+                    let old = before.remove(&path);
+                    let new = after.remove(&path);
+                    (old != new).then(|| FieldChange {
+                        path,
+                        before: old.map(|value| value.to_string()),
+                        after: new.map(|value| value.to_string()),
+                    })
+                })
+                .collect();
+
+            Ok(RevisionDiff {
+                widget_id: widget_id.to_string(),
+                from,
+                to,
+                changes,
+            })
+        }
+
+        /// Puts a widget's configuration and modifiers back to how they were at `revision`,
+        /// which is recorded as a new revision. Whether the widget is open doesn't change.
+        pub fn rollback_widget(
+            &mut self,
+            widget_id: &str,
+            revision: u32,
+            source: ChangeSource,
+        ) -> DbResult<WidgetConfiguration> {
+            let target = self.get_revision(widget_id, revision)?;
+            if target.deleted {
+                return Err(DbError::InvalidInput(format!(
+                    "revision {} is from while the widget was in the trash",
+                    revision
+                )));
+            }
+            let current = self.get_widget_configuration_by_id(widget_id)?;
+            let widget = WidgetConfiguration {
+                id: current.id,
+                widget_id: current.widget_id,
+                is_open: current.is_open,
+                ..target.widget
+            };
+
+            let tx = self.conn.transaction()?;
+            tx.execute(
+                r#"
+                UPDATE widgets
+                SET title = ?, widget_type = ?, level = ?, transparent = ?, decorations = ?, bounds = ?
+                WHERE widget_id = ?
+                "#,
+                params![
+                    widget.title,
+                    serde_json::to_string(&widget.widget_type)?,
+                    serde_json::to_string(&widget.level)?,
+                    widget.transparent as i32,
+                    widget.decorations as i32,
+                    serde_json::to_string(&widget.bounds)?,
+                    widget_id,
+                ],
+            )?;
+            // Modifiers that are still there are updated in place so they keep their run state
+            let kept = target
+                .modifiers
+                .iter()
+                .map(|modifier| modifier.modifier_id().0.clone())
+                .collect::<Vec<_>>();
+            tx.execute(
+                "DELETE FROM modifiers WHERE widget_id = ? AND modifier_id NOT IN (SELECT value FROM json_each(?))",
+                params![widget_id, serde_json::to_string(&kept)?],
+            )?;
+            for modifier in target.modifiers {
+                let enabled = !target
+                    .disabled_modifiers
+                    .contains(&modifier.modifier_id().0);
+                let modifier_type = serde_json::to_string(&modifier)?;
+                // A changed modifier counts as never run, like after `update_widget_modifier`
+                let updated = tx.execute(
+                    r#"
+                    UPDATE modifiers
+                    SET enabled = ?3,
+                        last_run_at = CASE WHEN modifier_type = ?2 THEN last_run_at END,
+                        consecutive_failures =
+                            CASE WHEN modifier_type = ?2 THEN consecutive_failures ELSE 0 END,
+                        modifier_type = ?2
+                    WHERE widget_id = ?1 AND modifier_id = ?4
+                    "#,
+                    params![widget_id, modifier_type, enabled, modifier.modifier_id().0],
+                )?;
+                if updated == 0 {
+                    insert_modifier_row(
+                        &tx,
+                        &WidgetModifier {
+                            enabled,
+                            ..WidgetModifier::new(widget.widget_id.clone(), modifier)
+                        },
+                    )?;
+                }
+            }
+            record_revision(&tx, widget_id, source)?;
+            tx.commit()?;
+            self.publish(DbChange::WidgetSaved {
+                widget_id: widget_id.to_string(),
+            });
+            self.publish(DbChange::ModifiersChanged {
+                widget_id: widget_id.to_string(),
+            });
+            Ok(widget)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use widget_types::{NanoId, WidgetBounds};
+
+        fn refresh(modifier_id: &str, interval_sec: i32) -> WidgetModifier {
+            WidgetModifier::new(
+                NanoId("w".to_string()),
+                Modifier::Refresh {
+                    modifier_id: NanoId(modifier_id.to_string()),
+                    interval_sec,
+                },
+            )
+        }
+
+        fn bounds(x: u32) -> WidgetBounds {
+            WidgetBounds {
+                x,
+                y: 0,
+                width: 300,
+                height: 200,
+            }
+        }
+
+        fn db_with_widget() -> Database {
+            let mut db = Database::from(true).unwrap();
+            db.insert_widget_configuration(
+                vec![WidgetConfiguration::new()
+                    .with_widget_id(NanoId("w".to_string()))
+                    .with_title("First".to_string())],
+                ChangeSource::Api,
+            )
+            .unwrap();
+            db
+        }
+
+        #[test]
+        fn test_changes_are_recorded() {
+            let mut db = db_with_widget();
+            db.insert_widget_modifier(refresh("m", 60), ChangeSource::Api)
+                .unwrap();
+            // Open state isn't configuration
+            db.update_widget_open_state(NanoId("w".to_string()), true)
+                .unwrap();
+            db.delete_widget("w", ChangeSource::Tray).unwrap();
+
+            let revisions = db.list_revisions("w").unwrap();
+            assert_eq!(
+                revisions
+                    .iter()
+                    .map(|r| (r.revision, r.source, r.deleted, r.modifiers.len()))
+                    .collect::<Vec<_>>(),
+                vec![
+                    (3, ChangeSource::Tray, true, 1),
+                    (2, ChangeSource::Api, false, 1),
+                    (1, ChangeSource::Api, false, 0),
+                ]
+            );
+            assert!(matches!(
+                db.list_revisions("missing"),
+                Err(DbError::NotFound(_))
+            ));
+        }
+
+        #[test]
+        fn test_bounds_changes_are_coalesced() {
+            let db = db_with_widget();
+            for x in [10, 20, 30] {
+                db.update_widget_bounds("w".to_string(), bounds(x), ChangeSource::Api)
+                    .unwrap();
+            }
+            // A different source starts a new revision
+            db.update_widget_bounds("w".to_string(), bounds(40), ChangeSource::Ipc)
+                .unwrap();
+
+            let revisions = db.list_revisions("w").unwrap();
+            let xs = revisions
+                .iter()
+                .map(|r| r.widget.bounds.x)
+                .collect::<Vec<_>>();
+            // The first move can't fold into the revision that created the widget, the next
+            // ones fold into the first move
+            assert_eq!(xs, vec![40, 30, WidgetConfiguration::new().bounds.x]);
+        }
+
+        #[test]
+        fn test_diff_and_rollback() {
+            let mut db = db_with_widget();
+            db.insert_widget_modifier(refresh("m", 60), ChangeSource::Api)
+                .unwrap();
+            db.update_widget_bounds("w".to_string(), bounds(10), ChangeSource::Api)
+                .unwrap();
+            db.delete_widget_modifier("w", "m", ChangeSource::Api)
+                .unwrap();
+
+            let diff = db.diff_revisions("w", 2, 4).unwrap();
+            let paths = diff
+                .changes
+                .iter()
+                .map(|change| change.path.as_str())
+                .collect::<Vec<_>>();
+            assert!(paths.contains(&"widget.bounds.x"));
+            assert!(paths.contains(&"modifiers.m.content.interval_sec"));
+            let x = diff
+                .changes
+                .iter()
+                .find(|change| change.path == "widget.bounds.x")
+                .unwrap();
+            assert_eq!(x.after.as_deref(), Some("10"));
+            assert!(db.diff_revisions("w", 1, 1).unwrap().changes.is_empty());
+            assert!(matches!(
+                db.diff_revisions("w", 1, 9),
+                Err(DbError::NotFound(_))
+            ));
+
+            let widget = db.rollback_widget("w", 2, ChangeSource::Api).unwrap();
+            assert_eq!(widget.bounds, WidgetConfiguration::new().bounds);
+            assert_eq!(db.get_widget_modifier("w").unwrap().len(), 1);
+            assert_eq!(
+                db.get_widget_configuration_by_id("w").unwrap().bounds,
+                widget.bounds
+            );
+            let latest = &db.list_revisions("w").unwrap()[0];
+            assert_eq!(latest.revision, 5);
+            assert_eq!(latest.modifiers.len(), 1);
+
+            // Rolling back to how things are leaves the modifier's switch and run state alone
+            db.set_modifier_enabled("w", "m", false, ChangeSource::Api)
+                .unwrap();
+            db.record_modifier_run("m", 1_700_000_000_000).unwrap();
+            db.record_modifier_result("m", false, 1_700_000_000_000)
+                .unwrap();
+            let disabled = db.list_revisions("w").unwrap()[0].clone();
+            assert_eq!(disabled.disabled_modifiers, vec!["m".to_string()]);
+            db.rollback_widget("w", disabled.revision, ChangeSource::Api)
+                .unwrap();
+            let modifier = db.get_widget_modifier_by_id("w", "m").unwrap();
+            assert!(!modifier.enabled);
+            assert_eq!(modifier.last_run_at.as_deref(), Some("1700000000000"));
+            assert_eq!(modifier.consecutive_failures, 1);
+            let diff = db.diff_revisions("w", 5, disabled.revision).unwrap();
+            assert_eq!(diff.changes[0].path, "modifiers.m.enabled");
+            db.rollback_widget("w", 5, ChangeSource::Api).unwrap();
+            assert!(db.get_widget_modifier_by_id("w", "m").unwrap().enabled);
+
+            db.delete_widget("w", ChangeSource::Api).unwrap();
+            assert!(matches!(
+                db.rollback_widget("w", 2, ChangeSource::Api),
+                Err(DbError::NotFound(_))
+            ));
+            assert!(matches!(
+                db.rollback_widget("w", 8, ChangeSource::Api),
+                Err(DbError::InvalidInput(_))
+            ));
+        }
+    }
+}
@@ -0,0 +1,209 @@
+pub mod search {
+    use crate::{db::db::Database, error::error::DbResult, history::history::TIMESTAMP_MS};
+
+    use rusqlite::params;
+    use widget_types::{SearchHit, SearchResult};
+
+    /// Scraped values looked at per query, before grouping by widget.
+    const MAX_VALUE_HITS: usize = 1000;
+    const MAX_HITS_PER_WIDGET: usize = 20;
+
+    /// Turns user input into an FTS5 query that matches rows containing every word. Each word is
+    /// quoted so characters like `-`, `:` or `*` are searched for rather than parsed as syntax.
+    pub(crate) fn fts_query(input: &str) -> Option<String> {
+        let terms = input
+            .split_whitespace()
+            .map(|term| format!("\"{}\"", term.replace('"', "\"\"")))
+            .collect::<Vec<_>>();
+        (!terms.is_empty()).then(|| terms.join(" "))
+    }
+
+    impl Database {
+        /// Searches widget titles, URLs, inline HTML and scraped values, returning at most
+        /// `limit` widgets ordered by their best match. Widgets in the trash are left out.
+        pub fn search(&self, input: &str, limit: usize) -> DbResult<Vec<SearchResult>> {
+            let Some(query) = fts_query(input) else {
+                return Ok(vec![]);
+            };
+
+            // (best rank, result), bm25 ranks are negative and lower is better
+            let mut results: Vec<(f64, SearchResult)> = vec![];
+
+            let mut stmt = self.conn.prepare(
+                r#"
+                SELECT w.widget_id, w.title, widget_search.rank
+                FROM widget_search
+                JOIN widgets w ON w.id = widget_search.rowid
+                WHERE widget_search MATCH ? AND w.deleted_at IS NULL
+                "#,
+            )?;
+            let widgets = stmt.query_map([&query], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, f64>(2)?,
+                ))
+            })?;
+            for widget in widgets {
+                let (widget_id, title, rank) = widget?;
+                results.push((
+                    rank,
+                    SearchResult {
+                        widget_id,
+                        title,
+                        matched_widget: true,
+                        hits: vec![],
+                    },
+                ));
+            }
+
+            let mut stmt = self.conn.prepare(&format!(
+                r#"
+                SELECT s.widget_id, w.title, s.value, s.timestamp, value_search.rank
+                FROM value_search
+                JOIN scraped_data s ON s.id = value_search.rowid
+                JOIN widgets w ON w.widget_id = s.widget_id
+                WHERE value_search MATCH ?1 AND w.deleted_at IS NULL
+                ORDER BY value_search.rank, {} DESC
+                LIMIT ?2
+                "#,
+                TIMESTAMP_MS
+            ))?;
+            let values = stmt.query_map(params![query, MAX_VALUE_HITS as i64], |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    SearchHit {
+                        value: row.get(2)?,
+                        timestamp: row.get(3)?,
+                    },
+                    row.get::<_, f64>(4)?,
+                ))
+            })?;
+            for value in values {
+                let (widget_id, title, hit, rank) = value?;
+                match results
+                    .iter_mut()
+                    .find(|(_, result)| result.widget_id == widget_id)
+                {
+                    Some((best, result)) => {
+                        *best = best.min(rank);
+                        if result.hits.len() < MAX_HITS_PER_WIDGET {
+                            result.hits.push(hit);
+                        }
+                    }
+                    None => results.push((
+                        rank,
+                        SearchResult {
+                            widget_id,
+                            title,
+                            matched_widget: false,
+                            hits: vec![hit],
+                        },
+                    )),
+                }
+            }
+
+            results.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+            Ok(results
+                .into_iter()
+                .take(limit)
+                .map(|(_, result)| result)
+                .collect())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::fixtures::scraped;
+        use widget_types::{
+            ChangeSource, NanoId, UrlConfiguration, WidgetConfiguration, WidgetType,
+        };
+
+        fn widget(widget_id: &str, title: &str, url: &str) -> WidgetConfiguration {
+            WidgetConfiguration::new()
+                .with_widget_id(NanoId(widget_id.to_string()))
+                .with_title(title.to_string())
+                .with_widget_type(WidgetType::Url(UrlConfiguration {
+                    url: url.to_string(),
+                }))
+        }
+
+        fn insert_value(db: &Database, widget_id: &str, value: &str, timestamp: i64) {
+            db.insert_data(scraped(widget_id, value, timestamp))
+                .unwrap();
+        }
+
+        #[test]
+        fn test_fts_query() {
+            assert_eq!(fts_query("  "), None);
+            assert_eq!(
+                fts_query(r#"btc-usd "price"#),
+                Some(r#""btc-usd" """price""#.to_string())
+            );
+        }
+
+        #[test]
+        fn test_search_groups_by_widget() {
+            let mut db = Database::from(true).unwrap();
+            db.insert_widget_configuration(
+                vec![
+                    widget("btc", "Bitcoin price", "https://example.com/btc"),
+                    widget("weather", "Weather", "https://example.com/weather"),
+                ],
+                ChangeSource::Api,
+            )
+            .unwrap();
+            insert_value(&db, "btc", "$64,000", 1000);
+            insert_value(&db, "weather", "Sunny and warm", 2000);
+            insert_value(&db, "weather", "Rain, then sunny", 3000);
+
+            let results = db.search("sunny", 10).unwrap();
+            assert_eq!(results.len(), 1);
+            assert_eq!(results[0].widget_id, "weather");
+            assert_eq!(results[0].title, "Weather");
+            assert!(!results[0].matched_widget);
+            assert_eq!(results[0].hits.len(), 2);
+
+            let results = db.search("bitcoin", 10).unwrap();
+            assert_eq!(results.len(), 1);
+            assert!(results[0].matched_widget);
+            assert!(results[0].hits.is_empty());
+
+            let results = db.search("64,000", 10).unwrap();
+            assert_eq!(results[0].hits[0].timestamp, "1000");
+
+            assert_eq!(db.search("example.com", 10).unwrap().len(), 2);
+            assert_eq!(db.search("example.com", 1).unwrap().len(), 1);
+
+            // Values of widgets that no longer exist aren't results
+            insert_value(&db, "gone", "sunny", 4000);
+            assert_eq!(db.search("sunny", 10).unwrap().len(), 1);
+        }
+
+        #[test]
+        fn test_search_index_follows_changes() {
+            let mut db = Database::from(true).unwrap();
+            db.insert_widget_configuration(
+                vec![widget("w", "Old title", "https://example.com")],
+                ChangeSource::Api,
+            )
+            .unwrap();
+            insert_value(&db, "w", "needle", 1000);
+
+            db.conn
+                .execute(
+                    "UPDATE widgets SET title = 'New title' WHERE widget_id = 'w'",
+                    [],
+                )
+                .unwrap();
+            assert!(db.search("old", 10).unwrap().is_empty());
+            assert_eq!(db.search("new", 10).unwrap().len(), 1);
+
+            db.delete_widget("w", ChangeSource::Api).unwrap();
+            assert!(db.search("needle", 10).unwrap().is_empty());
+            assert!(db.search("new", 10).unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,265 @@
+pub mod service {
+    use crate::{
+        db::db::Database,
+        error::error::{DbError, DbResult},
+        paths::paths,
+        query::query::{QueryRequest, QueryResult, QUERY_TIME_LIMIT},
+    };
+
+    use log::info;
+    use std::{
+        path::{Path, PathBuf},
+        sync::{Arc, Condvar, Mutex, MutexGuard},
+    };
+    use tokio::sync::broadcast;
+    use widget_types::DbChange;
+
+    /// Read-only connections next to the writer. With WAL they read while a write is going on.
+    pub const DEFAULT_READERS: usize = 4;
+
+    struct Inner {
+        writer: Mutex<Database>,
+        /// Idle read-only connections, empty for an in-memory database, which reads through
+        /// the writer instead.
+        readers: Mutex<Vec<Database>>,
+        reader_returned: Condvar,
+        has_readers: bool,
+        /// Runs `POST /query` statements, kept apart from the pool so a slow one doesn't hold
+        /// up the app's reads. `None` for an in-memory database.
+        query: Option<Mutex<Database>>,
+        path: Option<PathBuf>,
+        /// The writer's channel, so subscribing doesn't wait for a write to finish
+        changes: broadcast::Sender<DbChange>,
+    }
+
+    /// The one place the app, the API and the scheduler get their database access from. Writes
+    /// go through a single connection one at a time, reads are spread over a small pool of
+    /// read-only connections.
+    #[derive(Clone)]
+    pub struct DbService {
+        inner: Arc<Inner>,
+    }
+
+    /// A read-only connection taken from the pool, put back when dropped.
+    struct Reader<'a> {
+        inner: &'a Inner,
+        db: Option<Database>,
+    }
+
+    impl Drop for Reader<'_> {
+        fn drop(&mut self) {
+            if let Some(db) = self.db.take() {
+                lock(&self.inner.readers).push(db);
+                self.inner.reader_returned.notify_one();
+            }
+        }
+    }
+
+    fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+        // A panic halfway through a call leaves nothing half written behind that a
+        // transaction wouldn't have rolled back, so the connection can still be used
+        mutex.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    impl DbService {
+        pub fn open_profile(profile: &str) -> DbResult<Self> {
+            let db_path = paths::database_path(profile)?;
+            if let Some(dir) = db_path.parent() {
+                std::fs::create_dir_all(dir)?;
+            }
+            info!("Opening profile {} at {:?}", profile, db_path);
+
+            Self::open(&db_path, DEFAULT_READERS)
+        }
+
+        /// Opens the database at `db_path`, migrating or recovering it first, with `readers`
+        /// read-only connections.
+        pub fn open(db_path: &Path, readers: usize) -> DbResult<Self> {
+            let writer = Database::open(db_path)?;
+            let readers = (0..readers)
+                .map(|_| Database::open_reader(db_path))
+                .collect::<DbResult<Vec<_>>>()?;
+            let query = Database::open_query(db_path)?;
+            Ok(Self::with_connections(writer, readers, Some(query)))
+        }
+
+        /// Wraps an in-memory database, mostly for tests.
+        pub fn in_memory() -> DbResult<Self> {
+            Ok(Self::with_connections(Database::from(true)?, vec![], None))
+        }
+
+        fn with_connections(
+            writer: Database,
+            readers: Vec<Database>,
+            query: Option<Database>,
+        ) -> Self {
+            Self {
+                inner: Arc::new(Inner {
+                    changes: writer.changes.clone(),
+                    path: writer.path.clone(),
+                    writer: Mutex::new(writer),
+                    has_readers: !readers.is_empty(),
+                    readers: Mutex::new(readers),
+                    query: query.map(Mutex::new),
+                    reader_returned: Condvar::new(),
+                }),
+            }
+        }
+
+        /// The database file, `None` for an in-memory database.
+        pub fn path(&self) -> Option<&Path> {
+            self.inner.path.as_deref()
+        }
+
+        /// Changes committed from now on, see `Database::subscribe`.
+        pub fn subscribe(&self) -> broadcast::Receiver<DbChange> {
+            self.inner.changes.subscribe()
+        }
+
+        /// Runs `read` on an idle read-only connection, waiting for one if they are all busy.
+        /// Blocks the calling thread, async code should use `read`.
+        pub fn blocking_read<T>(&self, read: impl FnOnce(&Database) -> DbResult<T>) -> DbResult<T> {
+            if !self.inner.has_readers {
+                return read(&lock(&self.inner.writer));
+            }
+
+            let reader = {
+                let mut idle = lock(&self.inner.readers);
+                loop {
+                    if let Some(db) = idle.pop() {
+                        break Reader {
+                            inner: &self.inner,
+                            db: Some(db),
+                        };
+                    }
+                    idle = self
+                        .inner
+                        .reader_returned
+                        .wait(idle)
+                        .unwrap_or_else(|e| e.into_inner());
+                }
+            };
+            read(reader.db.as_ref().expect("taken until dropped"))
+        }
+
+        /// Runs `write` on the writer connection once every earlier write is done. Blocks the
+        /// calling thread, async code should use `write`.
+        pub fn blocking_write<T>(
+            &self,
+            write: impl FnOnce(&mut Database) -> DbResult<T>,
+        ) -> DbResult<T> {
+            write(&mut lock(&self.inner.writer))
+        }
+
+        /// `blocking_read` on a thread meant for blocking work, so the runtime stays free.
+        pub async fn read<T, F>(&self, read: F) -> DbResult<T>
+        where
+            T: Send + 'static,
+            F: FnOnce(&Database) -> DbResult<T> + Send + 'static,
+        {
+            let service = self.clone();
+            tokio::task::spawn_blocking(move || service.blocking_read(read))
+                .await
+                .map_err(|e| DbError::Task(e.to_string()))?
+        }
+
+        /// `blocking_write` on a thread meant for blocking work, so the runtime stays free.
+        pub async fn write<T, F>(&self, write: F) -> DbResult<T>
+        where
+            T: Send + 'static,
+            F: FnOnce(&mut Database) -> DbResult<T> + Send + 'static,
+        {
+            let service = self.clone();
+            tokio::task::spawn_blocking(move || service.blocking_write(write))
+                .await
+                .map_err(|e| DbError::Task(e.to_string()))?
+        }
+
+        /// Runs a user's read-only statement over the query views, see `Database::run_query`.
+        pub async fn query(&self, request: QueryRequest) -> DbResult<QueryResult> {
+            let service = self.clone();
+            tokio::task::spawn_blocking(move || match &service.inner.query {
+                Some(db) => lock(db).run_query(&request, QUERY_TIME_LIMIT),
+                None => Err(DbError::InvalidInput(
+                    "queries need a database file".to_string(),
+                )),
+            })
+            .await
+            .map_err(|e| DbError::Task(e.to_string()))?
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::fixtures::TempDb;
+        use std::{sync::mpsc, thread, time::Duration};
+        use widget_types::{ChangeSource, NanoId, WidgetConfiguration};
+
+        #[test]
+        fn test_reads_do_not_wait_for_writes() {
+            let temp = TempDb::new();
+            let service = DbService::open(&temp.path(), 2).unwrap();
+            service
+                .blocking_write(|db| {
+                    db.insert_widget_configuration(
+                        vec![WidgetConfiguration::new().with_widget_id(NanoId("w".to_string()))],
+                        ChangeSource::Api,
+                    )
+                })
+                .unwrap();
+
+            // Hold the writer in an open transaction while reading from another thread
+            let (started, wait_for_start) = mpsc::channel();
+            let (finish, wait_for_finish) = mpsc::channel::<()>();
+            let writer = service.clone();
+            let writing = thread::spawn(move || {
+                writer.blocking_write(|db| {
+                    let tx = db.conn.transaction()?;
+                    tx.execute("DELETE FROM widgets", [])?;
+                    started.send(()).unwrap();
+                    wait_for_finish.recv().unwrap();
+                    tx.commit()?;
+                    Ok(())
+                })
+            });
+            wait_for_start.recv().unwrap();
+
+            let (read, wait_for_read) = mpsc::channel();
+            let reader = service.clone();
+            thread::spawn(move || {
+                read.send(reader.blocking_read(|db| db.get_configuration()))
+                    .unwrap()
+            });
+            let widgets = wait_for_read
+                .recv_timeout(Duration::from_secs(5))
+                .expect("read waited for the write")
+                .unwrap();
+            assert_eq!(widgets.len(), 1);
+
+            finish.send(()).unwrap();
+            writing.join().unwrap().unwrap();
+            assert!(service
+                .blocking_read(|db| db.get_configuration())
+                .unwrap()
+                .is_empty());
+            assert!(matches!(
+                service.blocking_read(|db| db.set_settings(&db.get_settings()?)),
+                Err(DbError::Sqlite(_))
+            ));
+        }
+
+        #[tokio::test]
+        async fn test_in_memory_reads_through_writer() {
+            let service = DbService::in_memory().unwrap();
+            let mut changes = service.subscribe();
+            service
+                .write(|db| db.set_setting("trash_retention_days", 3.into(), None))
+                .await
+                .unwrap();
+            let settings = service.read(|db| db.get_settings()).await.unwrap();
+            assert_eq!(settings.trash_retention_days, 3);
+            assert_eq!(changes.try_recv().unwrap(), DbChange::SettingsChanged);
+        }
+    }
+}
@@ -0,0 +1,273 @@
+pub mod settings {
+    use crate::{
+        db::db::{default_app_settings, Database},
+        error::error::{DbError, DbResult},
+    };
+
+    use log::error;
+    use rusqlite::{params, Connection, OptionalExtension};
+    use serde_json::{Map, Value};
+    use widget_types::{AppSettings, DbChange, SettingEntry};
+
+    /// Anything longer is almost certainly a typo, a century in the trash.
+    const MAX_TRASH_RETENTION_DAYS: u32 = 36500;
+
+    fn default_values() -> DbResult<Map<String, Value>> {
+        match serde_json::to_value(default_app_settings())? {
+            Value::Object(values) => Ok(values),
+            _ => unreachable!("AppSettings serializes to an object"),
+        }
+    }
+
+    fn validate(settings: &AppSettings) -> DbResult<()> {
+        if settings.trash_retention_days == 0
+            || settings.trash_retention_days > MAX_TRASH_RETENTION_DAYS
+        {
+            return Err(DbError::InvalidInput(format!(
+                "trash_retention_days must be between 1 and {}",
+                MAX_TRASH_RETENTION_DAYS
+            )));
+        }
+        if !settings.email.is_empty() && !settings.email.contains('@') {
+            return Err(DbError::InvalidInput(format!(
+                "{} is not an email address",
+                settings.email
+            )));
+        }
+        Ok(())
+    }
+
+    /// Reads `values` as settings, checking both the types and the allowed ranges.
+    fn to_settings(values: Map<String, Value>) -> DbResult<AppSettings> {
+        let settings = serde_json::from_value(Value::Object(values))
+            .map_err(|e| DbError::InvalidInput(e.to_string()))?;
+        validate(&settings)?;
+        Ok(settings)
+    }
+
+    /// Stores one setting, bumping its version when the value changed. Returns whether it did.
+    pub(crate) fn write_setting(conn: &Connection, key: &str, value: &Value) -> DbResult<bool> {
+        let rows_affected = conn.execute(
+            r#"
+            INSERT INTO settings (key, value, version, updated_at) VALUES (?1, ?2, 1, ?3)
+            ON CONFLICT (key) DO UPDATE
+            SET value = excluded.value, version = version + 1, updated_at = excluded.updated_at
+            WHERE value != excluded.value
+            "#,
+            params![
+                key,
+                value.to_string(),
+                jiff::Timestamp::now().as_millisecond()
+            ],
+        )?;
+        Ok(rows_affected > 0)
+    }
+
+    impl Database {
+        fn stored_settings(&self) -> DbResult<Vec<SettingEntry>> {
+            let mut stmt = self
+                .conn
+                .prepare("SELECT key, value, version, updated_at FROM settings ORDER BY key")?;
+            let rows = stmt.query_map([], |row| {
+                Ok(SettingEntry {
+                    key: row.get(0)?,
+                    value: row.get(1)?,
+                    version: row.get(2)?,
+                    updated_at: Some(row.get::<_, i64>(3)?.to_string()),
+                })
+            })?;
+            Ok(rows.collect::<rusqlite::Result<Vec<_>>>()?)
+        }
+
+        /// The stored settings on top of the defaults. Stored values that no longer fit their
+        /// setting are logged and read as the default.
+        pub fn get_settings(&self) -> DbResult<AppSettings> {
+            let mut values = default_values()?;
+            for entry in self.stored_settings()? {
+                // Settings that were removed since are left alone
+                if !values.contains_key(&entry.key) {
+                    continue;
+                }
+                let mut candidate = values.clone();
+                match serde_json::from_str(&entry.value) {
+                    Ok(value) => {
+                        candidate.insert(entry.key.clone(), value);
+                    }
+                    Err(e) => {
+                        error!("Ignoring unreadable setting {}: {}", entry.key, e);
+                        continue;
+                    }
+                }
+                match to_settings(candidate.clone()) {
+                    Ok(_) => values = candidate,
+                    Err(e) => error!("Ignoring invalid setting {}: {}", entry.key, e),
+                }
+            }
+            Ok(serde_json::from_value(Value::Object(values))?)
+        }
+
+        /// Validates and stores every setting. Only the ones that changed get a new version.
+        pub fn set_settings(&self, settings: &AppSettings) -> DbResult<()> {
+            validate(settings)?;
+            let Value::Object(values) = serde_json::to_value(settings)? else {
+                unreachable!("AppSettings serializes to an object");
+            };
+
+            let tx = self.conn.unchecked_transaction()?;
+            let mut changed = false;
+            for (key, value) in values {
+                changed |= write_setting(&tx, &key, &value)?;
+            }
+            tx.commit()?;
+            if changed {
+                self.publish(DbChange::SettingsChanged);
+            }
+            Ok(())
+        }
+
+        /// Every setting with its version, defaults included.
+        pub fn get_setting_entries(&self) -> DbResult<Vec<SettingEntry>> {
+            let stored = self.stored_settings()?;
+            let Value::Object(values) = serde_json::to_value(self.get_settings()?)? else {
+                unreachable!("AppSettings serializes to an object");
+            };
+            Ok(values
+                .into_iter()
+                .map(|(key, value)| {
+                    let stored = stored.iter().find(|entry| entry.key == key);
+                    SettingEntry {
+                        value: value.to_string(),
+                        version: stored.map_or(0, |entry| entry.version),
+                        updated_at: stored.and_then(|entry| entry.updated_at.clone()),
+                        key,
+                    }
+                })
+                .collect())
+        }
+
+        pub fn get_setting(&self, key: &str) -> DbResult<SettingEntry> {
+            self.get_setting_entries()?
+                .into_iter()
+                .find(|entry| entry.key == key)
+                .ok_or_else(|| DbError::NotFound(format!("setting {}", key)))
+        }
+
+        /// Changes a single setting. With `expected_version` the write only goes through when
+        /// nobody else changed the setting since it was read.
+        pub fn set_setting(
+            &self,
+            key: &str,
+            value: Value,
+            expected_version: Option<u32>,
+        ) -> DbResult<SettingEntry> {
+            let Value::Object(mut values) = serde_json::to_value(self.get_settings()?)? else {
+                unreachable!("AppSettings serializes to an object");
+            };
+            if !values.contains_key(key) {
+                return Err(DbError::NotFound(format!("setting {}", key)));
+            }
+            values.insert(key.to_string(), value.clone());
+            to_settings(values).map_err(|e| match e {
+                DbError::InvalidInput(reason) => {
+                    DbError::InvalidInput(format!("{}: {}", key, reason))
+                }
+                e => e,
+            })?;
+
+            let tx = self.conn.unchecked_transaction()?;
+            if let Some(expected_version) = expected_version {
+                let version = tx
+                    .query_row("SELECT version FROM settings WHERE key = ?", [key], |row| {
+                        row.get::<_, u32>(0)
+                    })
+                    .optional()?
+                    .unwrap_or(0);
+                if version != expected_version {
+                    return Err(DbError::Conflict(format!(
+                        "setting {} is at version {}, not {}",
+                        key, version, expected_version
+                    )));
+                }
+            }
+            let changed = write_setting(&tx, key, &value)?;
+            tx.commit()?;
+            if changed {
+                self.publish(DbChange::SettingsChanged);
+            }
+            self.get_setting(key)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use serde_json::json;
+
+        #[test]
+        fn test_defaults_until_set() {
+            let db = Database::from(true).unwrap();
+            assert_eq!(db.get_settings().unwrap(), default_app_settings());
+            assert!(db
+                .get_setting_entries()
+                .unwrap()
+                .iter()
+                .all(|entry| entry.version == 0));
+
+            let settings = AppSettings {
+                show_tray_icon: false,
+                ..default_app_settings()
+            };
+            db.set_settings(&settings).unwrap();
+            db.set_settings(&settings).unwrap();
+            assert_eq!(db.get_settings().unwrap(), settings);
+            assert_eq!(db.get_setting("show_tray_icon").unwrap().version, 1);
+        }
+
+        #[test]
+        fn test_set_setting_validates() {
+            let db = Database::from(true).unwrap();
+
+            let entry = db
+                .set_setting("trash_retention_days", json!(7), Some(0))
+                .unwrap();
+            assert_eq!(entry.value, "7");
+            assert_eq!(entry.version, 1);
+            assert_eq!(db.get_settings().unwrap().trash_retention_days, 7);
+
+            assert!(matches!(
+                db.set_setting("trash_retention_days", json!(8), Some(0)),
+                Err(DbError::Conflict(_))
+            ));
+            for (key, value) in [
+                ("trash_retention_days", json!(0)),
+                ("trash_retention_days", json!("seven")),
+                ("email", json!("not an address")),
+            ] {
+                assert!(matches!(
+                    db.set_setting(key, value, None),
+                    Err(DbError::InvalidInput(_))
+                ));
+            }
+            assert!(matches!(
+                db.set_setting("theme", json!("dark"), None),
+                Err(DbError::NotFound(_))
+            ));
+        }
+
+        #[test]
+        fn test_invalid_stored_value_reads_as_default() {
+            let db = Database::from(true).unwrap();
+            db.conn
+                .execute(
+                    "INSERT INTO settings (key, value, updated_at) VALUES ('show_tray_icon', '\"yes\"', 0), ('removed', '1', 0), ('email', '\"legacy\"', 0)",
+                    [],
+                )
+                .unwrap();
+            let settings = db.get_settings().unwrap();
+            assert!(settings.show_tray_icon);
+            // Values that fail validation are ignored too, so saving the rest still works
+            assert_eq!(settings.email, default_app_settings().email);
+            db.set_settings(&settings).unwrap();
+        }
+    }
+}
@@ -0,0 +1,188 @@
+pub mod tags {
+    use crate::{
+        db::db::{skip_corrupt, widget_from_row, Database, WIDGET_COLUMNS},
+        error::error::{DbError, DbResult},
+    };
+
+    use std::collections::BTreeSet;
+    use widget_types::{DbChange, TagInfo, WidgetConfiguration};
+
+    pub const MAX_TAG_LENGTH: usize = 64;
+
+    /// Tags are compared without surrounding whitespace and case, so `Crypto ` and `crypto`
+    /// group together.
+    pub fn normalize_tag(tag: &str) -> DbResult<String> {
+        let tag = tag.trim().to_lowercase();
+        if tag.is_empty() {
+            return Err(DbError::InvalidInput("tags can't be empty".to_string()));
+        }
+        if tag.chars().count() > MAX_TAG_LENGTH {
+            return Err(DbError::InvalidInput(format!(
+                "tag {:?} is longer than {} characters",
+                tag, MAX_TAG_LENGTH
+            )));
+        }
+        Ok(tag)
+    }
+
+    impl Database {
+        /// Tags of a widget outside the trash, sorted.
+        pub fn get_widget_tags(&self, widget_id: &str) -> DbResult<Vec<String>> {
+            self.get_widget_configuration_by_id(widget_id)?;
+            let mut stmt = self
+                .conn
+                .prepare("SELECT tag FROM widget_tags WHERE widget_id = ? ORDER BY tag")?;
+            let tags = stmt
+                .query_map([widget_id], |row| row.get(0))?
+                .collect::<rusqlite::Result<Vec<String>>>()?;
+            Ok(tags)
+        }
+
+        /// Replaces the tags of a widget, returning them normalized and sorted.
+        pub fn set_widget_tags(
+            &mut self,
+            widget_id: &str,
+            tags: &[String],
+        ) -> DbResult<Vec<String>> {
+            self.get_widget_configuration_by_id(widget_id)?;
+            let tags = tags
+                .iter()
+                .map(|tag| normalize_tag(tag))
+                .collect::<DbResult<BTreeSet<_>>>()?;
+
+            let tx = self.conn.transaction()?;
+            tx.execute("DELETE FROM widget_tags WHERE widget_id = ?", [widget_id])?;
+            for tag in &tags {
+                tx.execute(
+                    "INSERT INTO widget_tags (widget_id, tag) VALUES (?, ?)",
+                    [widget_id, tag],
+                )?;
+            }
+            tx.commit()?;
+            self.publish(DbChange::TagsChanged {
+                widget_id: widget_id.to_string(),
+            });
+            Ok(tags.into_iter().collect())
+        }
+
+        /// Every tag in use by a widget outside the trash.
+        pub fn list_tags(&self) -> DbResult<Vec<TagInfo>> {
+            let mut stmt = self.conn.prepare(
+                r#"
+                SELECT t.tag, COUNT(*) FROM widget_tags t
+                JOIN widgets w ON w.widget_id = t.widget_id
+                WHERE w.deleted_at IS NULL
+                GROUP BY t.tag
+                ORDER BY t.tag
+                "#,
+            )?;
+            let tags = stmt
+                .query_map([], |row| {
+                    Ok(TagInfo {
+                        tag: row.get(0)?,
+                        widget_count: row.get(1)?,
+                    })
+                })?
+                .collect::<rusqlite::Result<Vec<_>>>()?;
+            Ok(tags)
+        }
+
+        /// Widgets outside the trash carrying `tag`.
+        pub fn get_configuration_by_tag(&self, tag: &str) -> DbResult<Vec<WidgetConfiguration>> {
+            let tag = normalize_tag(tag)?;
+            let mut stmt = self.conn.prepare(&format!(
+                r#"
+                SELECT {} FROM widgets
+                WHERE deleted_at IS NULL
+                  AND widget_id IN (SELECT widget_id FROM widget_tags WHERE tag = ?)
+                "#,
+                WIDGET_COLUMNS
+            ))?;
+            let widgets = stmt.query_map([tag], widget_from_row)?;
+            skip_corrupt(widgets)
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use widget_types::{ChangeSource, NanoId};
+
+        fn db_with_widgets(ids: &[&str]) -> Database {
+            let mut db = Database::from(true).unwrap();
+            db.insert_widget_configuration(
+                ids.iter()
+                    .map(|id| WidgetConfiguration::new().with_widget_id(NanoId(id.to_string())))
+                    .collect(),
+                ChangeSource::Api,
+            )
+            .unwrap();
+            db
+        }
+
+        #[test]
+        fn test_tags_group_widgets() {
+            let mut db = db_with_widgets(&["a", "b", "c"]);
+            let tags = db
+                .set_widget_tags("a", &[" Crypto".to_string(), "prices".to_string()])
+                .unwrap();
+            assert_eq!(tags, vec!["crypto", "prices"]);
+            db.set_widget_tags("b", &["crypto".to_string(), "CRYPTO".to_string()])
+                .unwrap();
+            assert_eq!(db.get_widget_tags("b").unwrap(), vec!["crypto"]);
+
+            let mut grouped = db
+                .get_configuration_by_tag("crypto")
+                .unwrap()
+                .into_iter()
+                .map(|widget| widget.widget_id.0)
+                .collect::<Vec<_>>();
+            grouped.sort();
+            assert_eq!(grouped, vec!["a", "b"]);
+
+            // Trashed widgets drop out of groups and counts, and lose their tags when purged
+            db.delete_widget("b", ChangeSource::Api).unwrap();
+            assert_eq!(
+                db.list_tags().unwrap(),
+                vec![
+                    TagInfo {
+                        tag: "crypto".to_string(),
+                        widget_count: 1,
+                    },
+                    TagInfo {
+                        tag: "prices".to_string(),
+                        widget_count: 1,
+                    },
+                ]
+            );
+            db.purge_widget("b").unwrap();
+            let remaining: u32 = db
+                .conn
+                .query_row(
+                    "SELECT COUNT(*) FROM widget_tags WHERE widget_id = 'b'",
+                    [],
+                    |row| row.get(0),
+                )
+                .unwrap();
+            assert_eq!(remaining, 0);
+        }
+
+        #[test]
+        fn test_invalid_tags() {
+            let mut db = db_with_widgets(&["a"]);
+            assert!(matches!(
+                db.set_widget_tags("a", &["  ".to_string()]),
+                Err(DbError::InvalidInput(_))
+            ));
+            assert!(matches!(
+                db.set_widget_tags("a", &["x".repeat(MAX_TAG_LENGTH + 1)]),
+                Err(DbError::InvalidInput(_))
+            ));
+            assert!(matches!(
+                db.set_widget_tags("missing", &[]),
+                Err(DbError::NotFound(_))
+            ));
+            assert!(db.get_widget_tags("a").unwrap().is_empty());
+        }
+    }
+}
@@ -0,0 +1,212 @@
+pub mod trash {
+    use crate::{
+        db::db::{Database, WIDGET_COLUMNS},
+        error::error::{DbError, DbResult},
+        revisions::revisions::record_revision,
+    };
+
+    use log::info;
+    use rusqlite::{params, Connection};
+    use widget_types::{ChangeSource, DbChange, TrashedWidget};
+
+    const DAY_MS: i64 = 24 * 60 * 60 * 1000;
+
+    /// Removes a widget together with its modifiers, revisions and scrape history.
+    fn purge(conn: &Connection, widget_id: &str) -> DbResult<usize> {
+        conn.execute("DELETE FROM scraped_data WHERE widget_id = ?", [widget_id])?;
+        conn.execute(
+            "DELETE FROM scraped_rollups WHERE widget_id = ?",
+            [widget_id],
+        )?;
+        conn.execute(
+            "DELETE FROM widget_revisions WHERE widget_id = ?",
+            [widget_id],
+        )?;
+        conn.execute("DELETE FROM modifiers WHERE widget_id = ?", [widget_id])?;
+        conn.execute("DELETE FROM widget_tags WHERE widget_id = ?", [widget_id])?;
+        conn.execute(
+            "DELETE FROM layout_widgets WHERE widget_id = ?",
+            [widget_id],
+        )?;
+        Ok(conn.execute(
+            "DELETE FROM widgets WHERE widget_id = ? AND deleted_at IS NOT NULL",
+            [widget_id],
+        )?)
+    }
+
+    impl Database {
+        fn trash_retention_ms(&self) -> DbResult<i64> {
+            let days = self.get_settings()?.trash_retention_days;
+            Ok(days as i64 * DAY_MS)
+        }
+
+        /// Widgets in the trash, most recently deleted first.
+        pub fn get_trash(&self) -> DbResult<Vec<TrashedWidget>> {
+            let retention_ms = self.trash_retention_ms()?;
+            let mut stmt = self.conn.prepare(&format!(
+                r#"
+                SELECT {}, deleted_at FROM widgets
+                WHERE deleted_at IS NOT NULL
+                ORDER BY deleted_at DESC
+                "#,
+                WIDGET_COLUMNS
+            ))?;
+            let rows = stmt.query_map([], |row| {
+                let deleted_at: i64 = row.get(9)?;
+                Ok(
+                    crate::db::db::widget_from_row(row)?.map(|widget| TrashedWidget {
+                        widget,
+                        deleted_at: deleted_at.to_string(),
+                        purge_at: (deleted_at + retention_ms).to_string(),
+                    }),
+                )
+            })?;
+            crate::db::db::skip_corrupt(rows)
+        }
+
+        /// Takes a widget back out of the trash and marks it open again.
+        pub fn restore_widget(&self, widget_id: &str, source: ChangeSource) -> DbResult<()> {
+            let rows_affected = self.conn.execute(
+                "UPDATE widgets SET deleted_at = NULL, is_open = 1 WHERE widget_id = ? AND deleted_at IS NOT NULL",
+                [widget_id],
+            )?;
+            if rows_affected == 0 {
+                return Err(DbError::NotFound(format!("trashed widget {}", widget_id)));
+            }
+            record_revision(&self.conn, widget_id, source)?;
+            self.publish(DbChange::WidgetSaved {
+                widget_id: widget_id.to_string(),
+            });
+            Ok(())
+        }
+
+        /// Permanently deletes a widget that is in the trash.
+        pub fn purge_widget(&mut self, widget_id: &str) -> DbResult<()> {
+            let tx = self.conn.transaction()?;
+            if purge(&tx, widget_id)? == 0 {
+                return Err(DbError::NotFound(format!("trashed widget {}", widget_id)));
+            }
+            tx.commit()?;
+            self.publish(DbChange::WidgetPurged {
+                widget_id: widget_id.to_string(),
+            });
+            Ok(())
+        }
+
+        /// Permanently deletes everything in the trash, or only what was deleted before
+        /// `deleted_before_ms`. Returns the purged widget ids.
+        pub fn empty_trash(&mut self, deleted_before_ms: Option<i64>) -> DbResult<Vec<String>> {
+            let tx = self.conn.transaction()?;
+            let widget_ids = {
+                let mut stmt = tx.prepare(
+                    "SELECT widget_id FROM widgets WHERE deleted_at IS NOT NULL AND (?1 IS NULL OR deleted_at < ?1)",
+                )?;
+                let widget_ids = stmt
+                    .query_map(params![deleted_before_ms], |row| row.get::<_, String>(0))?
+                    .collect::<rusqlite::Result<Vec<_>>>()?;
+                widget_ids
+            };
+            for widget_id in &widget_ids {
+                purge(&tx, widget_id)?;
+            }
+            tx.commit()?;
+            for widget_id in &widget_ids {
+                self.publish(DbChange::WidgetPurged {
+                    widget_id: widget_id.clone(),
+                });
+            }
+            Ok(widget_ids)
+        }
+
+        /// Purges widgets that have been in the trash for longer than the configured retention.
+        pub fn purge_expired_trash(&mut self, now_ms: i64) -> DbResult<usize> {
+            let cutoff = now_ms - self.trash_retention_ms()?;
+            let purged = self.empty_trash(Some(cutoff))?;
+            if !purged.is_empty() {
+                info!("Purged {} widgets from the trash", purged.len());
+            }
+            Ok(purged.len())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::fixtures::scraped;
+        use widget_types::{Modifier, NanoId, WidgetConfiguration, WidgetModifier};
+
+        fn db_with_widget() -> Database {
+            let mut db = Database::from(true).unwrap();
+            db.insert_widget_configuration(
+                vec![WidgetConfiguration::new().with_widget_id(NanoId("w".to_string()))],
+                ChangeSource::Api,
+            )
+            .unwrap();
+            db.insert_widget_modifier(
+                WidgetModifier::new(
+                    NanoId("w".to_string()),
+                    Modifier::Refresh {
+                        modifier_id: NanoId("m".to_string()),
+                        interval_sec: 60,
+                    },
+                ),
+                ChangeSource::Api,
+            )
+            .unwrap();
+            db.insert_data(scraped("w", "1", 1_700_000_000_000))
+                .unwrap();
+            db
+        }
+
+        #[test]
+        fn test_delete_and_restore() {
+            let mut db = db_with_widget();
+            db.delete_widget("w", ChangeSource::Api).unwrap();
+
+            assert!(db.get_configuration().unwrap().is_empty());
+            assert!(db.get_all_widget_modifiers().unwrap().is_empty());
+            assert!(matches!(
+                db.get_widget_configuration_by_id("w"),
+                Err(DbError::NotFound(_))
+            ));
+            assert!(matches!(
+                db.delete_widget("w", ChangeSource::Api),
+                Err(DbError::NotFound(_))
+            ));
+
+            let trash = db.get_trash().unwrap();
+            assert_eq!(trash.len(), 1);
+            assert_eq!(trash[0].widget.widget_id.0, "w");
+
+            db.restore_widget("w", ChangeSource::Api).unwrap();
+            assert!(db.get_widget_configuration_by_id("w").unwrap().is_open);
+            assert_eq!(db.get_all_widget_modifiers().unwrap().len(), 1);
+            assert_eq!(db.get_data().unwrap().len(), 1);
+            assert!(db.get_trash().unwrap().is_empty());
+        }
+
+        #[test]
+        fn test_purge() {
+            let mut db = db_with_widget();
+            assert!(matches!(db.purge_widget("w"), Err(DbError::NotFound(_))));
+
+            db.delete_widget("w", ChangeSource::Api).unwrap();
+            db.purge_widget("w").unwrap();
+            assert!(db.get_trash().unwrap().is_empty());
+            assert!(db.get_data().unwrap().is_empty());
+            assert!(db.get_modifiers().unwrap().is_empty());
+        }
+
+        #[test]
+        fn test_expired_trash_is_purged() {
+            let mut db = db_with_widget();
+            db.delete_widget("w", ChangeSource::Api).unwrap();
+            let now = jiff::Timestamp::now().as_millisecond();
+
+            assert_eq!(db.purge_expired_trash(now).unwrap(), 0);
+            let later = now + (widget_types::DEFAULT_TRASH_RETENTION_DAYS as i64 + 1) * DAY_MS;
+            assert_eq!(db.purge_expired_trash(later).unwrap(), 1);
+            assert!(db.get_trash().unwrap().is_empty());
+        }
+    }
+}
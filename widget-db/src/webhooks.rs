@@ -0,0 +1,686 @@
+pub mod webhooks {
+    use crate::{
+        db::db::{decode, skip_corrupt, Database},
+        error::error::{DbError, DbResult},
+        events::events::live_events,
+        messages::messages::to_sql_name,
+        service::service::DbService,
+    };
+
+    use futures::{future::join_all, StreamExt};
+    use hmac::{Hmac, Mac};
+    use log::{debug, error};
+    use rusqlite::{
+        params, params_from_iter, types::Value, OptionalExtension, Result as SqliteResult, Row,
+    };
+    use serde_json::Value as JsonValue;
+    use sha2::Sha256;
+    use std::time::Duration;
+    use widget_types::{
+        CreateWebhookRequest, CreatedWebhook, DeliveryStatus, LiveEvent, Webhook, WebhookDelivery,
+    };
+
+    /// The `LiveEvent` types webhooks can subscribe to.
+    pub const WEBHOOK_EVENTS: &[&str] = &[
+        "widget_created",
+        "widget_updated",
+        "widget_deleted",
+        "widget_moved",
+        "visibility_changed",
+        "scrape_result",
+        "scrape_error",
+        "settings_changed",
+    ];
+    /// A delivery is given up on after this many attempts.
+    pub const MAX_DELIVERY_ATTEMPTS: u32 = 6;
+    /// Wait before the first retry, doubling after every failed attempt.
+    pub const RETRY_DELAY: Duration = Duration::from_secs(10);
+    /// Older finished deliveries are dropped once the log grows past this.
+    pub const MAX_DELIVERIES: usize = 1000;
+
+    const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+    /// How many due deliveries are sent at once.
+    const DELIVERY_BATCH: usize = 20;
+    /// Longest the worker sleeps with nothing to retry, in case an event went missing.
+    const IDLE_WAIT: Duration = Duration::from_secs(60);
+
+    const WEBHOOK_COLUMNS: &str = "id, url, events, widget_ids, created_at";
+    const DELIVERY_COLUMNS: &str = "id, webhook_id, event_type, payload, status, attempts, last_status_code, last_error, created_at, next_attempt_at, delivered_at";
+
+    /// Which deliveries `Database::get_webhook_deliveries` returns. Unset fields don't filter.
+    #[derive(Debug, Clone, Default)]
+    pub struct DeliveryFilter {
+        pub status: Option<DeliveryStatus>,
+        pub limit: Option<usize>,
+    }
+
+    /// A delivery that is due, with what's needed to send it.
+    #[derive(Debug, Clone)]
+    pub(crate) struct DueDelivery {
+        id: u32,
+        url: String,
+        secret: String,
+        event_type: String,
+        payload: String,
+        attempts: u32,
+    }
+
+    fn webhook_from_row(row: &Row) -> SqliteResult<DbResult<Webhook>> {
+        let id: u32 = row.get(0)?;
+        let url: String = row.get(1)?;
+        let events: String = row.get(2)?;
+        let widget_ids: String = row.get(3)?;
+        let created_at: i64 = row.get(4)?;
+
+        Ok(
+            decode("webhooks", id as i64, "events", &events).and_then(|events| {
+                Ok(Webhook {
+                    id,
+                    url,
+                    events,
+                    widget_ids: decode("webhooks", id as i64, "widget_ids", &widget_ids)?,
+                    created_at: created_at.to_string(),
+                })
+            }),
+        )
+    }
+
+    fn delivery_from_row(row: &Row) -> SqliteResult<DbResult<WebhookDelivery>> {
+        let id: u32 = row.get(0)?;
+        let webhook_id: u32 = row.get(1)?;
+        let event_type: String = row.get(2)?;
+        let payload: String = row.get(3)?;
+        // Stored as a bare name, quoted so it decodes like the JSON columns
+        let status = JsonValue::String(row.get(4)?).to_string();
+        let attempts: u32 = row.get(5)?;
+        let last_status_code: Option<u32> = row.get(6)?;
+        let last_error: Option<String> = row.get(7)?;
+        let created_at: i64 = row.get(8)?;
+        let next_attempt_at: Option<i64> = row.get(9)?;
+        let delivered_at: Option<i64> = row.get(10)?;
+
+        Ok(
+            decode("webhook_deliveries", id as i64, "status", &status).map(|status| {
+                WebhookDelivery {
+                    id,
+                    webhook_id,
+                    event_type,
+                    payload,
+                    status,
+                    attempts,
+                    last_status_code,
+                    last_error,
+                    created_at: created_at.to_string(),
+                    next_attempt_at: next_attempt_at.map(|at| at.to_string()),
+                    delivered_at: delivered_at.map(|at| at.to_string()),
+                }
+            }),
+        )
+    }
+
+    fn validate_webhook(request: &CreateWebhookRequest) -> DbResult<()> {
+        match reqwest::Url::parse(&request.url) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") => {}
+            _ => {
+                return Err(DbError::InvalidInput(format!(
+                    "{:?} is not an http or https URL",
+                    request.url
+                )))
+            }
+        }
+        if let Some(event) = request
+            .events
+            .iter()
+            .find(|event| !WEBHOOK_EVENTS.contains(&event.as_str()))
+        {
+            return Err(DbError::InvalidInput(format!(
+                "unknown event {:?}, expected one of {}",
+                event,
+                WEBHOOK_EVENTS.join(", ")
+            )));
+        }
+        if request.secret.as_deref().is_some_and(str::is_empty) {
+            return Err(DbError::InvalidInput("secrets can't be empty".to_string()));
+        }
+        Ok(())
+    }
+
+    /// The `type` an event serializes with, and the widget it is about.
+    fn describe(event: &LiveEvent) -> (String, Option<&str>) {
+        let event_type = serde_json::to_value(event)
+            .ok()
+            .and_then(|value| value["type"].as_str().map(str::to_string))
+            .unwrap_or_default();
+        let widget_id = match event {
+            LiveEvent::WidgetCreated(widget) | LiveEvent::WidgetUpdated(widget) => {
+                Some(widget.widget_id.0.as_str())
+            }
+            LiveEvent::WidgetDeleted { widget_id }
+            | LiveEvent::WidgetMoved { widget_id, .. }
+            | LiveEvent::VisibilityChanged { widget_id, .. } => Some(widget_id.as_str()),
+            LiveEvent::ScrapeResult(data) | LiveEvent::ScrapeError(data) => {
+                Some(data.widget_id.as_str())
+            }
+            LiveEvent::SettingsChanged(_) | LiveEvent::Resync => None,
+        };
+        (event_type, widget_id)
+    }
+
+    /// Hex HMAC-SHA256 of `body` keyed with the webhook's secret.
+    pub fn sign(secret: &str, body: &str) -> String {
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC takes keys of any size");
+        mac.update(body.as_bytes());
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    /// Wait after the `attempts`th failed attempt.
+    fn retry_delay(attempts: u32) -> Duration {
+        RETRY_DELAY * 2u32.pow(attempts.saturating_sub(1))
+    }
+
+    impl Database {
+        /// Adds a webhook. The secret is returned here and never again.
+        pub fn create_webhook(&self, request: &CreateWebhookRequest) -> DbResult<CreatedWebhook> {
+            validate_webhook(request)?;
+            let secret = request
+                .secret
+                .clone()
+                .unwrap_or_else(|| hex::encode(rand::random::<[u8; 32]>()));
+            self.conn.execute(
+                "INSERT INTO webhooks (url, events, widget_ids, secret, created_at) VALUES (?, ?, ?, ?, ?)",
+                params![
+                    request.url,
+                    serde_json::to_string(&request.events)?,
+                    serde_json::to_string(&request.widget_ids)?,
+                    secret,
+                    jiff::Timestamp::now().as_millisecond()
+                ],
+            )?;
+            Ok(CreatedWebhook {
+                webhook: self.get_webhook(self.conn.last_insert_rowid() as u32)?,
+                secret,
+            })
+        }
+
+        pub fn get_webhook(&self, id: u32) -> DbResult<Webhook> {
+            self.conn
+                .query_row(
+                    &format!("SELECT {} FROM webhooks WHERE id = ?", WEBHOOK_COLUMNS),
+                    [id],
+                    webhook_from_row,
+                )
+                .optional()?
+                .ok_or_else(|| DbError::NotFound(format!("webhook {}", id)))?
+        }
+
+        pub fn list_webhooks(&self) -> DbResult<Vec<Webhook>> {
+            let mut stmt = self.conn.prepare(&format!(
+                "SELECT {} FROM webhooks ORDER BY id",
+                WEBHOOK_COLUMNS
+            ))?;
+            let rows = stmt.query_map([], webhook_from_row)?;
+            skip_corrupt(rows)
+        }
+
+        /// Removes a webhook together with its deliveries, including ones not sent yet.
+        pub fn delete_webhook(&mut self, id: u32) -> DbResult<()> {
+            let tx = self.conn.transaction()?;
+            if tx.execute("DELETE FROM webhooks WHERE id = ?", [id])? == 0 {
+                return Err(DbError::NotFound(format!("webhook {}", id)));
+            }
+            tx.execute("DELETE FROM webhook_deliveries WHERE webhook_id = ?", [id])?;
+            tx.commit()?;
+            Ok(())
+        }
+
+        /// Queues `event` for every webhook it matches, returning how many deliveries that
+        /// made.
+        pub fn enqueue_webhook_deliveries(
+            &mut self,
+            event: &LiveEvent,
+            now_ms: i64,
+        ) -> DbResult<usize> {
+            let (event_type, widget_id) = describe(event);
+            if !WEBHOOK_EVENTS.contains(&event_type.as_str()) {
+                return Ok(0);
+            }
+            let matching = self
+                .list_webhooks()?
+                .into_iter()
+                .filter(|webhook| webhook.events.is_empty() || webhook.events.contains(&event_type))
+                .filter(|webhook| {
+                    webhook.widget_ids.is_empty()
+                        || widget_id.is_some_and(|id| webhook.widget_ids.iter().any(|w| w == id))
+                })
+                .collect::<Vec<_>>();
+            if matching.is_empty() {
+                return Ok(0);
+            }
+
+            let payload = serde_json::to_string(event)?;
+            let tx = self.conn.transaction()?;
+            for webhook in &matching {
+                tx.execute(
+                    "INSERT INTO webhook_deliveries (webhook_id, event_type, payload, status, created_at, next_attempt_at) VALUES (?, ?, ?, ?, ?, ?)",
+                    params![
+                        webhook.id,
+                        event_type,
+                        payload,
+                        to_sql_name(&DeliveryStatus::Pending)?,
+                        now_ms,
+                        now_ms
+                    ],
+                )?;
+            }
+            tx.execute(
+                "DELETE FROM webhook_deliveries WHERE status != ? AND id <= (SELECT id FROM webhook_deliveries ORDER BY id DESC LIMIT 1 OFFSET ?)",
+                params![to_sql_name(&DeliveryStatus::Pending)?, MAX_DELIVERIES],
+            )?;
+            tx.commit()?;
+            Ok(matching.len())
+        }
+
+        /// Deliveries of a webhook matching `filter`, newest first.
+        pub fn get_webhook_deliveries(
+            &self,
+            webhook_id: u32,
+            filter: &DeliveryFilter,
+        ) -> DbResult<Vec<WebhookDelivery>> {
+            self.get_webhook(webhook_id)?;
+            let mut sql = format!(
+                "SELECT {} FROM webhook_deliveries WHERE webhook_id = ?",
+                DELIVERY_COLUMNS
+            );
+            let mut values = vec![Value::Integer(webhook_id as i64)];
+            if let Some(status) = &filter.status {
+                sql.push_str(" AND status = ?");
+                values.push(Value::Text(to_sql_name(status)?));
+            }
+            sql.push_str(" ORDER BY id DESC LIMIT ?");
+            values.push(Value::Integer(
+                filter.limit.unwrap_or(MAX_DELIVERIES).min(MAX_DELIVERIES) as i64,
+            ));
+
+            let mut stmt = self.conn.prepare(&sql)?;
+            let rows = stmt.query_map(params_from_iter(values), delivery_from_row)?;
+            skip_corrupt(rows)
+        }
+
+        pub(crate) fn due_webhook_deliveries(&self, now_ms: i64) -> DbResult<Vec<DueDelivery>> {
+            let mut stmt = self.conn.prepare(
+                r#"
+                SELECT d.id, w.url, w.secret, d.event_type, d.payload, d.attempts
+                FROM webhook_deliveries d
+                JOIN webhooks w ON w.id = d.webhook_id
+                WHERE d.status = ? AND d.next_attempt_at <= ?
+                ORDER BY d.next_attempt_at, d.id
+                LIMIT ?
+                "#,
+            )?;
+            let due = stmt
+                .query_map(
+                    params![
+                        to_sql_name(&DeliveryStatus::Pending)?,
+                        now_ms,
+                        DELIVERY_BATCH
+                    ],
+                    |row| {
+                        Ok(DueDelivery {
+                            id: row.get(0)?,
+                            url: row.get(1)?,
+                            secret: row.get(2)?,
+                            event_type: row.get(3)?,
+                            payload: row.get(4)?,
+                            attempts: row.get(5)?,
+                        })
+                    },
+                )?
+                .collect::<SqliteResult<Vec<_>>>()?;
+            Ok(due)
+        }
+
+        /// When the next pending delivery is due, in epoch milliseconds.
+        pub(crate) fn next_webhook_attempt_at(&self) -> DbResult<Option<i64>> {
+            Ok(self.conn.query_row(
+                "SELECT MIN(next_attempt_at) FROM webhook_deliveries WHERE status = ?",
+                [to_sql_name(&DeliveryStatus::Pending)?],
+                |row| row.get(0),
+            )?)
+        }
+
+        /// Records how an attempt went. A delivery without `error` is done, otherwise it is
+        /// retried later or, out of attempts, given up on.
+        pub(crate) fn record_webhook_attempt(
+            &self,
+            id: u32,
+            now_ms: i64,
+            status_code: Option<u16>,
+            error: Option<&str>,
+        ) -> DbResult<()> {
+            let attempts: u32 = self.conn.query_row(
+                "SELECT attempts + 1 FROM webhook_deliveries WHERE id = ?",
+                [id],
+                |row| row.get(0),
+            )?;
+            let (status, next_attempt_at, delivered_at) = match error {
+                None => (DeliveryStatus::Delivered, None, Some(now_ms)),
+                Some(_) if attempts >= MAX_DELIVERY_ATTEMPTS => {
+                    (DeliveryStatus::Failed, None, None)
+                }
+                Some(_) => (
+                    DeliveryStatus::Pending,
+                    Some(now_ms + retry_delay(attempts).as_millis() as i64),
+                    None,
+                ),
+            };
+            self.conn.execute(
+                "UPDATE webhook_deliveries SET status = ?, attempts = ?, last_status_code = ?, last_error = ?, next_attempt_at = ?, delivered_at = ? WHERE id = ?",
+                params![
+                    to_sql_name(&status)?,
+                    attempts,
+                    status_code,
+                    error,
+                    next_attempt_at,
+                    delivered_at,
+                    id
+                ],
+            )?;
+            Ok(())
+        }
+    }
+
+    async fn send(
+        client: &reqwest::Client,
+        delivery: &DueDelivery,
+    ) -> (Option<u16>, Option<String>) {
+        let response = client
+            .post(&delivery.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("X-HoverPane-Event", &delivery.event_type)
+            .header("X-HoverPane-Delivery", delivery.id.to_string())
+            .header(
+                "X-HoverPane-Signature",
+                format!("sha256={}", sign(&delivery.secret, &delivery.payload)),
+            )
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+        match response {
+            Ok(response) if response.status().is_success() => {
+                (Some(response.status().as_u16()), None)
+            }
+            Ok(response) => (
+                Some(response.status().as_u16()),
+                Some(format!("responded with {}", response.status())),
+            ),
+            Err(e) => (None, Some(e.to_string())),
+        }
+    }
+
+    /// Sends every delivery due at `now_ms` and records how it went, returning how many were
+    /// attempted.
+    pub(crate) async fn deliver_due(
+        db: &DbService,
+        client: &reqwest::Client,
+        now_ms: i64,
+    ) -> DbResult<usize> {
+        let due = db.read(move |db| db.due_webhook_deliveries(now_ms)).await?;
+        let results = join_all(due.iter().map(|delivery| send(client, delivery))).await;
+        for (delivery, (status_code, error)) in due.iter().zip(results) {
+            if let Some(e) = &error {
+                debug!(
+                    "Webhook delivery {} to {} failed on attempt {}: {}",
+                    delivery.id,
+                    delivery.url,
+                    delivery.attempts + 1,
+                    e
+                );
+            }
+            let id = delivery.id;
+            db.write(move |db| {
+                db.record_webhook_attempt(id, now_ms, status_code, error.as_deref())
+            })
+            .await?;
+        }
+        Ok(due.len())
+    }
+
+    /// Queues events for the webhooks they match and sends them, for as long as the API is
+    /// running.
+    pub async fn run_webhooks(db: DbService) {
+        let client = match reqwest::Client::builder().timeout(DELIVERY_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => {
+                error!("Webhooks are off, no HTTP client: {}", e);
+                return;
+            }
+        };
+        let events = live_events(db.clone());
+        futures::pin_mut!(events);
+        loop {
+            let now = jiff::Timestamp::now().as_millisecond();
+            // A batch was full, there may be more due right away
+            let wait = match deliver_due(&db, &client, now).await {
+                Ok(DELIVERY_BATCH) => Duration::ZERO,
+                Ok(_) => match db.read(|db| db.next_webhook_attempt_at()).await {
+                    Ok(Some(at)) => Duration::from_millis((at - now).max(0) as u64).min(IDLE_WAIT),
+                    Ok(None) => IDLE_WAIT,
+                    Err(e) => {
+                        error!("Failed to read pending webhook deliveries: {}", e);
+                        IDLE_WAIT
+                    }
+                },
+                Err(e) => {
+                    error!("Failed to deliver webhooks: {}", e);
+                    IDLE_WAIT
+                }
+            };
+
+            tokio::select! {
+                event = events.next() => {
+                    let Some(event) = event else { return };
+                    let queued = db
+                        .write(move |db| {
+                            db.enqueue_webhook_deliveries(&event, jiff::Timestamp::now().as_millisecond())
+                        })
+                        .await;
+                    if let Err(e) = queued {
+                        error!("Failed to queue webhook deliveries: {}", e);
+                    }
+                }
+                _ = tokio::time::sleep(wait) => {}
+            }
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+        use crate::db::db::fixtures::scraped;
+        use axum::{extract::State, http::HeaderMap, http::StatusCode, routing::post, Router};
+        use std::sync::{Arc, Mutex};
+        use widget_types::ScrapedData;
+
+        fn scrape(widget_id: &str) -> LiveEvent {
+            LiveEvent::ScrapeResult(ScrapedData {
+                id: 1,
+                numeric_value: Some(42.0),
+                ..scraped(widget_id, "42", 1_700_000_000_000)
+            })
+        }
+
+        type Received = Arc<Mutex<Vec<(HeaderMap, String)>>>;
+
+        /// Answers 500 to the first request and 200 after that.
+        async fn receiver() -> (String, Received) {
+            let received = Received::default();
+            let app = Router::new()
+                .route(
+                    "/hook",
+                    post(
+                        |State(received): State<Received>, headers: HeaderMap, body: String| async move {
+                            let mut received = received.lock().unwrap();
+                            received.push((headers, body));
+                            if received.len() == 1 {
+                                StatusCode::INTERNAL_SERVER_ERROR
+                            } else {
+                                StatusCode::OK
+                            }
+                        },
+                    ),
+                )
+                .with_state(received.clone());
+            let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}/hook", listener.local_addr().unwrap());
+            tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+            (url, received)
+        }
+
+        #[tokio::test]
+        async fn test_deliveries_are_signed_and_retried() {
+            let (url, received) = receiver().await;
+            let db = DbService::in_memory().unwrap();
+            let client = reqwest::Client::new();
+            let webhook = db
+                .write(move |db| {
+                    db.create_webhook(&CreateWebhookRequest {
+                        url,
+                        events: vec!["scrape_result".to_string()],
+                        widget_ids: vec!["w".to_string()],
+                        secret: Some("s3cret".to_string()),
+                    })
+                })
+                .await
+                .unwrap()
+                .webhook;
+
+            let now = 1_700_000_000_000;
+            let queued = db
+                .write(move |db| {
+                    Ok(db.enqueue_webhook_deliveries(&scrape("w"), now)?
+                        + db.enqueue_webhook_deliveries(&scrape("other"), now)?
+                        + db.enqueue_webhook_deliveries(
+                            &LiveEvent::WidgetDeleted {
+                                widget_id: "w".to_string(),
+                            },
+                            now,
+                        )?)
+                })
+                .await
+                .unwrap();
+            assert_eq!(queued, 1);
+
+            // The first attempt gets a 500 and waits before trying again
+            assert_eq!(deliver_due(&db, &client, now).await.unwrap(), 1);
+            assert_eq!(deliver_due(&db, &client, now).await.unwrap(), 0);
+            let retry_at = now + RETRY_DELAY.as_millis() as i64;
+            assert_eq!(deliver_due(&db, &client, retry_at).await.unwrap(), 1);
+
+            let received = received.lock().unwrap().clone();
+            assert_eq!(received.len(), 2);
+            let (headers, body) = &received[1];
+            assert_eq!(body, &received[0].1);
+            assert_eq!(body, &serde_json::to_string(&scrape("w")).unwrap());
+            assert_eq!(headers["x-hoverpane-event"], "scrape_result");
+            let mut mac = Hmac::<Sha256>::new_from_slice(b"s3cret").unwrap();
+            mac.update(body.as_bytes());
+            assert_eq!(
+                headers["x-hoverpane-signature"].to_str().unwrap(),
+                format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+            );
+
+            let deliveries = db
+                .read(move |db| db.get_webhook_deliveries(webhook.id, &DeliveryFilter::default()))
+                .await
+                .unwrap();
+            assert_eq!(deliveries.len(), 1);
+            assert_eq!(deliveries[0].status, DeliveryStatus::Delivered);
+            assert_eq!(deliveries[0].attempts, 2);
+            assert_eq!(deliveries[0].last_status_code, Some(200));
+            assert_eq!(deliveries[0].delivered_at, Some(retry_at.to_string()));
+
+            // Only the response to creating the webhook had the secret
+            let shown = db
+                .read(move |db| {
+                    Ok(serde_json::to_string(&(
+                        db.list_webhooks()?,
+                        db.get_webhook(webhook.id)?,
+                    ))?)
+                })
+                .await
+                .unwrap();
+            assert!(!shown.contains("s3cret"));
+        }
+
+        #[tokio::test]
+        async fn test_unreachable_webhooks_give_up() {
+            // Nothing listens on a port that was just freed
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let url = format!("http://{}/hook", listener.local_addr().unwrap());
+            drop(listener);
+
+            let db = DbService::in_memory().unwrap();
+            let client = reqwest::Client::new();
+            let webhook = db
+                .write(move |db| {
+                    let webhook = db.create_webhook(&CreateWebhookRequest {
+                        url,
+                        events: vec![],
+                        widget_ids: vec![],
+                        secret: None,
+                    })?;
+                    db.enqueue_webhook_deliveries(&scrape("w"), 0)?;
+                    Ok(webhook)
+                })
+                .await
+                .unwrap();
+            assert!(!webhook.secret.is_empty());
+            let webhook = webhook.webhook;
+
+            let mut now = 0;
+            for _ in 0..MAX_DELIVERY_ATTEMPTS {
+                assert_eq!(deliver_due(&db, &client, now).await.unwrap(), 1);
+                now += 24 * 60 * 60 * 1000;
+            }
+            assert_eq!(deliver_due(&db, &client, now).await.unwrap(), 0);
+            let failed = db
+                .read(move |db| {
+                    db.get_webhook_deliveries(
+                        webhook.id,
+                        &DeliveryFilter {
+                            status: Some(DeliveryStatus::Failed),
+                            ..Default::default()
+                        },
+                    )
+                })
+                .await
+                .unwrap();
+            assert_eq!(failed.len(), 1);
+            assert_eq!(failed[0].attempts, MAX_DELIVERY_ATTEMPTS);
+            assert!(failed[0].last_error.is_some());
+        }
+
+        #[test]
+        fn test_invalid_webhooks() {
+            let db = Database::from(true).unwrap();
+            let request = |url: &str, event: &str| CreateWebhookRequest {
+                url: url.to_string(),
+                events: vec![event.to_string()],
+                widget_ids: vec![],
+                secret: None,
+            };
+            for invalid in [
+                request("ftp://example.com", "scrape_result"),
+                request("not a url", "scrape_result"),
+                request("https://example.com", "resync"),
+            ] {
+                assert!(matches!(
+                    db.create_webhook(&invalid),
+                    Err(DbError::InvalidInput(_))
+                ));
+            }
+            assert!(matches!(db.get_webhook(1), Err(DbError::NotFound(_))));
+        }
+    }
+}
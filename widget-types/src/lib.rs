@@ -14,6 +14,8 @@ pub const DEFAULT_WIDGET_WIDTH: u32 = 200;
 pub const DEFAULT_WIDGET_HEIGHT: u32 = 200;
 pub const DEFAULT_WIDGET_X: u32 = 0;
 pub const DEFAULT_WIDGET_Y: u32 = 0;
+/// The built-in settings window, stored alongside user widgets but recreated on every start.
+pub const CONTROLS_WIDGET_ID: &str = "controls";
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VersionInfo {
@@ -34,12 +36,21 @@ pub struct ApiError {
 #[serde(tag = "type", content = "content", rename_all = "lowercase")]
 pub enum IpcEvent {
     SaveSettings(AppSettings),
-    ExtractResult(ScrapedData),
+    ExtractResult(ExtractResult),
     DragEvent(DragEvent),
     BuyLicence(UserEmail),
     CheckLicence(CheckLicenceRequest),
 }
 
+/// What the script injected for a scrape modifier posts back, so the result can be put down
+/// to that modifier alone.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct ExtractResult {
+    pub modifier_id: String,
+    pub data: ScrapedData,
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[typeshare]
 pub struct UserEmail {
@@ -85,6 +96,7 @@ pub enum LicenceTier {
 #[typeshare]
 pub struct AppUiState {
     pub app_settings: AppSettings,
+    /// Messages that haven't been acknowledged yet, oldest first.
     pub messages: Vec<String>,
 }
 
@@ -96,6 +108,27 @@ pub struct AppSettings {
     pub licence_key: String,
     pub machine_id: String,
     pub licence_tier: LicenceTier,
+    /// Days a deleted widget stays in the trash before it is purged for good.
+    #[serde(default = "default_trash_retention_days")]
+    pub trash_retention_days: u32,
+}
+
+pub const DEFAULT_TRASH_RETENTION_DAYS: u32 = 30;
+
+fn default_trash_retention_days() -> u32 {
+    DEFAULT_TRASH_RETENTION_DAYS
+}
+
+/// One stored setting. `key` is the `AppSettings` field name and `value` its JSON. `version`
+/// goes up with every change and is 0 while the setting still has its default.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct SettingEntry {
+    pub key: String,
+    pub value: String,
+    pub version: u32,
+    /// Epoch milliseconds, missing for defaults.
+    pub updated_at: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
@@ -154,6 +187,39 @@ pub enum ApiAction {
         user_email: String,
         licence_key: String,
     },
+    SwitchProfile {
+        profile: String,
+    },
+    /// Settings were changed through the API and are already saved.
+    SettingsChanged(AppSettings),
+    /// Shows or hides every listed widget, opening the ones that aren't open yet.
+    SetWidgetsVisibility {
+        widget_ids: Vec<String>,
+        visible: bool,
+    },
+    /// Reloads every listed widget that is open, ignoring its refresh interval.
+    RefreshWidgets {
+        widget_ids: Vec<String>,
+    },
+    /// The listed widgets were moved to the trash, close their windows.
+    DeleteWidgets {
+        widget_ids: Vec<String>,
+    },
+    ApplyLayout(LayoutPreset),
+    /// A layout preset was saved or deleted through the API.
+    LayoutsChanged,
+    /// A widget was edited through the API and is already saved, as it is now.
+    UpdateWidget(WidgetConfiguration),
+    /// Reloads an open widget now, ignoring its refresh interval.
+    RefreshWidget {
+        widget_id: String,
+    },
+    /// Runs the scrape modifiers against an open widget now. Results come back like scheduled
+    /// ones, as `IpcEvent::ExtractResult`.
+    ScrapeWidget {
+        widget_id: String,
+        modifiers: Vec<Modifier>,
+    },
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -188,6 +254,23 @@ pub enum Modifier {
     },
 }
 
+impl Modifier {
+    pub fn modifier_id(&self) -> &NanoId {
+        match self {
+            Modifier::Scrape { modifier_id, .. } => modifier_id,
+            Modifier::Refresh { modifier_id, .. } => modifier_id,
+        }
+    }
+
+    pub fn with_modifier_id(mut self, id: NanoId) -> Self {
+        match &mut self {
+            Modifier::Scrape { modifier_id, .. } => *modifier_id = id,
+            Modifier::Refresh { modifier_id, .. } => *modifier_id = id,
+        }
+        self
+    }
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
 #[typeshare]
 pub struct ScrapedData {
@@ -197,6 +280,74 @@ pub struct ScrapedData {
     pub value: String,
     pub error: Option<String>,
     pub timestamp: String,
+    /// `value` read as a number, filled in by the database on insert.
+    #[serde(default)]
+    pub numeric_value: Option<f64>,
+    /// Currency or unit that came with `numeric_value`, such as `$` or `%`.
+    #[serde(default)]
+    pub unit: Option<String>,
+}
+
+/// One page of a widget's scrape history, newest first. Pass `next_cursor` back to get the
+/// following page.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct HistoryPage {
+    pub items: Vec<ScrapedData>,
+    pub next_cursor: Option<String>,
+}
+
+/// Summary of the numeric values scraped during one interval.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct AggregateBucket {
+    pub bucket_start: String,
+    pub count: u32,
+    pub min: f64,
+    pub max: f64,
+    pub avg: f64,
+    pub first: f64,
+    pub last: f64,
+}
+
+/// Widgets matching a `/search` query, best match first. `matched_widget` is set when the
+/// title, URL or HTML matched, `hits` holds the matching scraped values, best match first.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct SearchResult {
+    pub widget_id: String,
+    pub title: String,
+    pub matched_widget: bool,
+    pub hits: Vec<SearchHit>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct SearchHit {
+    pub value: String,
+    pub timestamp: String,
+}
+
+/// How much scrape history to keep for a widget.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+#[serde(rename_all = "lowercase", tag = "type", content = "content")]
+#[typeshare]
+pub enum RetentionPolicy {
+    #[default]
+    KeepAll,
+    KeepDays {
+        days: u32,
+    },
+    KeepRows {
+        rows: u32,
+    },
+    /// Keep every value for `raw_days`, then only the last value per hour for another
+    /// `hourly_days`, then only the last value per day. What is removed is rolled up per hour
+    /// and per day, so aggregates still cover it.
+    Downsample {
+        raw_days: u32,
+        hourly_days: u32,
+    },
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -207,6 +358,357 @@ pub struct WidgetModifier {
     pub id: i32,
     pub widget_id: NanoId,
     pub modifier_type: Modifier,
+    /// Disabled modifiers are kept but never run.
+    #[serde(default = "default_modifier_enabled")]
+    pub enabled: bool,
+    /// Epoch milliseconds of the last time the modifier was run and the last time that worked.
+    #[serde(default)]
+    pub last_run_at: Option<String>,
+    #[serde(default)]
+    pub last_success_at: Option<String>,
+    #[serde(default)]
+    pub consecutive_failures: u32,
+}
+
+/// New settings for a modifier, of the kind it already is: `selector` for scrape modifiers and
+/// `interval_sec` for refresh ones.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+#[typeshare]
+pub struct UpdateModifierRequest {
+    pub selector: Option<String>,
+    pub interval_sec: Option<i32>,
+}
+
+fn default_modifier_enabled() -> bool {
+    true
+}
+
+impl WidgetModifier {
+    /// An enabled modifier that has never run.
+    pub fn new(widget_id: NanoId, modifier_type: Modifier) -> Self {
+        Self {
+            id: 0,
+            widget_id,
+            modifier_type,
+            enabled: true,
+            last_run_at: None,
+            last_success_at: None,
+            consecutive_failures: 0,
+        }
+    }
+}
+
+/// A deleted widget, kept with its modifiers and history until `purge_at`. Both timestamps are
+/// epoch milliseconds.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct TrashedWidget {
+    pub widget: WidgetConfiguration,
+    pub deleted_at: String,
+    pub purge_at: String,
+}
+
+/// A tag and how many widgets outside the trash carry it.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct TagInfo {
+    pub tag: String,
+    pub widget_count: u32,
+}
+
+/// Where a change to a widget's configuration came from.
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[typeshare]
+pub enum ChangeSource {
+    Api,
+    Ipc,
+    Tray,
+    Import,
+    /// Changes the app makes on its own, such as recreating the controls widget at startup.
+    App,
+}
+
+/// Something that changed in the database. Sent to subscribers once the change is committed,
+/// whichever connection made it.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type", content = "content")]
+#[typeshare]
+pub enum DbChange {
+    WidgetCreated { widget_id: String },
+    /// A widget was edited, imported, restored from the trash or rolled back.
+    WidgetSaved { widget_id: String },
+    /// A widget was moved or resized.
+    WidgetMoved { widget_id: String },
+    VisibilityChanged { widget_id: String, is_open: bool },
+    /// A widget was moved to the trash.
+    WidgetDeleted { widget_id: String },
+    /// A widget was deleted from the trash for good.
+    WidgetPurged { widget_id: String },
+    /// Modifiers of a widget were added, removed, enabled or disabled.
+    ModifiersChanged { widget_id: String },
+    TagsChanged { widget_id: String },
+    LayoutsChanged,
+    DataInserted { widget_id: String, id: u32 },
+    SettingsChanged,
+    MessageAdded { id: u32 },
+    MessageAcknowledged { id: u32 },
+    /// The whole database was replaced, by a reset or by restoring a backup. Anything read
+    /// before should be read again.
+    Reloaded,
+}
+
+/// What `GET /events` streams, each one a `data:` line of JSON.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case", tag = "type", content = "content")]
+#[typeshare]
+pub enum LiveEvent {
+    WidgetCreated(WidgetConfiguration),
+    WidgetUpdated(WidgetConfiguration),
+    WidgetDeleted { widget_id: String },
+    WidgetMoved { widget_id: String, bounds: WidgetBounds },
+    VisibilityChanged { widget_id: String, visible: bool },
+    ScrapeResult(ScrapedData),
+    /// A scrape that failed, with the reason in `error`.
+    ScrapeError(ScrapedData),
+    SettingsChanged(AppSettings),
+    /// Events were missed, or the whole database was replaced. Anything shown should be read
+    /// again.
+    Resync,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[typeshare]
+pub enum MessageSeverity {
+    Info,
+    Warning,
+    Error,
+}
+
+/// A notification for the user. Messages stay in the log after they are acknowledged, they
+/// just no longer show up as new.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct AppMessage {
+    pub id: u32,
+    pub severity: MessageSeverity,
+    pub source: ChangeSource,
+    pub widget_id: Option<String>,
+    pub message: String,
+    /// Epoch milliseconds
+    pub created_at: String,
+    pub acknowledged: bool,
+}
+
+/// Somewhere events are POSTed to as they happen. Empty `events` or `widget_ids` match
+/// everything. The secret is left out, it is only returned once in `CreatedWebhook`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct Webhook {
+    pub id: u32,
+    pub url: String,
+    /// `LiveEvent` types, such as `scrape_result`
+    pub events: Vec<String>,
+    pub widget_ids: Vec<String>,
+    /// Epoch milliseconds
+    pub created_at: String,
+}
+
+/// A webhook that was just added, the only time its secret can be read back.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct CreatedWebhook {
+    pub webhook: Webhook,
+    /// Key of the HMAC-SHA256 sent as `X-HoverPane-Signature: sha256=<hex>` with every body
+    pub secret: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct CreateWebhookRequest {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    #[serde(default)]
+    pub widget_ids: Vec<String>,
+    /// Generated when left out
+    pub secret: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+#[typeshare]
+pub enum DeliveryStatus {
+    Pending,
+    Delivered,
+    /// Every attempt failed, it won't be tried again
+    Failed,
+}
+
+/// One event sent to one webhook, with how its attempts went.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct WebhookDelivery {
+    pub id: u32,
+    pub webhook_id: u32,
+    pub event_type: String,
+    /// The JSON body, the same on every attempt
+    pub payload: String,
+    pub status: DeliveryStatus,
+    pub attempts: u32,
+    /// Response status of the last attempt, if it got that far
+    pub last_status_code: Option<u32>,
+    pub last_error: Option<String>,
+    /// Epoch milliseconds
+    pub created_at: String,
+    /// Epoch milliseconds, while pending
+    pub next_attempt_at: Option<String>,
+    /// Epoch milliseconds
+    pub delivered_at: Option<String>,
+}
+
+/// A widget's configuration and modifiers as they were after a change. `is_open` is window
+/// state rather than configuration and is always `false` here, and so is modifier run state.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct WidgetRevision {
+    pub revision: u32,
+    pub source: ChangeSource,
+    /// Epoch milliseconds.
+    pub created_at: String,
+    /// Whether the widget was in the trash.
+    pub deleted: bool,
+    pub widget: WidgetConfiguration,
+    pub modifiers: Vec<Modifier>,
+    /// Ids of the modifiers that were disabled.
+    #[serde(default)]
+    pub disabled_modifiers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct RevisionDiff {
+    pub widget_id: String,
+    pub from: u32,
+    pub to: u32,
+    pub changes: Vec<FieldChange>,
+}
+
+/// One changed value, e.g. `widget.bounds.x` or `modifiers.<modifier_id>.content.selector`.
+/// `before` and `after` are JSON and missing when the field was added or removed.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct FieldChange {
+    pub path: String,
+    pub before: Option<String>,
+    pub after: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct ProfileInfo {
+    pub name: String,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct CreateProfileRequest {
+    pub name: String,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct CreateLayoutRequest {
+    pub name: String,
+}
+
+/// Where one widget goes when a layout preset is applied.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct LayoutEntry {
+    pub widget_id: String,
+    pub bounds: WidgetBounds,
+    pub is_open: bool,
+    pub level: Level,
+}
+
+/// A saved arrangement of widgets. `created_at` is epoch milliseconds. Widgets deleted since
+/// the preset was saved are left out.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct LayoutPreset {
+    pub name: String,
+    pub created_at: String,
+    pub widgets: Vec<LayoutEntry>,
+}
+
+/// A snapshot of the widget database in the `backups` folder next to it.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct BackupInfo {
+    pub name: String,
+    pub created_at: String,
+    pub size_kb: u32,
+}
+
+/// Version written by this build. Bundles with a newer version are rejected on import.
+pub const WORKSPACE_BUNDLE_VERSION: u32 = 1;
+
+/// Portable snapshot of a HoverPane setup, see `GET /export` and `POST /import`.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct WorkspaceBundle {
+    pub version: u32,
+    pub exported_at: String,
+    pub widgets: Vec<BundledWidget>,
+    pub settings: Option<BundledSettings>,
+    /// Only filled in when the export asked for history.
+    #[serde(default)]
+    pub history: Vec<ScrapedData>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct BundledWidget {
+    pub widget: WidgetConfiguration,
+    pub modifiers: Vec<Modifier>,
+    /// Ids of the modifiers that are disabled, older bundles have them all enabled.
+    #[serde(default)]
+    pub disabled_modifiers: Vec<String>,
+}
+
+/// The parts of `AppSettings` that make sense on another machine. Email, licence and machine
+/// id never leave the device.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct BundledSettings {
+    pub show_tray_icon: bool,
+}
+
+/// What an import changed, or would change when it was a dry run.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Default)]
+#[typeshare]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub widgets_created: Vec<String>,
+    /// Ids that were already taken here and got a fresh one.
+    pub remapped_ids: Vec<RemappedId>,
+    pub modifiers_created: u32,
+    pub history_rows: u32,
+    pub settings_updated: bool,
+    /// Modifiers that fail validation and why. A real import refuses the bundle when there
+    /// are any, a dry run leaves them out and lists them here.
+    pub invalid_modifiers: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
+#[typeshare]
+pub struct RemappedId {
+    pub from: String,
+    pub to: String,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq)]
@@ -259,6 +761,21 @@ pub struct CreateWidgetRequest {
     pub bounds: Option<WidgetBounds>,
 }
 
+/// Fields of a widget to change, the rest stay as they are. `url` only applies to URL widgets
+/// and `html` only to HTML ones.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+#[typeshare]
+pub struct UpdateWidgetRequest {
+    pub title: Option<String>,
+    pub url: Option<String>,
+    pub html: Option<String>,
+    pub level: Option<Level>,
+    pub transparent: Option<bool>,
+    pub decorations: Option<bool>,
+    pub bounds: Option<WidgetBounds>,
+}
+
 impl WidgetConfiguration {
     pub fn new() -> Self {
         Self {